@@ -9,14 +9,37 @@ fn main() {
     build("src/targets/reg_read.s", "reg_read");
     build("src/targets/hello_world.c", "hello_world");
     build("src/targets/memory.c", "memory");
+    build("src/targets/steps.c", "steps");
+    build_with("src/targets/steps.c", "steps_dwarf4", &["-gdwarf-4"]);
+    build("src/targets/recursion.c", "recursion");
+    build("src/targets/variables.c", "variables");
+    build_with("src/targets/threads.c", "threads", &["-g", "-pthread"]);
+    build_with("src/targets/threadwait.c", "threadwait", &["-g", "-pthread"]);
+    build("src/targets/fork.c", "fork");
+    build("src/targets/exec.c", "exec");
+    build("src/targets/signals.c", "signals");
+    build("src/targets/faults.c", "faults");
+    build("src/targets/syscalls.c", "syscalls");
+    build("src/targets/segv.c", "segv");
+    build_with("src/targets/plugin.c", "libplugin.so", &["-g", "-shared", "-fPIC"]);
+    let plugin = PathBuf::from(env::var("OUT_DIR").unwrap()).join("libplugin.so");
+    build_with("src/targets/dlopen.c", "dlopen", &["-g", &format!("-DPLUGIN_PATH=\"{}\"", plugin.display())]);
+    build_with("src/targets/pending_plugin.c", "libpending.so", &["-g", "-shared", "-fPIC"]);
+    let plugin = PathBuf::from(env::var("OUT_DIR").unwrap()).join("libpending.so");
+    build_with("src/targets/pending.c", "pending", &["-g", &format!("-DPLUGIN_PATH=\"{}\"", plugin.display())]);
 }
 
 fn build(infile: &str, outfile: &str) {
+    build_with(infile, outfile, &["-g"]);
+}
+
+fn build_with(infile: &str, outfile: &str, flags: &[&str]) {
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
     let exe_file = out_dir.join(outfile);
     let path_file = outfile.to_owned() + "_path";
 
     let status = Command::new("gcc")
+        .args(flags)
         .arg(infile)
         .arg("-o")
         .arg(&exe_file)
@@ -1,4 +1,10 @@
-use crate::ValUnion;
+use crate::{
+    ValUnion,
+    GadbErr,
+    Result,
+    error,
+    parsing::Location
+};
 
 #[derive(Eq, PartialEq, Clone, Copy, Hash, Debug)]
 pub struct VirtAddr (
@@ -99,6 +105,57 @@ impl Ord for VirtAddr {
     }
 }
 
+impl VirtAddr {
+    /// Converts to an address in the ELF file loaded at the given bias
+    pub fn to_file(self, load_bias: u64) -> FileAddr {
+        FileAddr(self.0.wrapping_sub(load_bias))
+    }
+}
+
+/// An address as it appears in an ELF file, before the load bias is applied
+#[derive(Eq, PartialEq, Clone, Copy, Hash, Debug, PartialOrd, Ord)]
+pub struct FileAddr (
+    pub u64
+);
+
+impl FileAddr {
+    /// Converts to the address this location occupies in a process that loaded the file at the given bias
+    pub fn to_virt(self, load_bias: u64) -> VirtAddr {
+        VirtAddr(self.0.wrapping_add(load_bias))
+    }
+}
+
+impl From::<u64> for FileAddr {
+    fn from(value: u64) -> Self {
+        Self(value)
+    }
+}
+
+impl std::fmt::LowerHex for FileAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "0x")?;
+        }
+        write!(f, "{:x}", self.0)
+    }
+}
+
+impl std::ops::Add::<u64> for FileAddr {
+    type Output = Self;
+
+    fn add(self, rhs: u64) -> Self::Output {
+        Self(self.0 + rhs)
+    }
+}
+
+impl std::ops::Sub::<u64> for FileAddr {
+    type Output = Self;
+
+    fn sub(self, rhs: u64) -> Self::Output {
+        Self(self.0 - rhs)
+    }
+}
+
 pub trait StopPoint {
     fn addr(&self) -> VirtAddr;
     fn is_at(&self, va: VirtAddr) -> bool {
@@ -112,12 +169,15 @@ pub trait StopPoint {
     fn enabled(&self) -> bool;
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct BreakSite {
     pub id: usize,
     pub enabled: bool,
     pub va: VirtAddr,
-    pub saved_data: Option<Vec<u8>>
+    pub saved_data: Option<Vec<u8>>,
+    pub internal: bool,
+    /// The user breakpoints sharing this site, which is enabled while any of them is
+    pub breakpoints: Vec<usize>
 }
 
 impl BreakSite {
@@ -126,11 +186,40 @@ impl BreakSite {
             id,
             enabled: true,
             va,
-            saved_data: None
+            saved_data: None,
+            internal: false,
+            breakpoints: Vec::new()
         }
     }
 }
 
+/// A user breakpoint on a location. It owns a breaksite for every address the location
+/// resolves to, which may be several (one per library defining a symbol) or none while it is
+/// pending on a library that has not been loaded yet.
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub location: Location,
+    pub enabled: bool,
+    /// Ids of the breaksites inserted for this breakpoint
+    pub sites: Vec<usize>
+}
+
+impl Breakpoint {
+    pub(crate) fn new(id: usize, location: Location) -> Self {
+        Self {
+            id,
+            location,
+            enabled: true,
+            sites: Vec::new()
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.sites.is_empty()
+    }
+}
+
 impl StopPoint for BreakSite {
     fn addr(&self) -> VirtAddr {
         self.va
@@ -147,4 +236,100 @@ impl StopPoint for BreakSite {
     fn enabled(&self) -> bool {
         self.enabled
     }
-}
\ No newline at end of file
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum WatchMode {
+    Write,
+    ReadWrite,
+    Execute
+}
+
+impl std::str::FromStr for WatchMode {
+    type Err = GadbErr;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "w" | "write" => Ok(WatchMode::Write),
+            "rw" | "read_write" => Ok(WatchMode::ReadWrite),
+            "x" | "execute" => Ok(WatchMode::Execute),
+            _ => error(&format!("unknown watchpoint mode {} (expected w, rw or x)", s))
+        }
+    }
+}
+
+impl WatchMode {
+    /// The R/W bits DR7 uses for this mode
+    pub fn dr7_bits(&self) -> u64 {
+        match self {
+            WatchMode::Execute => 0b00,
+            WatchMode::Write => 0b01,
+            WatchMode::ReadWrite => 0b11,
+        }
+    }
+}
+
+impl std::fmt::Display for WatchMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            WatchMode::Write => "w",
+            WatchMode::ReadWrite => "rw",
+            WatchMode::Execute => "x",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Watchpoint {
+    pub id: usize,
+    pub enabled: bool,
+    pub va: VirtAddr,
+    pub size: usize,
+    pub mode: WatchMode,
+    /// Index of the debug register (DR0-DR3) holding this watchpoint while it is enabled
+    pub slot: Option<usize>
+}
+
+impl Watchpoint {
+    pub(crate) fn new(id: usize, va: VirtAddr, size: usize, mode: WatchMode) -> Result<Self> {
+        if ![1, 2, 4, 8].contains(&size) {
+            return error("watchpoint size must be 1, 2, 4 or 8");
+        }
+        if mode == WatchMode::Execute && size != 1 {
+            return error("execute watchpoints must have size 1");
+        }
+        if !va.0.is_multiple_of(size as u64) {
+            return error(&format!("watchpoint address must be aligned to {} bytes", size));
+        }
+        Ok(Self {
+            id,
+            enabled: false,
+            va,
+            size,
+            mode,
+            slot: None
+        })
+    }
+}
+
+impl StopPoint for Watchpoint {
+    fn addr(&self) -> VirtAddr {
+        self.va
+    }
+
+    fn in_range(&self, low: VirtAddr, high: VirtAddr) -> bool {
+        low.0 < self.va.0 + self.size as u64 && high.0 >= self.va.0
+    }
+
+    fn set_enabled(&mut self) {
+        self.enabled = true;
+    }
+
+    fn set_disabled(&mut self) {
+        self.enabled = false;
+    }
+
+    fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
@@ -0,0 +1,443 @@
+use crate::{
+    Result,
+    error,
+    FileAddr,
+    elf::Elf,
+    dwarf::*
+};
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::path::PathBuf;
+
+const DW_UT_COMPILE: u8 = 0x01;
+const DW_UT_PARTIAL: u8 = 0x03;
+
+#[derive(Clone, Debug)]
+struct AttrSpec {
+    name: u64,
+    form: u64,
+    implicit_const: i64
+}
+
+#[derive(Clone, Debug)]
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<AttrSpec>
+}
+
+/// An attribute value, with references already made relative to the start of .debug_info
+#[derive(PartialEq, Clone, Debug)]
+pub enum AttrValue {
+    Addr(u64),
+    Udata(u64),
+    Sdata(i64),
+    Str(String),
+    Ref(u64),
+    Block(Vec<u8>),
+    Flag(bool),
+    SecOffset(u64),
+    /// Index into .debug_str_offsets, resolved once the unit's base is known
+    StrIndex(u64),
+    /// Index into .debug_addr, resolved once the unit's base is known
+    AddrIndex(u64)
+}
+
+/// A debugging information entry
+#[derive(Clone, Debug)]
+pub struct Die {
+    /// Offset of this entry within .debug_info
+    pub offset: u64,
+    pub tag: u64,
+    pub attrs: Vec<(u64, AttrValue)>,
+    pub children: Vec<u64>,
+    pub parent: Option<u64>
+}
+
+impl Die {
+    pub fn attr(&self, name: u64) -> Option<&AttrValue> {
+        self.attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self.attr(DW_AT_NAME) {
+            Some(AttrValue::Str(s)) => Some(s),
+            _ => None
+        }
+    }
+
+    /// An attribute that holds a plain number, whatever form it was encoded with
+    pub fn udata(&self, name: u64) -> Option<u64> {
+        match self.attr(name)? {
+            AttrValue::Addr(v) | AttrValue::Udata(v) | AttrValue::SecOffset(v) => Some(*v),
+            AttrValue::Sdata(v) => Some(*v as u64),
+            _ => None
+        }
+    }
+
+    pub fn sdata(&self, name: u64) -> Option<i64> {
+        match self.attr(name)? {
+            AttrValue::Sdata(v) => Some(*v),
+            AttrValue::Udata(v) => Some(*v as i64),
+            _ => None
+        }
+    }
+
+    pub fn reference(&self, name: u64) -> Option<u64> {
+        match self.attr(name)? {
+            AttrValue::Ref(off) => Some(*off),
+            _ => None
+        }
+    }
+
+    pub fn block(&self, name: u64) -> Option<&[u8]> {
+        match self.attr(name)? {
+            AttrValue::Block(b) => Some(b),
+            _ => None
+        }
+    }
+
+    pub fn flag(&self, name: u64) -> bool {
+        matches!(self.attr(name), Some(AttrValue::Flag(true)))
+    }
+
+    /// The [low_pc, high_pc) range of a function or block, if it is contiguous
+    pub fn pc_range(&self) -> Option<(FileAddr, FileAddr)> {
+        let low = match self.attr(DW_AT_LOW_PC)? {
+            AttrValue::Addr(addr) => *addr,
+            _ => return None
+        };
+        let high = match self.attr(DW_AT_HIGH_PC)? {
+            AttrValue::Addr(addr) => *addr,
+            AttrValue::Udata(len) => low + len,
+            AttrValue::Sdata(len) => low.wrapping_add_signed(*len),
+            _ => return None
+        };
+        Some((FileAddr(low), FileAddr(high)))
+    }
+
+    pub fn contains_pc(&self, addr: FileAddr) -> bool {
+        self.pc_range().is_some_and(|(low, high)| addr >= low && addr < high)
+    }
+}
+
+/// The DIE trees of every compilation unit in an object's .debug_info
+#[derive(Debug, Default)]
+pub struct DebugInfo {
+    dies: HashMap<u64, Die>,
+    /// Offsets of each unit's root DIE
+    units: Vec<u64>,
+    /// Functions with a contiguous pc range, sorted by start address
+    functions: Vec<(FileAddr, FileAddr, u64)>
+}
+
+struct UnitContext<'a> {
+    elf: &'a Elf,
+    offset: u64,
+    version: u16,
+    addr_size: u8,
+    is_64: bool
+}
+
+impl DebugInfo {
+    pub fn parse(elf: &Elf) -> Result<Self> {
+        let mut info = Self::default();
+        let Some(data) = elf.section_data_by_name(".debug_info") else {
+            return Ok(info);
+        };
+        let Some(abbrev_data) = elf.section_data_by_name(".debug_abbrev") else {
+            return error("missing .debug_abbrev section");
+        };
+        let mut abbrev_tables: HashMap<u64, HashMap<u64, Abbrev>> = HashMap::new();
+        let mut cur = Cursor::new(data);
+        while !cur.finished() {
+            let offset = cur.pos() as u64;
+            let (len, is_64) = cur.unit_length()?;
+            let Some(unit_data) = cur.pos().checked_add(len as usize).and_then(|end| data.get(..end)) else {
+                return error(&format!("unit at {:#x} runs past the end of .debug_info", offset));
+            };
+            let end = unit_data.len();
+            let version = cur.u16()?;
+            let (unit_type, addr_size, abbrev_offset) = if version >= 5 {
+                let unit_type = cur.u8()?;
+                let addr_size = cur.u8()?;
+                (unit_type, addr_size, cur.offset(is_64)?)
+            } else {
+                let abbrev_offset = cur.offset(is_64)?;
+                (DW_UT_COMPILE, cur.u8()?, abbrev_offset)
+            };
+            if !(2..=5).contains(&version) || (unit_type != DW_UT_COMPILE && unit_type != DW_UT_PARTIAL) {
+                cur.seek(end);
+                continue;
+            }
+            let abbrevs = match abbrev_tables.entry(abbrev_offset) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(parse_abbrevs(abbrev_data, abbrev_offset)?)
+            };
+            let unit = UnitContext { elf, offset, version, addr_size, is_64 };
+            info.parse_unit(&unit, abbrevs, &mut Cursor::at(unit_data, cur.pos()))?;
+            cur.seek(end);
+        }
+        info.functions.sort_by_key(|(low, _, _)| *low);
+        Ok(info)
+    }
+
+    fn parse_unit(&mut self, unit: &UnitContext, abbrevs: &HashMap<u64, Abbrev>, cur: &mut Cursor) -> Result<()> {
+        let mut parents: Vec<u64> = Vec::new();
+        let mut unit_dies: Vec<u64> = Vec::new();
+        while !cur.finished() {
+            let offset = cur.pos() as u64;
+            let code = cur.uleb128()?;
+            if code == 0 {
+                parents.pop();
+                continue;
+            }
+            let Some(abbrev) = abbrevs.get(&code) else {
+                return error(&format!("unknown abbreviation code {} at {:#x}", code, offset));
+            };
+            let mut attrs = Vec::with_capacity(abbrev.attrs.len());
+            for spec in abbrev.attrs.iter() {
+                attrs.push((spec.name, read_attr(unit, cur, spec.form, spec.implicit_const)?));
+            }
+            let parent = parents.last().copied();
+            if let Some(parent) = parent {
+                self.dies.get_mut(&parent).unwrap().children.push(offset);
+            } else {
+                self.units.push(offset);
+            }
+            self.dies.insert(offset, Die { offset, tag: abbrev.tag, attrs, children: Vec::new(), parent });
+            unit_dies.push(offset);
+            if abbrev.has_children {
+                parents.push(offset);
+            }
+        }
+        self.resolve_indices(unit, &unit_dies)?;
+        for offset in unit_dies {
+            let die = &self.dies[&offset];
+            if die.tag == DW_TAG_SUBPROGRAM {
+                if let Some((low, high)) = die.pc_range() {
+                    self.functions.push((low, high, offset));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces strx and addrx forms now that the unit's str_offsets_base and addr_base are known
+    fn resolve_indices(&mut self, unit: &UnitContext, unit_dies: &[u64]) -> Result<()> {
+        let Some(root) = unit_dies.first().and_then(|off| self.dies.get(off)) else {
+            return Ok(());
+        };
+        let offset_size = if unit.is_64 { 8 } else { 4 };
+        let str_base = root.udata(DW_AT_STR_OFFSETS_BASE).unwrap_or(8);
+        let addr_base = root.udata(DW_AT_ADDR_BASE).unwrap_or(8);
+        let str_offsets = unit.elf.section_data_by_name(".debug_str_offsets").unwrap_or(&[]);
+        let addrs = unit.elf.section_data_by_name(".debug_addr").unwrap_or(&[]);
+        let strs = unit.elf.section_data_by_name(".debug_str").unwrap_or(&[]);
+        for off in unit_dies {
+            let die = self.dies.get_mut(off).unwrap();
+            for (_, val) in die.attrs.iter_mut() {
+                match val {
+                    AttrValue::StrIndex(idx) => {
+                        let pos = (str_base + *idx * offset_size) as usize;
+                        let str_off = Cursor::at(str_offsets, pos).offset(unit.is_64)?;
+                        *val = AttrValue::Str(str_at(strs, str_off)?.to_string());
+                    },
+                    AttrValue::AddrIndex(idx) => {
+                        let pos = (addr_base + *idx * unit.addr_size as u64) as usize;
+                        *val = AttrValue::Addr(Cursor::at(addrs, pos).u64()?);
+                    },
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    pub fn die(&self, offset: u64) -> Option<&Die> {
+        self.dies.get(&offset)
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = &Die> {
+        self.units.iter().filter_map(|off| self.dies.get(off))
+    }
+
+    /// The compilation directory of each unit, keyed by where its line table starts in
+    /// .debug_line
+    pub fn comp_dirs(&self) -> HashMap<u64, PathBuf> {
+        self.units()
+            .filter_map(|unit| match unit.attr(DW_AT_COMP_DIR) {
+                Some(AttrValue::Str(dir)) => Some((unit.udata(DW_AT_STMT_LIST)?, PathBuf::from(dir))),
+                _ => None
+            })
+            .collect()
+    }
+
+    pub fn children<'a>(&'a self, die: &'a Die) -> impl Iterator<Item = &'a Die> {
+        die.children.iter().filter_map(|off| self.dies.get(off))
+    }
+
+    /// The name of a DIE, following DW_AT_specification and DW_AT_abstract_origin if needed
+    pub fn name_of<'a>(&'a self, die: &'a Die) -> Option<&'a str> {
+        die.name().or_else(|| {
+            let origin = die.reference(DW_AT_SPECIFICATION).or(die.reference(DW_AT_ABSTRACT_ORIGIN))?;
+            self.name_of(self.die(origin)?)
+        })
+    }
+
+    /// The innermost function whose code covers `addr`
+    pub fn function_containing(&self, addr: FileAddr) -> Option<&Die> {
+        let idx = self.functions.partition_point(|(low, _, _)| *low <= addr);
+        self.functions[..idx].iter().rev()
+            .find(|(_, high, _)| addr < *high)
+            .and_then(|(_, _, off)| self.die(*off))
+    }
+
+    /// The function covering `addr` followed by each nested lexical block that also covers it
+    pub fn scopes_at(&self, addr: FileAddr) -> Vec<&Die> {
+        let Some(func) = self.function_containing(addr) else {
+            return Vec::new();
+        };
+        let mut scopes = vec![func];
+        while let Some(block) = self.children(scopes[scopes.len() - 1])
+            .find(|d| d.tag == DW_TAG_LEXICAL_BLOCK && d.contains_pc(addr)) {
+            scopes.push(block);
+        }
+        scopes
+    }
+
+    /// Finds a variable or parameter visible at `addr`, innermost scope first, then globals
+    pub fn find_variable(&self, name: &str, addr: Option<FileAddr>) -> Option<&Die> {
+        let is_var = |d: &&Die| (d.tag == DW_TAG_VARIABLE || d.tag == DW_TAG_FORMAL_PARAMETER)
+            && self.name_of(d) == Some(name);
+        if let Some(addr) = addr {
+            for scope in self.scopes_at(addr).iter().rev() {
+                if let Some(var) = self.children(scope).find(is_var) {
+                    return Some(var);
+                }
+            }
+        }
+        self.global_variables().find(|d| is_var(d) && !d.flag(DW_AT_DECLARATION))
+    }
+
+    /// Variables defined at file scope in any unit
+    pub fn global_variables(&self) -> impl Iterator<Item = &Die> {
+        self.units().flat_map(|unit| self.children(unit)).filter(|d| d.tag == DW_TAG_VARIABLE)
+    }
+
+    /// Finds a named type. `struct x`, `union x` and `enum x` only match tagged types of that kind.
+    pub fn find_type(&self, name: &str) -> Option<&Die> {
+        let (tags, name): (&[u64], &str) = if let Some(rest) = name.strip_prefix("struct ") {
+            (&[DW_TAG_STRUCTURE_TYPE], rest)
+        } else if let Some(rest) = name.strip_prefix("union ") {
+            (&[DW_TAG_UNION_TYPE], rest)
+        } else if let Some(rest) = name.strip_prefix("enum ") {
+            (&[DW_TAG_ENUMERATION_TYPE], rest)
+        } else {
+            (&[DW_TAG_BASE_TYPE, DW_TAG_TYPEDEF], name)
+        };
+        let name = name.trim();
+        self.units()
+            .flat_map(|unit| self.children(unit))
+            .filter(|d| tags.contains(&d.tag) && d.name() == Some(name))
+            .max_by_key(|d| !d.flag(DW_AT_DECLARATION))
+    }
+}
+
+fn parse_abbrevs(data: &[u8], offset: u64) -> Result<HashMap<u64, Abbrev>> {
+    let mut out = HashMap::new();
+    let mut cur = Cursor::at(data, offset as usize);
+    loop {
+        let code = cur.uleb128()?;
+        if code == 0 {
+            return Ok(out);
+        }
+        let tag = cur.uleb128()?;
+        let has_children = cur.u8()? != 0;
+        let mut attrs = Vec::new();
+        loop {
+            let name = cur.uleb128()?;
+            let form = cur.uleb128()?;
+            let implicit_const = if form == DW_FORM_IMPLICIT_CONST { cur.sleb128()? } else { 0 };
+            if name == 0 && form == 0 {
+                break;
+            }
+            attrs.push(AttrSpec { name, form, implicit_const });
+        }
+        out.insert(code, Abbrev { tag, has_children, attrs });
+    }
+}
+
+fn read_attr(unit: &UnitContext, cur: &mut Cursor, form: u64, implicit_const: i64) -> Result<AttrValue> {
+    let elf = unit.elf;
+    Ok(match form {
+        DW_FORM_ADDR => AttrValue::Addr(if unit.addr_size == 4 { cur.u32()? as u64 } else { cur.u64()? }),
+        DW_FORM_BLOCK1 => {
+            let len = cur.u8()? as usize;
+            AttrValue::Block(cur.bytes(len)?.to_vec())
+        },
+        DW_FORM_BLOCK2 => {
+            let len = cur.u16()? as usize;
+            AttrValue::Block(cur.bytes(len)?.to_vec())
+        },
+        DW_FORM_BLOCK4 => {
+            let len = cur.u32()? as usize;
+            AttrValue::Block(cur.bytes(len)?.to_vec())
+        },
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let len = cur.uleb128()? as usize;
+            AttrValue::Block(cur.bytes(len)?.to_vec())
+        },
+        DW_FORM_DATA1 => AttrValue::Udata(cur.u8()? as u64),
+        DW_FORM_DATA2 => AttrValue::Udata(cur.u16()? as u64),
+        DW_FORM_DATA4 => AttrValue::Udata(cur.u32()? as u64),
+        DW_FORM_DATA8 => AttrValue::Udata(cur.u64()?),
+        DW_FORM_DATA16 => AttrValue::Block(cur.bytes(16)?.to_vec()),
+        DW_FORM_SDATA => AttrValue::Sdata(cur.sleb128()?),
+        DW_FORM_UDATA => AttrValue::Udata(cur.uleb128()?),
+        DW_FORM_IMPLICIT_CONST => AttrValue::Sdata(implicit_const),
+        DW_FORM_STRING => AttrValue::Str(cur.cstr()?.to_string()),
+        DW_FORM_STRP | DW_FORM_LINE_STRP => {
+            let section = if form == DW_FORM_STRP { ".debug_str" } else { ".debug_line_str" };
+            let off = cur.offset(unit.is_64)?;
+            let Some(data) = elf.section_data_by_name(section) else {
+                return error(&format!("missing {} section", section));
+            };
+            AttrValue::Str(str_at(data, off)?.to_string())
+        },
+        DW_FORM_STRP_SUP => AttrValue::SecOffset(cur.offset(unit.is_64)?),
+        DW_FORM_FLAG => AttrValue::Flag(cur.u8()? != 0),
+        DW_FORM_FLAG_PRESENT => AttrValue::Flag(true),
+        DW_FORM_REF1 => AttrValue::Ref(unit.offset + cur.u8()? as u64),
+        DW_FORM_REF2 => AttrValue::Ref(unit.offset + cur.u16()? as u64),
+        DW_FORM_REF4 => AttrValue::Ref(unit.offset + cur.u32()? as u64),
+        DW_FORM_REF8 => AttrValue::Ref(unit.offset + cur.u64()?),
+        DW_FORM_REF_UDATA => AttrValue::Ref(unit.offset + cur.uleb128()?),
+        DW_FORM_REF_ADDR => AttrValue::Ref(if unit.version == 2 { cur.u64()? } else { cur.offset(unit.is_64)? }),
+        DW_FORM_REF_SIG8 => AttrValue::Udata(cur.u64()?),
+        DW_FORM_REF_SUP4 => AttrValue::Udata(cur.u32()? as u64),
+        DW_FORM_REF_SUP8 => AttrValue::Udata(cur.u64()?),
+        DW_FORM_SEC_OFFSET => AttrValue::SecOffset(cur.offset(unit.is_64)?),
+        DW_FORM_LOCLISTX | DW_FORM_RNGLISTX => AttrValue::Udata(cur.uleb128()?),
+        DW_FORM_STRX => AttrValue::StrIndex(cur.uleb128()?),
+        DW_FORM_STRX1 => AttrValue::StrIndex(cur.u8()? as u64),
+        DW_FORM_STRX2 => AttrValue::StrIndex(cur.u16()? as u64),
+        DW_FORM_STRX3 => AttrValue::StrIndex(cur.u24()? as u64),
+        DW_FORM_STRX4 => AttrValue::StrIndex(cur.u32()? as u64),
+        DW_FORM_ADDRX => AttrValue::AddrIndex(cur.uleb128()?),
+        DW_FORM_ADDRX1 => AttrValue::AddrIndex(cur.u8()? as u64),
+        DW_FORM_ADDRX2 => AttrValue::AddrIndex(cur.u16()? as u64),
+        DW_FORM_ADDRX3 => AttrValue::AddrIndex(cur.u24()? as u64),
+        DW_FORM_ADDRX4 => AttrValue::AddrIndex(cur.u32()? as u64),
+        DW_FORM_INDIRECT => {
+            let form = cur.uleb128()?;
+            return read_attr(unit, cur, form, implicit_const);
+        },
+        _ => return error(&format!("unsupported attribute form {:#x}", form))
+    })
+}
@@ -6,13 +6,20 @@ use crate::{
 };
 use iced_x86::{
     Decoder, GasFormatter, Instruction as II, Formatter,
-    Mnemonic
+    Mnemonic, FlowControl
 };
 
 pub struct Instruction {
     pub va: VirtAddr,
     pub text: String,
-    pub opcodes: Vec::<u8>
+    pub opcodes: Vec::<u8>,
+    pub flow: FlowControl
+}
+
+impl Instruction {
+    pub fn is_call(&self) -> bool {
+        matches!(self.flow, FlowControl::Call | FlowControl::IndirectCall)
+    }
 }
 
 pub fn disassemble(p: &Process, rip: VirtAddr, mut max_inst: Option<usize>) -> Result<Vec<Instruction>> {
@@ -48,7 +55,8 @@ pub fn disassemble(p: &Process, rip: VirtAddr, mut max_inst: Option<usize>) -> R
         out.push(Instruction {
             va,
             text: fmt_output.clone(),
-            opcodes: buf[buf_start..buf_start+instr_buf.len()].to_vec()
+            opcodes: buf[buf_start..buf_start+instr_buf.len()].to_vec(),
+            flow: instr_buf.flow_control()
         });
         inst_left -= 1;
         if instr_buf.mnemonic() == Mnemonic::Ret {
@@ -0,0 +1,254 @@
+use crate::{
+    Result,
+    error
+};
+
+pub const DW_FORM_ADDR: u64 = 0x01;
+pub const DW_FORM_BLOCK2: u64 = 0x03;
+pub const DW_FORM_BLOCK4: u64 = 0x04;
+pub const DW_FORM_DATA2: u64 = 0x05;
+pub const DW_FORM_DATA4: u64 = 0x06;
+pub const DW_FORM_DATA8: u64 = 0x07;
+pub const DW_FORM_STRING: u64 = 0x08;
+pub const DW_FORM_BLOCK: u64 = 0x09;
+pub const DW_FORM_BLOCK1: u64 = 0x0a;
+pub const DW_FORM_DATA1: u64 = 0x0b;
+pub const DW_FORM_FLAG: u64 = 0x0c;
+pub const DW_FORM_SDATA: u64 = 0x0d;
+pub const DW_FORM_STRP: u64 = 0x0e;
+pub const DW_FORM_UDATA: u64 = 0x0f;
+pub const DW_FORM_REF_ADDR: u64 = 0x10;
+pub const DW_FORM_REF1: u64 = 0x11;
+pub const DW_FORM_REF2: u64 = 0x12;
+pub const DW_FORM_REF4: u64 = 0x13;
+pub const DW_FORM_REF8: u64 = 0x14;
+pub const DW_FORM_REF_UDATA: u64 = 0x15;
+pub const DW_FORM_INDIRECT: u64 = 0x16;
+pub const DW_FORM_SEC_OFFSET: u64 = 0x17;
+pub const DW_FORM_EXPRLOC: u64 = 0x18;
+pub const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+pub const DW_FORM_STRX: u64 = 0x1a;
+pub const DW_FORM_ADDRX: u64 = 0x1b;
+pub const DW_FORM_REF_SUP4: u64 = 0x1c;
+pub const DW_FORM_STRP_SUP: u64 = 0x1d;
+pub const DW_FORM_DATA16: u64 = 0x1e;
+pub const DW_FORM_LINE_STRP: u64 = 0x1f;
+pub const DW_FORM_REF_SIG8: u64 = 0x20;
+pub const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+pub const DW_FORM_LOCLISTX: u64 = 0x22;
+pub const DW_FORM_RNGLISTX: u64 = 0x23;
+pub const DW_FORM_REF_SUP8: u64 = 0x24;
+pub const DW_FORM_STRX1: u64 = 0x25;
+pub const DW_FORM_STRX2: u64 = 0x26;
+pub const DW_FORM_STRX3: u64 = 0x27;
+pub const DW_FORM_STRX4: u64 = 0x28;
+pub const DW_FORM_ADDRX1: u64 = 0x29;
+pub const DW_FORM_ADDRX2: u64 = 0x2a;
+pub const DW_FORM_ADDRX3: u64 = 0x2b;
+pub const DW_FORM_ADDRX4: u64 = 0x2c;
+
+pub const DW_TAG_ARRAY_TYPE: u64 = 0x01;
+pub const DW_TAG_ENUMERATION_TYPE: u64 = 0x04;
+pub const DW_TAG_FORMAL_PARAMETER: u64 = 0x05;
+pub const DW_TAG_LEXICAL_BLOCK: u64 = 0x0b;
+pub const DW_TAG_MEMBER: u64 = 0x0d;
+pub const DW_TAG_POINTER_TYPE: u64 = 0x0f;
+pub const DW_TAG_REFERENCE_TYPE: u64 = 0x10;
+pub const DW_TAG_COMPILE_UNIT: u64 = 0x11;
+pub const DW_TAG_STRUCTURE_TYPE: u64 = 0x13;
+pub const DW_TAG_SUBROUTINE_TYPE: u64 = 0x15;
+pub const DW_TAG_TYPEDEF: u64 = 0x16;
+pub const DW_TAG_UNION_TYPE: u64 = 0x17;
+pub const DW_TAG_UNSPECIFIED_PARAMETERS: u64 = 0x18;
+pub const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+pub const DW_TAG_SUBRANGE_TYPE: u64 = 0x21;
+pub const DW_TAG_BASE_TYPE: u64 = 0x24;
+pub const DW_TAG_CONST_TYPE: u64 = 0x26;
+pub const DW_TAG_ENUMERATOR: u64 = 0x28;
+pub const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+pub const DW_TAG_VARIABLE: u64 = 0x34;
+pub const DW_TAG_VOLATILE_TYPE: u64 = 0x35;
+pub const DW_TAG_RESTRICT_TYPE: u64 = 0x37;
+pub const DW_TAG_PARTIAL_UNIT: u64 = 0x3c;
+pub const DW_TAG_ATOMIC_TYPE: u64 = 0x47;
+
+pub const DW_AT_LOCATION: u64 = 0x02;
+pub const DW_AT_NAME: u64 = 0x03;
+pub const DW_AT_BYTE_SIZE: u64 = 0x0b;
+pub const DW_AT_BIT_OFFSET: u64 = 0x0c;
+pub const DW_AT_BIT_SIZE: u64 = 0x0d;
+pub const DW_AT_STMT_LIST: u64 = 0x10;
+pub const DW_AT_LOW_PC: u64 = 0x11;
+pub const DW_AT_HIGH_PC: u64 = 0x12;
+pub const DW_AT_COMP_DIR: u64 = 0x1b;
+pub const DW_AT_CONST_VALUE: u64 = 0x1c;
+pub const DW_AT_UPPER_BOUND: u64 = 0x2f;
+pub const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+pub const DW_AT_COUNT: u64 = 0x37;
+pub const DW_AT_DATA_MEMBER_LOCATION: u64 = 0x38;
+pub const DW_AT_DECLARATION: u64 = 0x3c;
+pub const DW_AT_ENCODING: u64 = 0x3e;
+pub const DW_AT_FRAME_BASE: u64 = 0x40;
+pub const DW_AT_SPECIFICATION: u64 = 0x47;
+pub const DW_AT_TYPE: u64 = 0x49;
+pub const DW_AT_DATA_BIT_OFFSET: u64 = 0x6b;
+pub const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+pub const DW_AT_ADDR_BASE: u64 = 0x73;
+
+pub const DW_ATE_ADDRESS: u64 = 0x01;
+pub const DW_ATE_BOOLEAN: u64 = 0x02;
+pub const DW_ATE_FLOAT: u64 = 0x04;
+pub const DW_ATE_SIGNED: u64 = 0x05;
+pub const DW_ATE_SIGNED_CHAR: u64 = 0x06;
+pub const DW_ATE_UNSIGNED: u64 = 0x07;
+pub const DW_ATE_UNSIGNED_CHAR: u64 = 0x08;
+pub const DW_ATE_UTF: u64 = 0x10;
+
+/// Reads the little-endian, LEB128 and string encodings used throughout DWARF
+#[derive(Clone)]
+pub struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn at(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn seek(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    pub fn finished(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n);
+        let Some(slice) = end.and_then(|end| self.data.get(self.pos..end)) else {
+            return error("unexpected end of DWARF data");
+        };
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn i8(&mut self) -> Result<i8> {
+        Ok(self.u8()? as i8)
+    }
+
+    pub fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u24(&mut self) -> Result<u32> {
+        let b = self.bytes(3)?;
+        Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16)
+    }
+
+    pub fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn i16(&mut self) -> Result<i16> {
+        Ok(self.u16()? as i16)
+    }
+
+    pub fn i32(&mut self) -> Result<i32> {
+        Ok(self.u32()? as i32)
+    }
+
+    pub fn i64(&mut self) -> Result<i64> {
+        Ok(self.u64()? as i64)
+    }
+
+    pub fn uleb128(&mut self) -> Result<u64> {
+        let mut out = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                out |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(out);
+            }
+        }
+    }
+
+    pub fn sleb128(&mut self) -> Result<i64> {
+        let mut out = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                out |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    out |= -1i64 << shift;
+                }
+                return Ok(out);
+            }
+        }
+    }
+
+    pub fn cstr(&mut self) -> Result<&'a str> {
+        let rest = &self.data[self.pos.min(self.data.len())..];
+        let Some(len) = rest.iter().position(|b| *b == 0) else {
+            return error("unterminated string in DWARF data");
+        };
+        let s = std::str::from_utf8(&rest[..len]).unwrap_or("");
+        self.pos += len + 1;
+        Ok(s)
+    }
+
+    /// Reads an offset that is 4 bytes in 32-bit DWARF and 8 in 64-bit DWARF
+    pub fn offset(&mut self, is_64: bool) -> Result<u64> {
+        if is_64 {
+            self.u64()
+        } else {
+            self.u32().map(|v| v as u64)
+        }
+    }
+
+    /// Reads a unit length, returning it along with whether the unit uses 64-bit DWARF
+    pub fn unit_length(&mut self) -> Result<(u64, bool)> {
+        let len = self.u32()?;
+        if len == 0xffffffff {
+            return Ok((self.u64()?, true));
+        }
+        Ok((len as u64, false))
+    }
+}
+
+/// Reads the null-terminated string at `offset` in a string section
+pub fn str_at(section: &[u8], offset: u64) -> Result<&str> {
+    Cursor::at(section, offset as usize).cstr()
+}
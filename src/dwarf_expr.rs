@@ -0,0 +1,236 @@
+use crate::{
+    Result,
+    error,
+    dwarf::Cursor
+};
+
+pub const DW_OP_ADDR: u8 = 0x03;
+pub const DW_OP_DEREF: u8 = 0x06;
+pub const DW_OP_CONST1U: u8 = 0x08;
+pub const DW_OP_CONST1S: u8 = 0x09;
+pub const DW_OP_CONST2U: u8 = 0x0a;
+pub const DW_OP_CONST2S: u8 = 0x0b;
+pub const DW_OP_CONST4U: u8 = 0x0c;
+pub const DW_OP_CONST4S: u8 = 0x0d;
+pub const DW_OP_CONST8U: u8 = 0x0e;
+pub const DW_OP_CONST8S: u8 = 0x0f;
+pub const DW_OP_CONSTU: u8 = 0x10;
+pub const DW_OP_CONSTS: u8 = 0x11;
+pub const DW_OP_DUP: u8 = 0x12;
+pub const DW_OP_DROP: u8 = 0x13;
+pub const DW_OP_OVER: u8 = 0x14;
+pub const DW_OP_PICK: u8 = 0x15;
+pub const DW_OP_SWAP: u8 = 0x16;
+pub const DW_OP_ROT: u8 = 0x17;
+pub const DW_OP_ABS: u8 = 0x19;
+pub const DW_OP_AND: u8 = 0x1a;
+pub const DW_OP_DIV: u8 = 0x1b;
+pub const DW_OP_MINUS: u8 = 0x1c;
+pub const DW_OP_MOD: u8 = 0x1d;
+pub const DW_OP_MUL: u8 = 0x1e;
+pub const DW_OP_NEG: u8 = 0x1f;
+pub const DW_OP_NOT: u8 = 0x20;
+pub const DW_OP_OR: u8 = 0x21;
+pub const DW_OP_PLUS: u8 = 0x22;
+pub const DW_OP_PLUS_UCONST: u8 = 0x23;
+pub const DW_OP_SHL: u8 = 0x24;
+pub const DW_OP_SHR: u8 = 0x25;
+pub const DW_OP_SHRA: u8 = 0x26;
+pub const DW_OP_XOR: u8 = 0x27;
+pub const DW_OP_BRA: u8 = 0x28;
+pub const DW_OP_EQ: u8 = 0x29;
+pub const DW_OP_GE: u8 = 0x2a;
+pub const DW_OP_GT: u8 = 0x2b;
+pub const DW_OP_LE: u8 = 0x2c;
+pub const DW_OP_LT: u8 = 0x2d;
+pub const DW_OP_NE: u8 = 0x2e;
+pub const DW_OP_SKIP: u8 = 0x2f;
+pub const DW_OP_LIT0: u8 = 0x30;
+pub const DW_OP_LIT31: u8 = 0x4f;
+pub const DW_OP_REG0: u8 = 0x50;
+pub const DW_OP_REG31: u8 = 0x6f;
+pub const DW_OP_BREG0: u8 = 0x70;
+pub const DW_OP_BREG31: u8 = 0x8f;
+pub const DW_OP_REGX: u8 = 0x90;
+pub const DW_OP_FBREG: u8 = 0x91;
+pub const DW_OP_BREGX: u8 = 0x92;
+pub const DW_OP_DEREF_SIZE: u8 = 0x94;
+pub const DW_OP_NOP: u8 = 0x96;
+pub const DW_OP_CALL_FRAME_CFA: u8 = 0x9c;
+pub const DW_OP_STACK_VALUE: u8 = 0x9f;
+
+/// What evaluating an expression needs from the stopped process
+pub trait ExprContext {
+    /// Reads the register with the given DWARF number
+    fn read_reg(&self, dwarf_id: u16) -> Result<u64>;
+    /// Reads `size` (at most 8) bytes of memory as a little-endian integer
+    fn read_mem(&self, addr: u64, size: usize) -> Result<u64>;
+
+    fn frame_base(&self) -> Result<u64> {
+        error("no frame base available for DW_OP_fbreg")
+    }
+
+    fn cfa(&self) -> Result<u64> {
+        error("no CFA available for DW_OP_call_frame_cfa")
+    }
+
+    /// Turns a DW_OP_addr operand, which is a file address, into a runtime one
+    fn relocate(&self, addr: u64) -> u64 {
+        addr
+    }
+}
+
+/// Where an expression says a value lives
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ExprResult {
+    /// The value is in memory at this address
+    Address(u64),
+    /// The value is in the register with this DWARF number
+    Register(u16),
+    /// The expression computed the value itself
+    Value(u64)
+}
+
+/// Runs a DWARF expression, starting from `initial` pushed onto the stack
+pub fn evaluate(expr: &[u8], ctx: &dyn ExprContext, initial: &[u64]) -> Result<ExprResult> {
+    let mut stack: Vec<u64> = initial.to_vec();
+    let mut cur = Cursor::new(expr);
+    let mut stack_value = false;
+
+    fn pop(stack: &mut Vec<u64>) -> Result<u64> {
+        match stack.pop() {
+            Some(v) => Ok(v),
+            None => error("DWARF expression stack underflow")
+        }
+    }
+
+    while !cur.finished() {
+        let op = cur.u8()?;
+        match op {
+            DW_OP_ADDR => stack.push(ctx.relocate(cur.u64()?)),
+            DW_OP_DEREF => {
+                let addr = pop(&mut stack)?;
+                stack.push(ctx.read_mem(addr, 8)?);
+            },
+            DW_OP_DEREF_SIZE => {
+                let size = cur.u8()? as usize;
+                if size == 0 || size > 8 {
+                    return error("bad size for DW_OP_deref_size");
+                }
+                let addr = pop(&mut stack)?;
+                stack.push(ctx.read_mem(addr, size)?);
+            },
+            DW_OP_CONST1U => stack.push(cur.u8()? as u64),
+            DW_OP_CONST1S => stack.push(cur.i8()? as u64),
+            DW_OP_CONST2U => stack.push(cur.u16()? as u64),
+            DW_OP_CONST2S => stack.push(cur.i16()? as u64),
+            DW_OP_CONST4U => stack.push(cur.u32()? as u64),
+            DW_OP_CONST4S => stack.push(cur.i32()? as u64),
+            DW_OP_CONST8U => stack.push(cur.u64()?),
+            DW_OP_CONST8S => stack.push(cur.i64()? as u64),
+            DW_OP_CONSTU => stack.push(cur.uleb128()?),
+            DW_OP_CONSTS => stack.push(cur.sleb128()? as u64),
+            DW_OP_LIT0..=DW_OP_LIT31 => stack.push((op - DW_OP_LIT0) as u64),
+            DW_OP_DUP => {
+                let top = pop(&mut stack)?;
+                stack.extend([top, top]);
+            },
+            DW_OP_DROP => { pop(&mut stack)?; },
+            DW_OP_OVER | DW_OP_PICK => {
+                let idx = if op == DW_OP_OVER { 1 } else { cur.u8()? as usize };
+                let Some(val) = stack.len().checked_sub(idx + 1).map(|i| stack[i]) else {
+                    return error("DWARF expression stack underflow");
+                };
+                stack.push(val);
+            },
+            DW_OP_SWAP => {
+                let a = pop(&mut stack)?;
+                let b = pop(&mut stack)?;
+                stack.extend([a, b]);
+            },
+            DW_OP_ROT => {
+                let a = pop(&mut stack)?;
+                let b = pop(&mut stack)?;
+                let c = pop(&mut stack)?;
+                stack.extend([a, c, b]);
+            },
+            DW_OP_ABS => {
+                let a = pop(&mut stack)? as i64;
+                stack.push(a.unsigned_abs());
+            },
+            DW_OP_NEG => {
+                let a = pop(&mut stack)? as i64;
+                stack.push(a.wrapping_neg() as u64);
+            },
+            DW_OP_NOT => {
+                let a = pop(&mut stack)?;
+                stack.push(!a);
+            },
+            DW_OP_PLUS_UCONST => {
+                let a = pop(&mut stack)?;
+                stack.push(a.wrapping_add(cur.uleb128()?));
+            },
+            DW_OP_AND | DW_OP_DIV | DW_OP_MINUS | DW_OP_MOD | DW_OP_MUL | DW_OP_OR | DW_OP_PLUS
+            | DW_OP_SHL | DW_OP_SHR | DW_OP_SHRA | DW_OP_XOR | DW_OP_EQ | DW_OP_GE | DW_OP_GT
+            | DW_OP_LE | DW_OP_LT | DW_OP_NE => {
+                let b = pop(&mut stack)?;
+                let a = pop(&mut stack)?;
+                stack.push(binary_op(op, a, b)?);
+            },
+            DW_OP_SKIP | DW_OP_BRA => {
+                let offset = cur.i16()? as isize;
+                if op == DW_OP_SKIP || pop(&mut stack)? != 0 {
+                    let Some(target) = cur.pos().checked_add_signed(offset).filter(|t| *t <= expr.len()) else {
+                        return error("DWARF expression branch out of range");
+                    };
+                    cur.seek(target);
+                }
+            },
+            DW_OP_REG0..=DW_OP_REG31 => return Ok(ExprResult::Register((op - DW_OP_REG0) as u16)),
+            DW_OP_REGX => return Ok(ExprResult::Register(cur.uleb128()? as u16)),
+            DW_OP_BREG0..=DW_OP_BREG31 => {
+                let reg = ctx.read_reg((op - DW_OP_BREG0) as u16)?;
+                stack.push(reg.wrapping_add_signed(cur.sleb128()?));
+            },
+            DW_OP_BREGX => {
+                let reg = ctx.read_reg(cur.uleb128()? as u16)?;
+                stack.push(reg.wrapping_add_signed(cur.sleb128()?));
+            },
+            DW_OP_FBREG => {
+                let base = ctx.frame_base()?;
+                stack.push(base.wrapping_add_signed(cur.sleb128()?));
+            },
+            DW_OP_CALL_FRAME_CFA => stack.push(ctx.cfa()?),
+            DW_OP_STACK_VALUE => stack_value = true,
+            DW_OP_NOP => {},
+            _ => return error(&format!("unsupported DWARF expression op {:#x}", op))
+        }
+    }
+
+    let top = pop(&mut stack)?;
+    Ok(if stack_value { ExprResult::Value(top) } else { ExprResult::Address(top) })
+}
+
+fn binary_op(op: u8, a: u64, b: u64) -> Result<u64> {
+    Ok(match op {
+        DW_OP_AND => a & b,
+        DW_OP_OR => a | b,
+        DW_OP_XOR => a ^ b,
+        DW_OP_PLUS => a.wrapping_add(b),
+        DW_OP_MINUS => a.wrapping_sub(b),
+        DW_OP_MUL => a.wrapping_mul(b),
+        DW_OP_DIV | DW_OP_MOD if b == 0 => return error("division by zero in DWARF expression"),
+        DW_OP_DIV => (a as i64).wrapping_div(b as i64) as u64,
+        DW_OP_MOD => a % b,
+        DW_OP_SHL => a.checked_shl(b as u32).unwrap_or(0),
+        DW_OP_SHR => a.checked_shr(b as u32).unwrap_or(0),
+        DW_OP_SHRA => (a as i64).checked_shr(b as u32).unwrap_or(if (a as i64) < 0 { -1 } else { 0 }) as u64,
+        DW_OP_EQ => (a == b) as u64,
+        DW_OP_NE => (a != b) as u64,
+        DW_OP_GE => ((a as i64) >= (b as i64)) as u64,
+        DW_OP_GT => ((a as i64) > (b as i64)) as u64,
+        DW_OP_LE => ((a as i64) <= (b as i64)) as u64,
+        DW_OP_LT => ((a as i64) < (b as i64)) as u64,
+        _ => return error(&format!("unsupported DWARF expression op {:#x}", op))
+    })
+}
@@ -0,0 +1,247 @@
+use crate::{
+    Result,
+    error
+};
+
+use libc::{
+    Elf64_Ehdr,
+    Elf64_Phdr,
+    Elf64_Shdr,
+    Elf64_Sym
+};
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub const SHT_SYMTAB: u32 = 2;
+pub const SHT_STRTAB: u32 = 3;
+pub const SHT_NOBITS: u32 = 8;
+pub const SHT_DYNSYM: u32 = 11;
+
+pub const STT_OBJECT: u8 = 1;
+pub const STT_FUNC: u8 = 2;
+
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub sh_type: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64
+}
+
+impl Section {
+    pub fn contains_addr(&self, addr: u64) -> bool {
+        self.addr != 0 && addr >= self.addr && addr < self.addr + self.size
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub p_type: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+    pub info: u8,
+    pub shndx: u16
+}
+
+impl Symbol {
+    pub fn sym_type(&self) -> u8 {
+        self.info & 0xf
+    }
+
+    pub fn contains_addr(&self, addr: u64) -> bool {
+        addr == self.value || (addr > self.value && addr < self.value + self.size)
+    }
+}
+
+/// An ELF64 file read from disk, with its section and program headers and symbol tables
+pub struct Elf {
+    pub path: PathBuf,
+    data: Vec<u8>,
+    pub header: Elf64_Ehdr,
+    pub sections: Vec<Section>,
+    pub segments: Vec<Segment>,
+    /// Entries from .symtab and .dynsym, sorted by address
+    pub symbols: Vec<Symbol>
+}
+
+impl std::fmt::Debug for Elf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<elf {}>", self.path.display())
+    }
+}
+
+fn read_struct<T>(data: &[u8], offset: usize) -> Result<T> {
+    if offset.checked_add(std::mem::size_of::<T>()).is_none_or(|end| end > data.len()) {
+        return error("ELF structure extends past end of file");
+    }
+    unsafe {
+        Ok(std::ptr::read_unaligned(data.as_ptr().add(offset) as *const T))
+    }
+}
+
+fn read_cstr(data: &[u8], offset: usize) -> String {
+    let Some(bytes) = data.get(offset..) else {
+        return String::new();
+    };
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+impl Elf {
+    /// Whether the path is a regular file starting with the ELF magic, found by reading
+    /// only the first four bytes
+    pub fn is_elf_file(path: &Path) -> bool {
+        let mut magic = [0u8; 4];
+        path.metadata().is_ok_and(|m| m.is_file())
+            && File::open(path).and_then(|mut f| f.read_exact(&mut magic)).is_ok()
+            && magic == *b"\x7fELF"
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let Ok(data) = std::fs::read(path) else {
+            return error(&format!("could not read ELF file {}", path.display()));
+        };
+        Self::parse(path, data)
+    }
+
+    pub fn parse(path: &Path, data: Vec<u8>) -> Result<Self> {
+        if data.len() < 16 || data[0..4] != *b"\x7fELF" {
+            return error("not an ELF file");
+        }
+        if data[4] != 2 || data[5] != 1 {
+            return error("only little-endian ELF64 files are supported");
+        }
+        let header: Elf64_Ehdr = read_struct(&data, 0)?;
+
+        let mut raw_sections = Vec::<Elf64_Shdr>::new();
+        for i in 0..header.e_shnum as usize {
+            let offset = header.e_shoff as usize + i * header.e_shentsize as usize;
+            raw_sections.push(read_struct(&data, offset)?);
+        }
+        let shstrtab = raw_sections.get(header.e_shstrndx as usize).map(|s| s.sh_offset as usize);
+        let sections: Vec<Section> = raw_sections.iter().map(|s| Section {
+            name: shstrtab.map(|off| read_cstr(&data, off + s.sh_name as usize)).unwrap_or_default(),
+            sh_type: s.sh_type,
+            flags: s.sh_flags,
+            addr: s.sh_addr,
+            offset: s.sh_offset,
+            size: s.sh_size,
+            link: s.sh_link,
+            info: s.sh_info,
+            addralign: s.sh_addralign,
+            entsize: s.sh_entsize
+        }).collect();
+
+        let mut segments = Vec::new();
+        for i in 0..header.e_phnum as usize {
+            let offset = header.e_phoff as usize + i * header.e_phentsize as usize;
+            let p: Elf64_Phdr = read_struct(&data, offset)?;
+            segments.push(Segment {
+                p_type: p.p_type,
+                flags: p.p_flags,
+                offset: p.p_offset,
+                vaddr: p.p_vaddr,
+                filesz: p.p_filesz,
+                memsz: p.p_memsz,
+                align: p.p_align
+            });
+        }
+
+        let mut elf = Self {
+            path: path.to_path_buf(),
+            data,
+            header,
+            sections,
+            segments,
+            symbols: Vec::new()
+        };
+        elf.symbols = elf.parse_symbols()?;
+        Ok(elf)
+    }
+
+    fn parse_symbols(&self) -> Result<Vec<Symbol>> {
+        let mut out = Vec::new();
+        for sec in self.sections.iter().filter(|s| s.sh_type == SHT_SYMTAB || s.sh_type == SHT_DYNSYM) {
+            let Some(strtab) = self.sections.get(sec.link as usize) else {
+                return error(&format!("{} has no string table", sec.name));
+            };
+            let entsize = if sec.entsize == 0 { std::mem::size_of::<Elf64_Sym>() as u64 } else { sec.entsize };
+            // The first entry of every symbol table is the reserved null symbol
+            for i in 1..(sec.size / entsize) {
+                let sym: Elf64_Sym = read_struct(&self.data, (sec.offset + i * entsize) as usize)?;
+                if sym.st_name == 0 {
+                    continue;
+                }
+                out.push(Symbol {
+                    name: read_cstr(&self.data, strtab.offset as usize + sym.st_name as usize),
+                    value: sym.st_value,
+                    size: sym.st_size,
+                    info: sym.st_info,
+                    shndx: sym.st_shndx
+                });
+            }
+        }
+        out.sort_by_key(|s| s.value);
+        Ok(out)
+    }
+
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub fn section_data(&self, sec: &Section) -> &[u8] {
+        if sec.sh_type == SHT_NOBITS {
+            return &[];
+        }
+        let start = sec.offset as usize;
+        let end = start.saturating_add(sec.size as usize);
+        self.data.get(start..end).unwrap_or(&[])
+    }
+
+    pub fn section_data_by_name(&self, name: &str) -> Option<&[u8]> {
+        self.section(name).map(|s| self.section_data(s))
+    }
+
+    pub fn section_containing_addr(&self, addr: u64) -> Option<&Section> {
+        self.sections.iter().find(|s| s.contains_addr(addr))
+    }
+
+    /// Whether the file is position independent, i.e. loaded at a bias chosen at runtime
+    pub fn is_pie(&self) -> bool {
+        self.header.e_type == ET_DYN
+    }
+
+    pub fn symbols_by_name(&self, name: &str) -> Vec<&Symbol> {
+        self.symbols.iter().filter(|s| s.name == name).collect()
+    }
+
+    /// Finds the function or object symbol covering the given file address
+    pub fn symbol_containing_addr(&self, addr: u64) -> Option<&Symbol> {
+        let idx = self.symbols.partition_point(|s| s.value <= addr);
+        self.symbols[..idx].iter().rev()
+            .filter(|s| s.value != 0 && matches!(s.sym_type(), STT_FUNC | STT_OBJECT))
+            .find(|s| s.contains_addr(addr))
+    }
+}
@@ -0,0 +1,464 @@
+use crate::{
+    Result,
+    error,
+    Process,
+    VirtAddr,
+    RegisterFormat,
+    register_by_name,
+    parse_u64,
+    variables::{Type, Value, read_variable, lookup_type}
+};
+
+#[derive(PartialEq, Clone, Debug)]
+enum Token {
+    Int(u64),
+    Float(f64),
+    Ident(String),
+    Register(String),
+    Punct(&'static str)
+}
+
+const PUNCTUATION: [&str; 27] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||",
+    "+", "-", "*", "/", "%", "&", "|", "^", "~", "!", "<", ">", "(", ")", "[", "]", ".", ","
+];
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        let ch = rest.chars().next().unwrap();
+        let word_len = |s: &str| s.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.')).unwrap_or(s.len());
+        if ch.is_ascii_digit() {
+            let len = word_len(rest);
+            let word = &rest[..len];
+            let is_float = !word.starts_with("0x") && (word.contains('.') || word.contains('e'));
+            tokens.push(if is_float {
+                let Ok(val) = word.parse::<f64>() else {
+                    return error(&format!("could not parse number {}", word));
+                };
+                Token::Float(val)
+            } else {
+                let Ok(val) = parse_u64(word) else {
+                    return error(&format!("could not parse number {}", word));
+                };
+                Token::Int(val)
+            });
+            rest = &rest[len..];
+        } else if ch == '$' || ch.is_alphabetic() || ch == '_' {
+            let start = if ch == '$' { 1 } else { 0 };
+            let len = start + rest[start..].find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len() - start);
+            if ch == '$' {
+                tokens.push(Token::Register(rest[1..len].to_string()));
+            } else {
+                tokens.push(Token::Ident(rest[..len].to_string()));
+            }
+            rest = &rest[len..];
+        } else {
+            let Some(punct) = PUNCTUATION.iter().find(|p| rest.starts_with(**p)) else {
+                return error(&format!("unexpected character '{}' in expression", ch));
+            };
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Binary operators from loosest to tightest binding
+const PRECEDENCE: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"]
+];
+
+struct Parser<'a, 't> {
+    p: &'a Process,
+    tokens: &'t [Token],
+    pos: usize
+}
+
+impl<'a> Parser<'a, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(p)) => Some(p),
+            _ => None
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.peek_punct() != Some(punct) {
+            return error(&format!("expected '{}' in expression", punct));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Value<'a>> {
+        if level == PRECEDENCE.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = self.peek_punct().filter(|op| PRECEDENCE[level].contains(op)) {
+            self.pos += 1;
+            // like C, the right side of && and || is not evaluated once the left decides
+            if (op == "&&" || op == "||") && truthy(&lhs)? == (op == "||") {
+                self.skip_operand(level)?;
+                lhs = bool_value(op == "||");
+                continue;
+            }
+            let rhs = self.binary(level + 1)?;
+            lhs = binary_op(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    /// Moves past the operand of an operator at `level` without evaluating it: up to the
+    /// next operator binding as loosely, or the end of the enclosing parentheses
+    fn skip_operand(&mut self, level: usize) -> Result<()> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        while let Some(token) = self.peek() {
+            match token {
+                Token::Punct("(" | "[") => depth += 1,
+                Token::Punct(")" | "]") if depth == 0 => break,
+                Token::Punct(")" | "]") => depth -= 1,
+                Token::Punct(op) if depth == 0 && PRECEDENCE[..=level].iter().any(|ops| ops.contains(op)) => break,
+                _ => ()
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return error("unexpected end of expression");
+        }
+        Ok(())
+    }
+
+    fn unary(&mut self) -> Result<Value<'a>> {
+        let Some(op) = self.peek_punct() else {
+            return self.postfix();
+        };
+        if op == "(" {
+            if let Some((ty, end)) = self.cast_type() {
+                self.pos = end;
+                let val = self.unary()?;
+                return cast(val, &ty);
+            }
+            return self.postfix();
+        }
+        if !["-", "+", "~", "!", "*", "&"].contains(&op) {
+            return error(&format!("unexpected '{}' in expression", op));
+        }
+        self.pos += 1;
+        let val = self.unary()?;
+        match op {
+            "*" => decay(val)?.deref(self.p),
+            "&" => val.address_of(),
+            "+" => Ok(val),
+            "-" => binary_op("-", int_value(Type::builtin("int").unwrap(), 0), val),
+            "~" => {
+                let ty = promote(&val.ty, &val.ty)?;
+                Ok(int_value(ty, !val.as_u64()?))
+            },
+            _ => Ok(bool_value(!truthy(&val)?))
+        }
+    }
+
+    /// If the parenthesis at the current position starts a cast, the type and where the
+    /// expression being cast starts
+    fn cast_type(&self) -> Option<(Type<'a>, usize)> {
+        let mut text = String::new();
+        let mut idx = self.pos + 1;
+        loop {
+            match self.tokens.get(idx)? {
+                Token::Ident(word) => {
+                    text.push(' ');
+                    text.push_str(word);
+                },
+                Token::Punct("*") => text.push('*'),
+                Token::Punct(")") => break,
+                _ => return None
+            }
+            idx += 1;
+        }
+        let ty = lookup_type(self.p, text.trim()).ok()?;
+        Some((ty, idx + 1))
+    }
+
+    fn postfix(&mut self) -> Result<Value<'a>> {
+        let mut val = self.primary()?;
+        loop {
+            match self.peek_punct() {
+                Some("[") => {
+                    self.pos += 1;
+                    let idx = self.binary(0)?;
+                    self.expect("]")?;
+                    val = val.index(self.p, idx.as_u64()? as i64)?;
+                },
+                Some(op @ ("." | "->")) => {
+                    self.pos += 1;
+                    let Some(Token::Ident(field)) = self.peek().cloned() else {
+                        return error(&format!("expected a member name after {}", op));
+                    };
+                    self.pos += 1;
+                    if op == "->" {
+                        val = val.deref(self.p)?;
+                    }
+                    val = val.member(&field)?;
+                },
+                _ => return Ok(val)
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value<'a>> {
+        let Some(token) = self.peek().cloned() else {
+            return error("unexpected end of expression");
+        };
+        self.pos += 1;
+        match token {
+            Token::Int(val) => {
+                let name = if val <= i32::MAX as u64 {
+                    "int"
+                } else if val <= i64::MAX as u64 {
+                    "long"
+                } else {
+                    "unsigned long"
+                };
+                Ok(int_value(Type::builtin(name).unwrap(), val))
+            },
+            Token::Float(val) => Ok(Value::new(Type::builtin("double").unwrap(), val.to_le_bytes().to_vec())),
+            Token::Register(name) => read_register(self.p, &name),
+            Token::Ident(name) => read_identifier(self.p, &name),
+            Token::Punct("(") => {
+                let val = self.binary(0)?;
+                self.expect(")")?;
+                Ok(val)
+            },
+            Token::Punct(op) => error(&format!("unexpected '{}' in expression", op))
+        }
+    }
+}
+
+fn read_register<'a>(p: &'a Process, name: &str) -> Result<Value<'a>> {
+    let name = match name {
+        "pc" => "rip",
+        "sp" => "rsp",
+        "fp" => "rbp",
+        _ => name
+    };
+    let Ok(ri) = register_by_name(name) else {
+        return error(&format!("unknown register ${}", name));
+    };
+    let regs = p.frame_registers(p.selected_frame())?;
+    let ty = match (ri.format, ri.size) {
+        (RegisterFormat::Uint, 1) => "uint8_t",
+        (RegisterFormat::Uint, 2) => "uint16_t",
+        (RegisterFormat::Uint, 4) => "uint32_t",
+        (RegisterFormat::Uint, 8) => "uint64_t",
+        (RegisterFormat::Double, 4) => "float",
+        (RegisterFormat::Double, 8) => "double",
+        (RegisterFormat::LongDouble, _) => "long double",
+        _ => return error(&format!("${} cannot be used in expressions", name))
+    };
+    let ty = Type::builtin(ty).unwrap();
+    let data = match ty.size() {
+        16 => regs.read_as::<[u8; 16]>(ri).to_vec(),
+        size => regs.read_as::<u64>(ri).to_le_bytes()[..size].to_vec()
+    };
+    Ok(Value::new(ty, data))
+}
+
+/// A variable visible from the selected frame, or else the address of a symbol
+fn read_identifier<'a>(p: &'a Process, name: &str) -> Result<Value<'a>> {
+    if let Ok(val) = read_variable(p, name) {
+        return Ok(val);
+    }
+    match p.find_symbol(name).first() {
+        Some(addr) => Ok(int_value(Type::builtin("unsigned long").unwrap(), addr.0)),
+        None => error(&format!("no symbol \"{}\" in current context", name))
+    }
+}
+
+fn int_value<'a>(ty: Type<'a>, val: u64) -> Value<'a> {
+    let size = ty.size().clamp(1, 8);
+    Value::new(ty, val.to_le_bytes()[..size].to_vec())
+}
+
+fn bool_value<'a>(val: bool) -> Value<'a> {
+    int_value(Type::builtin("int").unwrap(), val as u64)
+}
+
+fn truthy(val: &Value) -> Result<bool> {
+    if val.ty.is_float() {
+        return Ok(val.as_f64()? != 0.0);
+    }
+    Ok(decay(val.clone())?.as_u64()? != 0)
+}
+
+/// Arrays used as values stand for a pointer to their first element
+fn decay(val: Value) -> Result<Value> {
+    let Some((elem, _)) = val.ty.element() else {
+        return Ok(val);
+    };
+    let Some(addr) = val.addr else {
+        return error("cannot take the address of an array that is not in memory");
+    };
+    Ok(int_value(elem.pointer_to(), addr.0))
+}
+
+/// The type integer arithmetic on `a` and `b` is done in, after C's usual promotions
+fn promote<'a>(a: &Type<'a>, b: &Type<'a>) -> Result<Type<'a>> {
+    for ty in [a, b] {
+        if ty.is_aggregate() || ty.is_void() || (ty.encoding().is_none() && !ty.is_pointer()) {
+            return error(&format!("invalid operand of type {}", ty.name()));
+        }
+    }
+    if a.is_float() || b.is_float() {
+        return Ok(Type::builtin("double").unwrap());
+    }
+    let size = a.size().max(b.size()).max(4);
+    let unsigned = [a, b].iter().any(|ty| !ty.is_signed() && ty.size() >= size);
+    let name = match (size, unsigned) {
+        (4, false) => "int",
+        (4, true) => "unsigned int",
+        (_, false) => "long",
+        (_, true) => "unsigned long"
+    };
+    Ok(Type::builtin(name).unwrap())
+}
+
+fn binary_op<'a>(op: &str, lhs: Value<'a>, rhs: Value<'a>) -> Result<Value<'a>> {
+    let (lhs, rhs) = (decay(lhs)?, decay(rhs)?);
+    if op == "&&" || op == "||" {
+        let (a, b) = (truthy(&lhs)?, truthy(&rhs)?);
+        return Ok(bool_value(if op == "&&" { a && b } else { a || b }));
+    }
+
+    // pointer arithmetic scales by the size of what is pointed to
+    if let Some(target) = lhs.ty.pointee().filter(|_| op == "+" || op == "-") {
+        let scale = target.size().max(1) as u64;
+        if let Some(rhs_target) = rhs.ty.pointee() {
+            if op == "-" {
+                let diff = lhs.as_u64()?.wrapping_sub(rhs.as_u64()?) as i64 / rhs_target.size().max(1) as i64;
+                return Ok(int_value(Type::builtin("long").unwrap(), diff as u64));
+            }
+            return error("cannot add two pointers");
+        }
+        let offset = rhs.as_u64()?.wrapping_mul(scale);
+        let addr = if op == "+" { lhs.as_u64()?.wrapping_add(offset) } else { lhs.as_u64()?.wrapping_sub(offset) };
+        return Ok(int_value(lhs.ty.clone(), addr));
+    }
+    if let Some(target) = rhs.ty.pointee().filter(|_| op == "+") {
+        let addr = rhs.as_u64()?.wrapping_add(lhs.as_u64()?.wrapping_mul(target.size().max(1) as u64));
+        return Ok(int_value(rhs.ty.clone(), addr));
+    }
+
+    let ty = if lhs.ty.is_pointer() || rhs.ty.is_pointer() {
+        Type::builtin("unsigned long").unwrap()
+    } else {
+        promote(&lhs.ty, &rhs.ty)?
+    };
+    if ty.is_float() {
+        let (a, b) = (lhs.as_f64()?, rhs.as_f64()?);
+        let val = match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            "==" => return Ok(bool_value(a == b)),
+            "!=" => return Ok(bool_value(a != b)),
+            "<" => return Ok(bool_value(a < b)),
+            "<=" => return Ok(bool_value(a <= b)),
+            ">" => return Ok(bool_value(a > b)),
+            ">=" => return Ok(bool_value(a >= b)),
+            _ => return error(&format!("invalid operands to {}", op))
+        };
+        return Ok(Value::new(ty, val.to_le_bytes().to_vec()));
+    }
+
+    let signed = ty.is_signed();
+    let (a, b) = (lhs.as_u64()?, rhs.as_u64()?);
+    let ordering = if signed { (a as i64).cmp(&(b as i64)) } else { a.cmp(&b) };
+    let val = match op {
+        "+" => a.wrapping_add(b),
+        "-" => a.wrapping_sub(b),
+        "*" => a.wrapping_mul(b),
+        "/" | "%" if b == 0 => return error("division by zero"),
+        "/" if signed => (a as i64).wrapping_div(b as i64) as u64,
+        "/" => a / b,
+        "%" if signed => (a as i64).wrapping_rem(b as i64) as u64,
+        "%" => a % b,
+        "&" => a & b,
+        "|" => a | b,
+        "^" => a ^ b,
+        "<<" => a.checked_shl(b as u32).unwrap_or(0),
+        ">>" if signed => (a as i64).checked_shr(b as u32).unwrap_or(if (a as i64) < 0 { -1 } else { 0 }) as u64,
+        ">>" => a.checked_shr(b as u32).unwrap_or(0),
+        "==" => return Ok(bool_value(a == b)),
+        "!=" => return Ok(bool_value(a != b)),
+        "<" => return Ok(bool_value(ordering.is_lt())),
+        "<=" => return Ok(bool_value(ordering.is_le())),
+        ">" => return Ok(bool_value(ordering.is_gt())),
+        ">=" => return Ok(bool_value(ordering.is_ge())),
+        _ => return error(&format!("unknown operator {}", op))
+    };
+    Ok(int_value(ty, val))
+}
+
+fn cast<'a>(val: Value<'a>, ty: &Type<'a>) -> Result<Value<'a>> {
+    let val = decay(val)?;
+    if ty.is_void() {
+        return Ok(Value::new(ty.clone(), Vec::new()));
+    }
+    if ty.is_aggregate() {
+        return error(&format!("cannot cast to {}", ty.name()));
+    }
+    if ty.is_float() {
+        let f = val.as_f64()?;
+        let data = match ty.size() {
+            4 => (f as f32).to_le_bytes().to_vec(),
+            8 => f.to_le_bytes().to_vec(),
+            _ => return error(&format!("cannot cast to {}", ty.name()))
+        };
+        return Ok(Value::new(ty.clone(), data));
+    }
+    Ok(int_value(ty.clone(), val.as_u64()?))
+}
+
+/// Evaluates a C-like expression against the selected frame. Supports registers (`$rsp`),
+/// variables and symbols, casts, `*`, `&`, `[]`, `.` and `->`, and arithmetic, bitwise,
+/// comparison and logical operators.
+pub fn evaluate_expression<'a>(p: &'a Process, text: &str) -> Result<Value<'a>> {
+    let tokens = tokenize(text)?;
+    if tokens.is_empty() {
+        return error("empty expression");
+    }
+    let mut parser = Parser { p, tokens: &tokens, pos: 0 };
+    let val = parser.binary(0)?;
+    if parser.pos != tokens.len() {
+        return error(&format!("unexpected {:?} in expression", tokens[parser.pos]));
+    }
+    Ok(val)
+}
+
+/// Evaluates an expression that names an address, such as a pointer, an array or a number
+pub fn evaluate_address(p: &Process, text: &str) -> Result<VirtAddr> {
+    let val = decay(evaluate_expression(p, text)?)?;
+    if val.ty.is_float() {
+        return error("an address cannot be a floating point value");
+    }
+    Ok(VirtAddr(val.as_u64()?))
+}
@@ -4,18 +4,50 @@ mod pipe;
 mod register_info;
 mod registers;
 mod process;
+mod threads;
+mod signals;
+mod syscalls;
+mod syscall_trace;
+mod syscall_faults;
 mod parsing;
 mod breakpoints;
 mod disassembler;
+mod elf;
+mod objects;
+mod loader;
+mod memory_map;
+mod dwarf;
+mod line_table;
+mod dwarf_expr;
+mod unwind;
+mod debug_info;
+mod variables;
+mod expr;
 
 pub use {
     pipe::*,
     register_info::*,
     registers::*,
     process::*,
+    threads::*,
+    signals::*,
+    syscalls::*,
+    syscall_trace::*,
+    syscall_faults::*,
     parsing::*,
     breakpoints::*,
-    disassembler::*
+    disassembler::*,
+    elf::*,
+    objects::*,
+    loader::*,
+    memory_map::*,
+    dwarf::*,
+    line_table::*,
+    dwarf_expr::*,
+    unwind::*,
+    debug_info::*,
+    variables::*,
+    expr::*
 };
 
 pub type Result<T> = std::result::Result<T, GadbErr>;
@@ -44,4 +76,4 @@ impl std::fmt::Display for GadbErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.msg)
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
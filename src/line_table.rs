@@ -0,0 +1,369 @@
+use crate::{
+    Result,
+    error,
+    FileAddr,
+    elf::Elf,
+    dwarf::*
+};
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_SET_COLUMN: u8 = 5;
+const DW_LNS_NEGATE_STMT: u8 = 6;
+const DW_LNS_SET_BASIC_BLOCK: u8 = 7;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+const DW_LNS_SET_PROLOGUE_END: u8 = 10;
+const DW_LNS_SET_EPILOGUE_BEGIN: u8 = 11;
+const DW_LNS_SET_ISA: u8 = 12;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+const DW_LNE_SET_DISCRIMINATOR: u8 = 4;
+
+const DW_LNCT_PATH: u64 = 1;
+const DW_LNCT_DIRECTORY_INDEX: u64 = 2;
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct LineRow {
+    pub addr: FileAddr,
+    pub file: usize,
+    pub line: u64,
+    pub column: u64,
+    pub is_stmt: bool,
+    pub prologue_end: bool,
+    pub end_sequence: bool
+}
+
+/// A file and line in the program's source
+#[derive(PartialEq, Clone, Debug)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: u64
+}
+
+impl std::fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+/// The line number program of one compilation unit
+#[derive(Debug)]
+pub struct LineTable {
+    /// Offset of this program within .debug_line
+    pub offset: u64,
+    pub files: Vec<PathBuf>,
+    pub rows: Vec<LineRow>
+}
+
+impl LineTable {
+    pub fn file(&self, row: &LineRow) -> Option<&Path> {
+        self.files.get(row.file).map(|p| p.as_path())
+    }
+
+    /// The row whose address range covers `addr`
+    pub fn row_containing(&self, addr: FileAddr) -> Option<&LineRow> {
+        self.rows.windows(2)
+            .find(|w| !w[0].end_sequence && w[0].addr <= addr && addr < w[1].addr)
+            .map(|w| &w[0])
+    }
+
+    fn parse(elf: &Elf, cur: &mut Cursor, comp_dir: Option<&Path>) -> Result<Self> {
+        let offset = cur.pos() as u64;
+        let (unit_length, is_64) = cur.unit_length()?;
+        let Some(end) = cur.pos().checked_add(unit_length as usize).filter(|end| *end <= cur.data().len()) else {
+            return error(&format!("line table at {:#x} runs past the end of .debug_line", offset));
+        };
+        let version = cur.u16()?;
+        if !(2..=5).contains(&version) {
+            return error(&format!("unsupported .debug_line version {}", version));
+        }
+        if version >= 5 {
+            let address_size = cur.u8()?;
+            if address_size != 8 {
+                return error("only 64-bit line tables are supported");
+            }
+            let _segment_selector_size = cur.u8()?;
+        }
+        let header_length = cur.offset(is_64)?;
+        let Some(program_start) = cur.pos().checked_add(header_length as usize).filter(|start| *start <= end) else {
+            return error("line table header runs past the end of its unit");
+        };
+        let min_inst_length = cur.u8()? as u64;
+        if version >= 4 {
+            let _max_ops_per_inst = cur.u8()?;
+        }
+        let default_is_stmt = cur.u8()? != 0;
+        let line_base = cur.i8()? as i64;
+        let line_range = cur.u8()? as u64;
+        let opcode_base = cur.u8()?;
+        let mut standard_opcode_lengths = vec![0u8];
+        for _ in 1..opcode_base {
+            standard_opcode_lengths.push(cur.u8()?);
+        }
+        if line_range == 0 {
+            return error("line table has a line_range of 0");
+        }
+
+        let mut files = if version >= 5 {
+            Self::parse_v5_files(elf, cur, is_64)?
+        } else {
+            Self::parse_v4_files(cur, comp_dir)?
+        };
+
+        cur.seek(program_start);
+        let mut rows = Vec::new();
+        let mut state = LineRow {
+            addr: FileAddr(0),
+            file: 1,
+            line: 1,
+            column: 0,
+            is_stmt: default_is_stmt,
+            prologue_end: false,
+            end_sequence: false
+        };
+        let reset = state.clone();
+        while cur.pos() < end {
+            let opcode = cur.u8()?;
+            if opcode >= opcode_base {
+                let adjusted = (opcode - opcode_base) as u64;
+                state.addr = state.addr + (adjusted / line_range) * min_inst_length;
+                state.line = (state.line as i64 + line_base + (adjusted % line_range) as i64) as u64;
+                rows.push(state.clone());
+                state.prologue_end = false;
+                continue;
+            }
+            match opcode {
+                0 => {
+                    let len = cur.uleb128()? as usize;
+                    let ext_end = cur.pos().saturating_add(len);
+                    match cur.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            state.end_sequence = true;
+                            rows.push(state.clone());
+                            state = reset.clone();
+                        },
+                        DW_LNE_SET_ADDRESS => state.addr = FileAddr(cur.u64()?),
+                        DW_LNE_DEFINE_FILE => files.push(PathBuf::from(cur.cstr()?)),
+                        DW_LNE_SET_DISCRIMINATOR => { cur.uleb128()?; },
+                        _ => {}
+                    }
+                    cur.seek(ext_end);
+                },
+                DW_LNS_COPY => {
+                    rows.push(state.clone());
+                    state.prologue_end = false;
+                },
+                DW_LNS_ADVANCE_PC => state.addr = state.addr + cur.uleb128()? * min_inst_length,
+                DW_LNS_ADVANCE_LINE => state.line = (state.line as i64 + cur.sleb128()?) as u64,
+                DW_LNS_SET_FILE => state.file = cur.uleb128()? as usize,
+                DW_LNS_SET_COLUMN => state.column = cur.uleb128()?,
+                DW_LNS_NEGATE_STMT => state.is_stmt = !state.is_stmt,
+                DW_LNS_SET_BASIC_BLOCK | DW_LNS_SET_EPILOGUE_BEGIN => {},
+                DW_LNS_CONST_ADD_PC => {
+                    let adjusted = (255 - opcode_base) as u64;
+                    state.addr = state.addr + (adjusted / line_range) * min_inst_length;
+                },
+                DW_LNS_FIXED_ADVANCE_PC => state.addr = state.addr + cur.u16()? as u64,
+                DW_LNS_SET_PROLOGUE_END => state.prologue_end = true,
+                DW_LNS_SET_ISA => { cur.uleb128()?; },
+                _ => {
+                    // Unknown standard opcodes declare how many ULEB operands to skip
+                    for _ in 0..standard_opcode_lengths[opcode as usize] {
+                        cur.uleb128()?;
+                    }
+                }
+            }
+        }
+        cur.seek(end);
+        Ok(Self { offset, files, rows })
+    }
+
+    /// DWARF 2-4: null-terminated lists of directories and files. File and directory
+    /// indices are 1-based; index 0 means the compilation directory, which the unit's
+    /// DW_AT_comp_dir gives and relative directories are taken from.
+    fn parse_v4_files(cur: &mut Cursor, comp_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+        let comp_dir = comp_dir.map(Path::to_path_buf).unwrap_or_default();
+        let mut dirs = vec![comp_dir.clone()];
+        loop {
+            let dir = cur.cstr()?;
+            if dir.is_empty() {
+                break;
+            }
+            dirs.push(comp_dir.join(dir));
+        }
+        let mut files = vec![PathBuf::new()];
+        loop {
+            let name = cur.cstr()?;
+            if name.is_empty() {
+                break;
+            }
+            let dir = cur.uleb128()? as usize;
+            let _mtime = cur.uleb128()?;
+            let _length = cur.uleb128()?;
+            files.push(dirs.get(dir).cloned().unwrap_or_default().join(name));
+        }
+        Ok(files)
+    }
+
+    /// DWARF 5: self-describing directory and file entry formats, with 0-based indices
+    fn parse_v5_files(elf: &Elf, cur: &mut Cursor, is_64: bool) -> Result<Vec<PathBuf>> {
+        let mut dirs: Vec<PathBuf> = Self::parse_v5_entries(elf, cur, is_64)?
+            .into_iter()
+            .map(|(path, _)| PathBuf::from(path))
+            .collect();
+        // Directory 0 is the compilation directory; the others may be relative to it
+        if let Some(comp_dir) = dirs.first().cloned() {
+            for dir in dirs.iter_mut().skip(1) {
+                *dir = comp_dir.join(&dir);
+            }
+        }
+        let files = Self::parse_v5_entries(elf, cur, is_64)?
+            .into_iter()
+            .map(|(path, dir)| dirs.get(dir).cloned().unwrap_or_default().join(path))
+            .collect();
+        Ok(files)
+    }
+
+    fn parse_v5_entries(elf: &Elf, cur: &mut Cursor, is_64: bool) -> Result<Vec<(String, usize)>> {
+        let format_count = cur.u8()?;
+        let mut formats = Vec::new();
+        for _ in 0..format_count {
+            formats.push((cur.uleb128()?, cur.uleb128()?));
+        }
+        let count = cur.uleb128()?;
+        let mut out = Vec::new();
+        for _ in 0..count {
+            let mut path = String::new();
+            let mut dir = 0usize;
+            for (content, form) in formats.iter() {
+                match (*content, *form) {
+                    (DW_LNCT_PATH, DW_FORM_STRING) => path = cur.cstr()?.to_string(),
+                    (DW_LNCT_PATH, DW_FORM_LINE_STRP) => {
+                        let off = cur.offset(is_64)?;
+                        path = str_at(elf.section_data_by_name(".debug_line_str").unwrap_or(&[]), off)?.to_string();
+                    },
+                    (DW_LNCT_PATH, DW_FORM_STRP) => {
+                        let off = cur.offset(is_64)?;
+                        path = str_at(elf.section_data_by_name(".debug_str").unwrap_or(&[]), off)?.to_string();
+                    },
+                    (DW_LNCT_DIRECTORY_INDEX, DW_FORM_DATA1) => dir = cur.u8()? as usize,
+                    (DW_LNCT_DIRECTORY_INDEX, DW_FORM_DATA2) => dir = cur.u16()? as usize,
+                    (DW_LNCT_DIRECTORY_INDEX, DW_FORM_UDATA) => dir = cur.uleb128()? as usize,
+                    (_, form) => skip_line_form(cur, form, is_64)?
+                }
+            }
+            out.push((path, dir));
+        }
+        Ok(out)
+    }
+}
+
+fn skip_line_form(cur: &mut Cursor, form: u64, is_64: bool) -> Result<()> {
+    match form {
+        DW_FORM_STRING => { cur.cstr()?; },
+        DW_FORM_LINE_STRP | DW_FORM_STRP | DW_FORM_SEC_OFFSET => { cur.offset(is_64)?; },
+        DW_FORM_UDATA => { cur.uleb128()?; },
+        DW_FORM_DATA1 => cur.skip(1)?,
+        DW_FORM_DATA2 => cur.skip(2)?,
+        DW_FORM_DATA4 => cur.skip(4)?,
+        DW_FORM_DATA8 => cur.skip(8)?,
+        DW_FORM_DATA16 => cur.skip(16)?,
+        DW_FORM_BLOCK => {
+            let len = cur.uleb128()? as usize;
+            cur.skip(len)?;
+        },
+        _ => return error(&format!("unsupported form {:#x} in line table header", form))
+    }
+    Ok(())
+}
+
+/// Every line table in an ELF file's .debug_line section
+#[derive(Debug, Default)]
+pub struct LineInfo {
+    pub tables: Vec<LineTable>
+}
+
+impl LineInfo {
+    /// Reads every line table in the object. `comp_dirs` gives the compilation directory of
+    /// the unit each table belongs to, by the table's offset, which DWARF 4 and earlier leave
+    /// out of the table itself.
+    pub fn parse(elf: &Elf, comp_dirs: &HashMap<u64, PathBuf>) -> Result<Self> {
+        let Some(data) = elf.section_data_by_name(".debug_line") else {
+            return Ok(Self::default());
+        };
+        let mut cur = Cursor::new(data);
+        let mut tables = Vec::new();
+        while !cur.finished() {
+            let comp_dir = comp_dirs.get(&(cur.pos() as u64)).map(PathBuf::as_path);
+            tables.push(LineTable::parse(elf, &mut cur, comp_dir)?);
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.iter().all(|t| t.rows.is_empty())
+    }
+
+    pub fn row_containing(&self, addr: FileAddr) -> Option<(&LineTable, &LineRow)> {
+        self.tables.iter().find_map(|t| t.row_containing(addr).map(|row| (t, row)))
+    }
+
+    /// The address of the first row after the one at `entry`, which is where the body of a
+    /// function starting at `entry` begins once its prologue has run
+    pub fn addr_after_prologue(&self, entry: FileAddr) -> Option<FileAddr> {
+        self.tables.iter().find_map(|t| {
+            let idx = t.rows.iter().position(|r| r.addr == entry && !r.end_sequence)?;
+            t.rows[idx..].iter()
+                .take_while(|r| !r.end_sequence)
+                .find(|r| r.addr > entry)
+                .map(|r| r.addr)
+        })
+    }
+
+    pub fn location(&self, addr: FileAddr) -> Option<SourceLocation> {
+        let (table, row) = self.row_containing(addr)?;
+        Some(SourceLocation {
+            file: table.file(row)?.to_path_buf(),
+            line: row.line
+        })
+    }
+
+    /// Addresses where execution of `line` in `file` begins. `file` may be a bare file name or
+    /// a trailing part of the path. If the line has no code, the next line that does is used.
+    pub fn addrs_for_line(&self, file: &str, line: u64) -> Vec<FileAddr> {
+        let mut candidates: Vec<(u64, FileAddr)> = Vec::new();
+        for table in self.tables.iter() {
+            let mut prev_line = None;
+            for row in table.rows.iter() {
+                let matches_file = table.file(row).is_some_and(|p| p.ends_with(file));
+                if row.end_sequence || !row.is_stmt || !matches_file || row.line < line {
+                    prev_line = None;
+                    continue;
+                }
+                // Only take the first row of each run of rows for the same line
+                if prev_line != Some(row.line) {
+                    candidates.push((row.line, row.addr));
+                }
+                prev_line = Some(row.line);
+            }
+        }
+        let Some(best) = candidates.iter().map(|(l, _)| *l).min() else {
+            return Vec::new();
+        };
+        let mut out: Vec<FileAddr> = candidates.into_iter()
+            .filter(|(l, _)| *l == best)
+            .map(|(_, addr)| addr)
+            .collect();
+        out.sort();
+        out.dedup();
+        out
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{
+    Result,
+    error,
+    Process,
+    FileAddr,
+    VirtAddr
+};
+
+pub const DT_NULL: u64 = 0;
+pub const DT_DEBUG: u64 = 21;
+
+/// Values of `r_debug.r_state`
+pub const RT_CONSISTENT: u32 = 0;
+pub const RT_ADD: u32 = 1;
+pub const RT_DELETE: u32 = 2;
+
+const MAX_DYNAMIC_ENTRIES: usize = 1024;
+const MAX_LINK_MAP_ENTRIES: usize = 4096;
+
+/// The dynamic loader's `struct r_debug`, through which it publishes the objects it has loaded
+#[derive(Clone, Copy, Debug)]
+pub struct RDebug {
+    pub version: i32,
+    /// Head of the link_map list
+    pub map: VirtAddr,
+    /// The function the loader calls before and after changing the list
+    pub brk: VirtAddr,
+    pub state: u32,
+    pub ldbase: VirtAddr
+}
+
+/// An entry in the loader's link_map list
+#[derive(Clone, PartialEq, Debug)]
+pub struct SharedLibrary {
+    /// The path the loader opened the object by; empty for the executable
+    pub name: String,
+    /// Difference between the object's file addresses and where it is loaded
+    pub base: u64,
+    /// Runtime address of the object's dynamic section
+    pub dynamic: VirtAddr
+}
+
+fn read_u64(p: &Process, addr: VirtAddr) -> Result<u64> {
+    let data = p.read_memory_clean(addr, 8)?;
+    Ok(u64::from_le_bytes(data[..].try_into().unwrap()))
+}
+
+fn read_string(p: &Process, addr: VirtAddr) -> Result<String> {
+    let mut out = Vec::new();
+    loop {
+        let chunk = p.read_memory_clean(addr + out.len(), 64)?;
+        match chunk.iter().position(|b| *b == 0) {
+            Some(end) => {
+                out.extend_from_slice(&chunk[..end]);
+                return Ok(String::from_utf8_lossy(&out).into_owned());
+            },
+            None if out.len() > 4096 => return error("unterminated string in link_map"),
+            None => out.extend_from_slice(&chunk)
+        }
+    }
+}
+
+/// The value of the executable's DT_DEBUG entry, which the loader points at `_r_debug` once
+/// it has started
+fn dt_debug(p: &Process) -> Option<VirtAddr> {
+    let exe = p.loaded_objects().first()?;
+    let dynamic = exe.elf.segments.iter().find(|seg| seg.p_type == libc::PT_DYNAMIC)?;
+    let start = exe.to_virt(FileAddr(dynamic.vaddr));
+    for i in 0..MAX_DYNAMIC_ENTRIES {
+        let entry = p.read_memory_clean(start + (i * 16), 16).ok()?;
+        let tag = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+        let val = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+        match tag {
+            DT_NULL => return None,
+            DT_DEBUG if val != 0 => return Some(VirtAddr(val)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Finds the loader's `_r_debug`, through DT_DEBUG if the loader has filled it in and
+/// otherwise through the symbol ld.so exports. Statically linked programs have neither.
+pub fn find_r_debug(p: &Process) -> Option<VirtAddr> {
+    dt_debug(p).or_else(|| p.find_symbol("_r_debug").first().copied())
+}
+
+pub fn read_r_debug(p: &Process, addr: VirtAddr) -> Result<RDebug> {
+    let data = p.read_memory_clean(addr, 40)?;
+    let word = |off: usize| u64::from_le_bytes(data[off..off + 8].try_into().unwrap());
+    Ok(RDebug {
+        version: i32::from_le_bytes(data[0..4].try_into().unwrap()),
+        map: VirtAddr(word(8)),
+        brk: VirtAddr(word(16)),
+        state: u32::from_le_bytes(data[24..28].try_into().unwrap()),
+        ldbase: VirtAddr(word(32))
+    })
+}
+
+/// Walks the link_map list starting at `head`
+pub fn read_link_map(p: &Process, head: VirtAddr) -> Result<Vec<SharedLibrary>> {
+    let mut out = Vec::new();
+    let mut entry = head;
+    while entry.0 != 0 {
+        if out.len() == MAX_LINK_MAP_ENTRIES {
+            return error("link_map list does not terminate");
+        }
+        let name_addr = VirtAddr(read_u64(p, entry + 8u64)?);
+        let name = if name_addr.0 == 0 { String::new() } else { read_string(p, name_addr)? };
+        out.push(SharedLibrary {
+            name,
+            base: read_u64(p, entry)?,
+            dynamic: VirtAddr(read_u64(p, entry + 16u64)?)
+        });
+        entry = VirtAddr(read_u64(p, entry + 24u64)?);
+    }
+    Ok(out)
+}
+
+/// The objects the loader currently has loaded, or None if it has not published any yet
+pub fn shared_libraries(p: &Process) -> Option<Vec<SharedLibrary>> {
+    let r_debug = read_r_debug(p, find_r_debug(p)?).ok()?;
+    if r_debug.map.0 == 0 {
+        return None;
+    }
+    read_link_map(p, r_debug.map).ok()
+}
@@ -1,11 +1,29 @@
 use std::cmp::min;
 
-use gadb::{parse_hex_vec, StopPoint};
+use gadb::{parse_hex_vec, StopPoint, WatchMode};
 use copperline::Copperline;
+use nix::sys::signal::Signal;
 use gadb::{
-    error, parse_float, parse_u64, parse_vec, register_by_name, Process, RValue, RegisterFormat, RegisterType, Result, REGISTER_INFOS,
+    error, parse_location, Location, parse_u64, parse_vec, register_by_id, register_by_name, Process, RegisterId, RValue, RegisterFormat, RegisterType, Result, REGISTER_INFOS,
+    ForkMode,
+    ProcessState,
+    SignalPolicy,
+    parse_signal,
+    parse_syscall,
+    syscall_name,
+    SyscallCatchPolicy,
+    parse_syscall_fault,
+    backtrace,
+    describe_type,
     disassemble,
+    evaluate_address,
+    evaluate_expression,
+    lookup_type,
+    SourceLocation,
+    StackFrame,
     Instruction,
+    StopReason,
+    TrapType,
     VirtAddr
 };
 
@@ -37,10 +55,37 @@ fn print_help(args: &Vec<&str>) {
         println!("Available comamnds:
 
     continue
+    step
+    next
+    finish
+    backtrace
+    frame [n]
+    up [n]
+    down [n]
+    stepi [n]
+    nexti [n]
     memory
+    maps
+    sharedlibrary
+    info libs
+    thread [list|n]
+    inferior [n]
+    follow-fork [parent|child|both]
+    handle [signal [actions]]
+    signal <signal|0>
+    catch syscall [none|name|number ...]
+    trace syscalls [file]
+    trace off
+    inject [syscall [argN==value] [when=n[+[step]]] error=ERRNO|retval=value|argN=value]
+    inject clear <id|all>
+    print <expression>
+    ptype <expression|type>
     register
     breakpoint
-    disassemble");
+    watchpoint
+    disassemble [expression] [n]
+
+Addresses and values can be expressions such as $rsp+0x20 or *(uint64_t*)$rdi");
     } else {
         if "register".starts_with(args[0]) {
             println!("Usage: register (subcommand)
@@ -49,14 +94,26 @@ Available subcommands:
 
     read <register>
     read all
-    write <register> <value>");
+    write <register> <expression>");
         } else if "breakpoint".starts_with(args[0]) {
             println!("Usage: breakpoint (subcommand)
 
 Available subcommands:
 
     list
-    set <addr>
+    set <expression|symbol[+offset]|file:line>
+    enable <addr|id|all>
+    disable <addr|id|all>
+    clear <addr|id|all>
+
+Symbols and lines in libraries that are not loaded yet make a pending breakpoint");
+        } else if "watchpoint".starts_with(args[0]) {
+            println!("Usage: watchpoint (subcommand)
+
+Available subcommands:
+
+    list
+    set <expression> <w|rw|x> <size>
     enable <addr|id>
     disable <addr|id>
     clear <addr|id>");
@@ -65,8 +122,8 @@ Available subcommands:
 
 Available subcommands:
 
-    read <addr> <numbytes>
-    write <addr> <data>");
+    read <expression> [numbytes]
+    write <expression> <data>");
         }
     }
 }
@@ -76,21 +133,21 @@ fn handle_memory_command(p: &mut Process, args: &Vec<&str>) {
         return print_help(args);
     }
     if "read".starts_with(args[1]) {
-        let numbytes: usize = if args.len() == 3 {
-            32
-        } else {
-            match parse_u64(args[3]) {
-                Ok(n) => n as usize,
-                Err(e) => {
-                    println!("{}", e);
-                    return;
-                },
+        // the count is optional, so the whole rest is tried as the address first
+        let (addr, numbytes) = match evaluate_address(p, &args[2..].join(" ")) {
+            Ok(addr) => (addr.0, 32),
+            Err(e) if args.len() == 3 => return println!("{}", e),
+            Err(_) => {
+                let addr = match evaluate_address(p, &args[2..args.len() - 1].join(" ")) {
+                    Ok(addr) => addr.0,
+                    Err(e) => return println!("{}", e)
+                };
+                match parse_u64(args[args.len() - 1]) {
+                    Ok(n) => (addr, n as usize),
+                    Err(e) => return println!("{}", e)
+                }
             }
         };
-        let Ok(addr) = parse_u64(args[2]) else {
-            println!("could not parse address");
-            return;
-        };
 
         let data = p.read_memory(addr.into(), numbytes);
         match data {
@@ -113,17 +170,20 @@ fn handle_memory_command(p: &mut Process, args: &Vec<&str>) {
         }
         return;
     } else if "write".starts_with(args[1]) {
-        let Ok(addr) = parse_u64(args[2]) else {
-            println!("could not parse address");
-            return;
+        if args.len() < 4 {
+            return print_help(args);
+        }
+        let addr = match evaluate_address(p, &args[2..args.len() - 1].join(" ")) {
+            Ok(addr) => addr,
+            Err(e) => return println!("{}", e)
         };
 
-        let bytes = match parse_hex_vec(args[3]) {
+        let bytes = match parse_hex_vec(args[args.len() - 1]) {
             Ok(b) => b,
             Err(e) => return println!("{}", e)
         };
         
-        match p.write_memory(addr.into(), bytes) {
+        match p.write_memory(addr, bytes) {
             Ok(_) => {},
             Err(e) => println!("{}", e)
         }
@@ -135,23 +195,27 @@ fn handle_register_command(p: &mut Process, args: &Vec<&str>) {
         return print_help(args);
     }
     if "read".starts_with(args[1]) {
+        let regs = match p.frame_registers(p.selected_frame()) {
+            Ok(regs) => regs,
+            Err(e) => return println!("{}", e)
+        };
         if args.len() == 2 || "all".starts_with(args[2]) {
             for ri in REGISTER_INFOS.iter() {
                 if ri.rtype != RegisterType::Gpr || ri.dwarf_id == -1 {
                     continue;
                 }
-                let val = p.regs().read(ri);
+                let val = regs.read(ri);
                 println!("{}:\t{}", ri.name, val);
             }
         } else {
             let Ok(ri) = register_by_name(args[2]) else {
                 return println!("Unrecognized register {}", args[2]);
             };
-            let val = p.regs().read(ri);
+            let val = regs.read(ri);
             println!("{}:\t{}", ri.name, val);
         }
     } else if "write".starts_with(args[1]) {
-        if args.len() != 4 {
+        if args.len() < 4 {
             return print_help(args);
         }
         let Ok(ri) = register_by_name(args[2]) else {
@@ -159,30 +223,34 @@ fn handle_register_command(p: &mut Process, args: &Vec<&str>) {
         };
 
         //TODO: move this to parsing?
+        let text = args[3..].join(" ");
         let val = match ri.format {
-            RegisterFormat::Uint => {
-                let Ok(v) = parse_u64(&args[3]) else {
-                    return println!("could not parse value");
+            RegisterFormat::Uint | RegisterFormat::Double => {
+                let v = match evaluate_expression(p, &text) {
+                    Ok(v) => v,
+                    Err(e) => return println!("{}", e)
                 };
-                RValue::from(v, ri)
-            },
-            RegisterFormat::Double => {
-                let Ok(v) = parse_float(&args[3]) else {
-                    return println!("could not parse value");
+                let converted = if ri.format == RegisterFormat::Uint {
+                    v.as_u64().map(|n| RValue::from(n, ri))
+                } else {
+                    v.as_f64().map(|f| RValue::from(f, ri))
                 };
-                RValue::from(v, ri)
+                match converted {
+                    Ok(val) => val,
+                    Err(e) => return println!("{}", e)
+                }
             },
             RegisterFormat::LongDouble => {
                 return println!("not supported yet");
             },
             RegisterFormat::Vector => {
                 if ri.size == 8 {
-                    let Ok(v) = parse_vec::<8>(&args[3]) else {
+                    let Ok(v) = parse_vec::<8>(&text) else {
                         return println!("could not parse value");
                     };
                     RValue::from(v, ri)
                 } else {
-                    let Ok(v) = parse_vec::<16>(&args[3]) else {
+                    let Ok(v) = parse_vec::<16>(&text) else {
                         return println!("could not parse value");
                     };
                     RValue::from(v, ri)
@@ -190,38 +258,37 @@ fn handle_register_command(p: &mut Process, args: &Vec<&str>) {
                 
             },
         };
-        p.write_reg(&val);
+        if let Err(e) = p.write_frame_reg(p.selected_frame(), &val) {
+            println!("{}", e);
+        }
     }
 }
 
 fn handle_disassemble_command(p: &mut Process, args: &Vec<&str>) {
-    let mut addr = p.get_pc();
+    let mut addr = match p.frame_registers(p.selected_frame()) {
+        Ok(regs) => VirtAddr(regs.read_as_id::<u64>(RegisterId::rip)),
+        Err(e) => return println!("{}", e)
+    };
     let mut n_inst: usize = 20;
     if args.len() > 1 {
-        if args.len() == 2 {
-            //TODO: macro for this
-            let val = match parse_u64(args[1]) {
-                Ok(v) => v,
+        // a small lone value is a count, anything else names an address followed by an optional count
+        let (text, count) = match evaluate_address(p, &args[1..].join(" ")) {
+            Ok(val) if args.len() == 2 && val.0 <= 1000 => (None, Some(val.0)),
+            Ok(_) => (Some(args[1..].join(" ")), None),
+            Err(e) if args.len() == 2 => return println!("{}", e),
+            Err(_) => match parse_u64(args[args.len() - 1]) {
+                Ok(n) => (Some(args[1..args.len() - 1].join(" ")), Some(n)),
                 Err(e) => return println!("{}", e)
-            };
-            if val > 1000 {
-                addr = val.into();
-            } else {
-                n_inst = val as usize;
             }
-        } else {
-            addr = if args[2] == "rip" {
-                addr
-            } else {
-                match parse_u64(args[1]) {
-                    Ok(v) => v.into(),
-                    Err(e) => return println!("{}", e),
-                }
-            };
-            n_inst = match parse_u64(args[2]) {
-                Ok(v) => v,
+        };
+        if let Some(text) = text {
+            addr = match evaluate_address(p, &text) {
+                Ok(addr) => addr,
                 Err(e) => return println!("{}", e)
-            } as usize;
+            };
+        }
+        if let Some(n) = count {
+            n_inst = n as usize;
         }
     }
     print_disassembly(p, addr, Some(n_inst));
@@ -237,47 +304,64 @@ fn print_disassembly(p: &mut Process, addr: VirtAddr, n_inst: Option<usize>) {
     }
 }
 fn handle_breakpoint_command(p: &mut Process, args: &Vec<&str>) {
-    let max_id = p.breaksites().iter().map(|b| b.id).max().unwrap_or(0);
+    let max_id = p.breakpoints().iter().map(|b| b.id).max().unwrap_or(0);
     if args.len() == 2 {
         if let Ok(_) = parse_u64(args[1]) {
             return handle_breakpoint_command(p, &vec![args[0], "set", args[1]]);
         }
     }
+    if args.len() < 2 {
+        return print_help(args);
+    }
     if "list".starts_with(args[1]) || "show".starts_with(args[1]) {
-        let mut bs = p.breaksites();
-        if bs.len() == 0 {
+        let bps = p.breakpoints();
+        if bps.is_empty() {
             println!("No breakpoints created");
             return;
         }
-        bs.sort_by_key(|k| k.addr());
-        let top_id = bs.iter().map(|b| b.id).max().unwrap();
-        let len = format!("{}", top_id).len();
+        let len = format!("{}", max_id).len();
         println!("Breakpoints:");
-        for bp in bs {
-            println!("{:>len$}:\t{:#x}", bp.id, bp.addr());
+        for bp in bps {
+            println!("{:>len$}:\t{}\t{}{}", bp.id, bp.location, if bp.enabled { "enabled" } else { "disabled" },
+                if bp.is_pending() { "\tpending" } else { "" });
+            let mut sites: Vec<_> = bp.sites.iter().filter_map(|id| p.breaksite_by_id(*id)).collect();
+            sites.sort_by_key(|k| k.addr());
+            for bs in sites {
+                match p.symbol_at(bs.addr()) {
+                    Some((name, 0)) => println!("{:>len$} \t{:#x} <{}>", "", bs.addr(), name),
+                    Some((name, off)) => println!("{:>len$} \t{:#x} <{}+{:#x}>", "", bs.addr(), name, off),
+                    None => println!("{:>len$} \t{:#x}", "", bs.addr()),
+                }
+            }
         }
     } else if args.len() < 3 {
         return print_help(args);
     } else if "set".starts_with(args[1]) {
-        let Ok(val) = parse_u64(args[2]) else {
-            println!("could not parse address");
-            return;
-        };
-        let res = p.create_breaksite(val.into());
-        let Ok(id) = res else {
-            println!("{}", res.err().unwrap());
-            return;
+        // locations that name a symbol or line may be pending; anything else must evaluate now
+        let text = args[2..].join(" ");
+        let location = match parse_location(&text) {
+            Ok(loc) if !text.starts_with('$') => loc,
+            _ => match evaluate_address(p, &text) {
+                Ok(va) => Location::Address(va.0),
+                Err(e) => return println!("{}", e)
+            }
         };
-        let res = p.enable_breaksite_by(id);
-        let Ok(_) = res else {
-            println!("{}", res.err().unwrap());
-            return;
+        let id = match p.create_breakpoint(location) {
+            Ok(id) => id,
+            Err(e) => return println!("{}", e)
         };
-        println!("created breaksite {}", id);
-    } else if "enable".starts_with(args[1]) || "disable".starts_with(args[1]) {
+        let bp = p.breakpoint_by_id(id).unwrap();
+        match bp.sites.len() {
+            0 => println!("created breakpoint {} on {}, pending until a library defining it is loaded", id, bp.location),
+            1 => println!("created breakpoint {} at {:#x}", id, p.breaksite_by_id(bp.sites[0]).unwrap().addr()),
+            n => println!("created breakpoint {} at {} locations", id, n)
+        }
+    } else if "enable".starts_with(args[1]) || "disable".starts_with(args[1]) || "clear".starts_with(args[1]) {
         let enable = "enable".starts_with(args[1]);
         if args[2] == "all" {
-            if enable {
+            if "clear".starts_with(args[1]) {
+                p.clear_all_breaksites();
+            } else if enable {
                 p.enable_all_breaksites();
             } else {
                 p.disable_all_breaksites();
@@ -289,58 +373,509 @@ fn handle_breakpoint_command(p: &mut Process, args: &Vec<&str>) {
             println!("could not parse address or ID");
             return;
         };
-        let id = {
-            let bs = if val as usize > max_id {
-                p.breaksite_at_va(val.into())
-            } else {
-                p.breaksite_by_id(val as usize)
-            };
-            let Some(bs) = bs else {
-                println!("could not find specified breakpoint");
-                return;
-            };
-            if enable == bs.enabled() {
-                println!("breaksite already {}abled", if enable { "en" } else { "dis" });
-                return;
-            }
-            bs.id
+        let bp = if val as usize > max_id {
+            p.breaksite_at_va(val.into()).and_then(|bs| p.breakpoint_for_site(bs.id))
+        } else {
+            p.breakpoint_by_id(val as usize)
         };
+        let Some(bp) = bp else {
+            println!("could not find specified breakpoint");
+            return;
+        };
+        let id = bp.id;
+        if "clear".starts_with(args[1]) {
+            if let Err(e) = p.clear_breakpoint(id) {
+                println!("{}", e);
+            }
+            return;
+        }
+        if enable == bp.enabled {
+            println!("breakpoint already {}abled", if enable { "en" } else { "dis" });
+            return;
+        }
         if enable {
-            match p.enable_breaksite_by(id) {
+            match p.enable_breakpoint(id) {
                 Err(e) => println!("{}", e),
                 Ok(_) => println!("breakpoint {} enabled", id)
             }
         } else {
-            match p.disable_breaksite_by(id) {
+            match p.disable_breakpoint(id) {
                 Err(e) => println!("{}", e),
                 Ok(_) => println!("breakpoint {} disabled", id),
             }
         }
-    } else if "clear".starts_with(args[1]) {
-        if args[2] == "all" {
-            p.clear_all_breaksites();
+    }
+}
+
+fn handle_watchpoint_command(p: &mut Process, args: &Vec<&str>) {
+    if args.len() < 2 {
+        return print_help(args);
+    }
+    let max_id = p.watchpoints().iter().map(|w| w.id).max().unwrap_or(0);
+    if "list".starts_with(args[1]) || "show".starts_with(args[1]) {
+        let mut wps = p.watchpoints();
+        if wps.is_empty() {
+            println!("No watchpoints created");
             return;
         }
-        let Ok(val) = parse_u64(args[2]) else {
-            println!("could not parse address or ID");
+        wps.sort_by_key(|k| k.addr());
+        let len = format!("{}", max_id).len();
+        println!("Watchpoints:");
+        for wp in wps {
+            println!("{:>len$}:\t{:#x}\t{}\t{}\t{}", wp.id, wp.addr(), wp.mode, wp.size,
+                if wp.enabled() { "enabled" } else { "disabled" });
+        }
+    } else if args.len() < 3 {
+        print_help(args);
+    } else if "set".starts_with(args[1]) {
+        if args.len() < 5 {
+            return print_help(args);
+        }
+        let addr = match evaluate_address(p, &args[2..args.len() - 2].join(" ")) {
+            Ok(addr) => addr,
+            Err(e) => return println!("{}", e)
+        };
+        let mode = match args[args.len() - 2].parse::<WatchMode>() {
+            Ok(m) => m,
+            Err(e) => return println!("{}", e)
+        };
+        let Ok(size) = parse_u64(args[args.len() - 1]) else {
+            println!("could not parse size");
+            return;
+        };
+        let res = p.create_watchpoint(addr, size as usize, mode);
+        let Ok(id) = res else {
+            println!("{}", res.err().unwrap());
             return;
         };
-        let id = {
-            let Some(bs) = (if val > max_id as u64 {
-                p.breaksite_at_va(val.into())
+        if let Err(e) = p.enable_watchpoint(id) {
+            let _ = p.clear_watchpoint(id);
+            println!("{}", e);
+            return;
+        }
+        println!("created watchpoint {}", id);
+    } else if "enable".starts_with(args[1]) || "disable".starts_with(args[1]) || "clear".starts_with(args[1]) {
+        let ids: Vec<usize> = if args[2] == "all" {
+            p.watchpoints().iter().map(|w| w.id).collect()
+        } else {
+            let Ok(val) = parse_u64(args[2]) else {
+                println!("could not parse address or ID");
+                return;
+            };
+            let wp = if val as usize > max_id {
+                p.watchpoint_at_va(val.into())
             } else {
-                p.breaksite_by_id(val as usize)
-            }) else {
-                println!("could not find breakpoint");
+                p.watchpoint_by_id(val as usize)
+            };
+            let Some(wp) = wp else {
+                println!("could not find specified watchpoint");
                 return;
             };
-            bs.id
+            vec![wp.id]
         };
-        let res = p.clear_breaksite(id);
-        if res.is_err() {
-            println!("{}", res.err().unwrap());
+        for id in ids {
+            let (res, verb) = if "enable".starts_with(args[1]) {
+                (p.enable_watchpoint(id), "enabled")
+            } else if "disable".starts_with(args[1]) {
+                (p.disable_watchpoint(id), "disabled")
+            } else {
+                (p.clear_watchpoint(id), "cleared")
+            };
+            match res {
+                Err(e) => println!("{}", e),
+                Ok(_) => println!("watchpoint {} {}", id, verb)
+            }
+        }
+    }
+}
+
+fn print_source(p: &Process, context: u64) -> bool {
+    match p.source_location(p.get_pc()) {
+        Some(loc) => {
+            print_source_lines(&loc, context);
+            true
+        },
+        None => false
+    }
+}
+
+fn print_source_lines(loc: &SourceLocation, context: u64) {
+    let Ok(text) = std::fs::read_to_string(&loc.file) else {
+        println!("{}", loc);
+        return;
+    };
+    println!("{}", loc);
+    let first = loc.line.saturating_sub(context).max(1);
+    for (idx, line) in text.lines().enumerate().skip(first as usize - 1).take((context * 2 + 1) as usize) {
+        let num = idx as u64 + 1;
+        println!("{} {:>4}  {}", if num == loc.line { '>' } else { ' ' }, num, line);
+    }
+}
+
+fn print_stop_reason(p: &mut Process, reason: &StopReason) {
+    for (id, sig) in p.take_noted_signals() {
+        println!("[thread {}] received {}", id, sig);
+    }
+    if let Some(id) = reason.thread().filter(|_| p.threads().len() > 1) {
+        print!("[thread {}] ", id);
+    }
+    match reason.trap() {
+        Some(TrapType::SoftwareBreak(Some(site))) if p.breakpoint_for_site(site).is_some() => {
+            println!("hit breakpoint {} at {:#x}", p.breakpoint_for_site(site).unwrap().id, p.get_pc());
+        },
+        Some(trap @ TrapType::SoftwareBreak(Some(_))) | Some(trap @ TrapType::HardwareBreak(Some(_))) => {
+            println!("hit {} at {:#x}", trap, p.get_pc());
+        },
+        Some(TrapType::SingleStep) => println!("single-stepped to {:#x}", p.get_pc()),
+        Some(TrapType::Fork(child)) => println!("forked child process {} at {:#x}", child, p.get_pc()),
+        Some(TrapType::Syscall) if reason.syscall().is_some() => {
+            println!("{} at {:#x}", reason.syscall().unwrap(), p.get_pc());
+        },
+        _ => println!("{} at {:#x}", reason, p.get_pc()),
+    }
+    if let Some(info) = reason.siginfo().filter(|_| reason.delivered_signal().is_some()) {
+        println!("{}", info);
+        if let (Some(addr), Ok(map)) = (info.addr, p.memory_map()) {
+            println!("{}", map.describe_address(addr));
+        }
+    }
+    if reason.is_signal() && p.state == ProcessState::Stopped && !print_source(p, 2) {
+        print_disassembly(p, p.get_pc(), Some(5));
+    }
+}
+
+fn handle_stepi_command(p: &mut Process, args: &Vec<&str>, over_calls: bool) -> Result<()> {
+    let count = match args.get(1) {
+        Some(n) => parse_u64(n)?,
+        None => 1
+    };
+    for _ in 0..count {
+        let reason = if over_calls {
+            p.step_over_instruction()?
+        } else {
+            p.step_instruction()?
+        };
+        if reason.trap() != Some(TrapType::SingleStep) {
+            print_stop_reason(p, &reason);
+            return Ok(());
+        }
+        print_disassembly(p, p.get_pc(), Some(1));
+    }
+    Ok(())
+}
+
+fn handle_finish_command(p: &mut Process) -> Result<()> {
+    let reason = p.step_out()?;
+    if reason.trap() != Some(TrapType::SingleStep) {
+        print_stop_reason(p, &reason);
+        return Ok(());
+    }
+    println!("returned to {:#x}", p.get_pc());
+    println!("rax:\t{}", p.regs().read(register_by_id(RegisterId::rax)?));
+    println!("xmm0:\t{}", p.regs().read_as_id::<f64>(RegisterId::xmm0));
+    if !print_source(p, 0) {
+        print_disassembly(p, p.get_pc(), Some(1));
+    }
+    Ok(())
+}
+
+fn handle_step_command(p: &mut Process, over_calls: bool) -> Result<()> {
+    let reason = p.step_line(over_calls)?;
+    if reason.trap() != Some(TrapType::SingleStep) {
+        print_stop_reason(p, &reason);
+        return Ok(());
+    }
+    if !print_source(p, 0) {
+        print_disassembly(p, p.get_pc(), Some(1));
+    }
+    Ok(())
+}
+
+fn format_frame(idx: usize, frame: &StackFrame) -> String {
+    let mut line = format!("#{:<3} {:#018x} in ", idx, frame.pc.0);
+    match &frame.symbol {
+        Some((name, 0)) => line.push_str(name),
+        Some((name, off)) => line.push_str(&format!("{}+{}", name, off)),
+        None => line.push_str("??")
+    }
+    if let Some(object) = &frame.object {
+        line.push_str(&format!(" ({})", object));
+    }
+    if let Some(loc) = &frame.location {
+        line.push_str(&format!(" at {}", loc));
+    }
+    line
+}
+
+fn handle_thread_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None => println!("current thread is {} (tid {})", p.current_thread(), p.tid()),
+        Some(sub) if "list".starts_with(sub) => {
+            p.refresh_loaded_objects()?;
+            println!("  {:<4} {:<8} {:<8} location", "id", "tid", "state");
+            for thread in p.threads() {
+                let pc = VirtAddr(thread.regs().read_as_id::<u64>(RegisterId::rip));
+                let mut line = format!("{} {:<4} {:<8} {:<8} {:#018x}",
+                    if thread.id == p.current_thread() { "*" } else { " " }, thread.id, thread.tid, thread.state.to_string(), pc.0);
+                match p.symbol_at(pc) {
+                    Some((name, 0)) => line.push_str(&format!(" in {}", name)),
+                    Some((name, off)) => line.push_str(&format!(" in {}+{}", name, off)),
+                    None => {}
+                }
+                if let Some(loc) = p.source_location(pc) {
+                    line.push_str(&format!(" at {}", loc));
+                }
+                println!("{}", line);
+            }
+        },
+        Some(id) => {
+            p.select_thread(parse_u64(id)? as usize)?;
+            let frame = p.select_frame(0)?;
+            println!("switched to thread {} (tid {})", p.current_thread(), p.tid());
+            println!("{}", format_frame(0, &frame));
         }
     }
+    Ok(())
+}
+
+fn handle_backtrace_command(p: &mut Process) -> Result<()> {
+    p.refresh_loaded_objects()?;
+    for (idx, frame) in backtrace(p)?.iter().enumerate() {
+        println!("{}", format_frame(idx, frame));
+    }
+    Ok(())
+}
+
+fn handle_frame_command(p: &mut Process, args: &Vec<&str>) -> Result<()> {
+    let current = p.selected_frame();
+    let count = match args.get(1) {
+        Some(n) => parse_u64(n)? as usize,
+        None if "frame".starts_with(args[0]) => current,
+        None => 1
+    };
+    let idx = if "up".starts_with(args[0]) {
+        current + count
+    } else if "down".starts_with(args[0]) {
+        let Some(idx) = current.checked_sub(count) else {
+            return error("already at the innermost frame");
+        };
+        idx
+    } else {
+        count
+    };
+    p.refresh_loaded_objects()?;
+    let frame = p.select_frame(idx)?;
+    println!("{}", format_frame(idx, &frame));
+    match &frame.location {
+        Some(loc) => print_source_lines(loc, 0),
+        None => print_disassembly(p, frame.pc, Some(1))
+    }
+    Ok(())
+}
+
+fn handle_print_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    if args.len() < 2 {
+        return error("usage: print <expression>");
+    }
+    p.refresh_loaded_objects()?;
+    let path = args[1..].join(" ");
+    let val = evaluate_expression(p, &path)?;
+    println!("{} = {}", path, val.format(p));
+    Ok(())
+}
+
+fn handle_ptype_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    if args.len() < 2 {
+        return error("usage: ptype <expression|type>");
+    }
+    p.refresh_loaded_objects()?;
+    let name = args[1..].join(" ");
+    let ty = match lookup_type(p, &name) {
+        Ok(ty) => ty,
+        Err(_) => evaluate_expression(p, &name)?.ty
+    };
+    println!("type = {}", describe_type(&ty));
+    Ok(())
+}
+
+fn handle_maps_command(p: &mut Process) -> Result<()> {
+    let map = p.memory_map()?;
+    println!("{:<14} {:<14} perm {:<8} {:<5} {:<8} path", "start", "end", "offset", "dev", "inode");
+    for region in map.regions.iter() {
+        println!("{}", region);
+    }
+    Ok(())
+}
+
+fn handle_sharedlibrary_command(p: &mut Process) -> Result<()> {
+    p.refresh_loaded_objects()?;
+    let libs: Vec<_> = p.shared_libraries().iter().filter(|lib| !lib.name.is_empty()).collect();
+    if libs.is_empty() {
+        println!("No shared libraries loaded at this time.");
+        return Ok(());
+    }
+    println!("{:<18}  {:<18}  path", "base", "dynamic");
+    for lib in libs {
+        println!("{:#018x}  {:#018x}  {}", lib.base, lib.dynamic.0, lib.name);
+    }
+    for object in p.loaded_objects() {
+        for e in object.debug_errors.iter() {
+            println!("warning: could not read {} from {}", e, object.name());
+        }
+    }
+    Ok(())
+}
+
+fn handle_info_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        Some(sub) if "sharedlibrary".starts_with(sub) || *sub == "libs" => handle_sharedlibrary_command(p),
+        _ => error("usage: info sharedlibrary|libs")
+    }
+}
+
+/// gdb's `handle`: shows or changes what happens when the inferior receives a signal
+fn handle_handle_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    let Some(name) = args.get(1) else {
+        println!("{:<10}{:<6}{:<7}pass", "signal", "stop", "print");
+        for sig in Signal::iterator() {
+            println!("{:<10}{}", sig.as_str(), p.signal_policy(sig));
+        }
+        return Ok(());
+    };
+    let sig = parse_signal(name)?;
+    let mut policy: SignalPolicy = p.signal_policy(sig);
+    for keyword in &args[2..] {
+        policy.apply(keyword)?;
+    }
+    p.set_signal_policy(sig, policy);
+    println!("{:<10}{:<6}{:<7}pass", "signal", "stop", "print");
+    println!("{:<10}{}", sig.as_str(), policy);
+    Ok(())
+}
+
+/// Continues, delivering the given signal to the current thread instead of the one it
+/// stopped with. `signal 0` continues without any.
+fn handle_signal_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    let Some(name) = args.get(1) else {
+        return error("usage: signal <signal|0>");
+    };
+    let sig = match *name {
+        "0" => None,
+        name => Some(parse_signal(name)?)
+    };
+    p.resume_with_signal(sig)?;
+    let reason = p.wait_on_signal()?;
+    print_stop_reason(p, &reason);
+    Ok(())
+}
+
+/// `catch syscall` stops at every syscall, `catch syscall a b` at those listed, and
+/// `catch syscall none` at none of them
+fn handle_catch_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None => {
+            match p.syscall_catch_policy() {
+                SyscallCatchPolicy::None => println!("not catching syscalls"),
+                SyscallCatchPolicy::All => println!("catching all syscalls"),
+                SyscallCatchPolicy::Some(ids) => {
+                    let names: Vec<String> = ids.iter()
+                        .map(|id| syscall_name(*id).map(String::from).unwrap_or_else(|| id.to_string()))
+                        .collect();
+                    println!("catching syscalls: {}", names.join(" "));
+                }
+            }
+            Ok(())
+        },
+        Some(sub) if "syscall".starts_with(sub) => {
+            let policy = match &args[2..] {
+                [] => SyscallCatchPolicy::All,
+                ["none"] => SyscallCatchPolicy::None,
+                names => SyscallCatchPolicy::Some(names.iter().map(|n| parse_syscall(n)).collect::<Result<Vec<u64>>>()?)
+            };
+            p.set_syscall_catch_policy(policy);
+            Ok(())
+        },
+        _ => error("usage: catch syscall [none|name|number ...]")
+    }
+}
+
+/// `trace syscalls` prints every syscall the inferior makes as it returns, strace style,
+/// without stopping; `trace syscalls file` writes them to a file instead
+fn handle_trace_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None if p.is_tracing_syscalls() => println!("tracing syscalls"),
+        None => println!("not tracing syscalls"),
+        Some(&"off") => p.stop_tracing_syscalls(),
+        Some(sub) if "syscalls".starts_with(sub) => {
+            let output: Box<dyn std::io::Write> = match args.get(2) {
+                Some(path) => match std::fs::File::create(path) {
+                    Ok(file) => Box::new(file),
+                    Err(e) => return error(&format!("could not open {}: {}", path, e))
+                },
+                None => Box::new(std::io::stdout())
+            };
+            p.trace_syscalls(Some(output));
+        },
+        _ => return error("usage: trace syscalls [file] | trace off")
+    }
+    Ok(())
+}
+
+/// Lists the rules for tampering with syscalls, adds one, such as
+/// `inject write when=3+3 error=EINTR` to fail every third write, or clears them
+fn handle_inject_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None if p.syscall_faults().is_empty() => println!("no syscall fault rules"),
+        None => {
+            for (id, fault) in p.syscall_faults() {
+                println!("{}: {} (matched {} calls)", id, fault, fault.matched);
+            }
+        },
+        Some(&"clear") => match args.get(2) {
+            Some(&"all") => {
+                let ids: Vec<usize> = p.syscall_faults().keys().copied().collect();
+                for id in ids {
+                    p.remove_syscall_fault(id)?;
+                }
+            },
+            Some(id) => p.remove_syscall_fault(parse_u64(id)? as usize)?,
+            None => return error("usage: inject clear <id|all>")
+        },
+        Some(_) => {
+            let id = p.add_syscall_fault(parse_syscall_fault(&args[1..])?);
+            println!("added syscall fault rule {}", id);
+        }
+    }
+    Ok(())
+}
+
+fn handle_follow_fork_command(p: &mut Process, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None => println!("following {} on fork", p.follow_fork),
+        Some(mode) => p.follow_fork = mode.parse::<ForkMode>()?
+    }
+    Ok(())
+}
+
+/// Lists the processes under control, or switches to one of them. There is more than one
+/// once a fork has been followed in both directions.
+fn handle_inferior_command(inferiors: &[Process], current: &mut usize, args: &[&str]) -> Result<()> {
+    match args.get(1) {
+        None => {
+            for (idx, p) in inferiors.iter().enumerate() {
+                println!("{} {:<4} {:<8} {}", if idx == *current { "*" } else { " " }, idx + 1, p.pid, p.state);
+            }
+        },
+        Some(n) => {
+            let idx = parse_u64(n)? as usize;
+            if idx == 0 || idx > inferiors.len() {
+                return error(&format!("no inferior {}", n));
+            }
+            *current = idx - 1;
+            println!("switched to inferior {} (pid {})", idx, inferiors[*current].pid);
+        }
+    }
+    Ok(())
 }
 
 fn handle_command(p: &mut Process, cmd: &str) -> Result<()> {
@@ -352,32 +887,69 @@ fn handle_command(p: &mut Process, cmd: &str) -> Result<()> {
     };
     if "continue".starts_with(command) {
         p.resume()?;
-        let reason = p.wait_on_signal();
-        if let Ok(reason) = reason {
-            println!("{} at {:#x}", &reason, p.get_pc());
-            if reason.is_signal() {
-                print_disassembly(p, p.get_pc(), Some(5));
-            }
-        } else {
-            return Err(reason.err().unwrap());
-        }
+        let reason = p.wait_on_signal()?;
+        print_stop_reason(p, &reason);
+    } else if *command == "stepi" || *command == "si" {
+        handle_stepi_command(p, &args, false)?;
+    } else if *command == "nexti" || *command == "ni" {
+        handle_stepi_command(p, &args, true)?;
+    } else if "step".starts_with(command) {
+        handle_step_command(p, false)?;
+    } else if "next".starts_with(command) {
+        handle_step_command(p, true)?;
+    } else if "finish".starts_with(command) {
+        handle_finish_command(p)?;
+    } else if *command == "backtrace" || *command == "bt" {
+        handle_backtrace_command(p)?;
+    } else if "up".starts_with(command) || "frame".starts_with(command) {
+        handle_frame_command(p, &args)?;
     } else if "help".starts_with(command) {
         print_help(&args);
     } else if "registers".starts_with(command) {
         handle_register_command(p, &args);
     } else if "breakpoint".starts_with(command) {
         handle_breakpoint_command(p, &args);
+    } else if "watchpoint".starts_with(command) {
+        handle_watchpoint_command(p, &args);
+    } else if *command == "maps" {
+        handle_maps_command(p)?;
+    } else if *command == "sharedlibrary" {
+        handle_sharedlibrary_command(p)?;
+    } else if *command == "thread" {
+        handle_thread_command(p, &args)?;
+    } else if *command == "handle" {
+        handle_handle_command(p, &args)?;
+    } else if *command == "signal" {
+        handle_signal_command(p, &args)?;
+    } else if *command == "catch" {
+        handle_catch_command(p, &args)?;
+    } else if *command == "inject" {
+        handle_inject_command(p, &args)?;
+    } else if *command == "trace" {
+        handle_trace_command(p, &args)?;
+    } else if *command == "follow-fork" {
+        handle_follow_fork_command(p, &args)?;
+    } else if *command == "info" {
+        handle_info_command(p, &args)?;
     } else if "memory".starts_with(command) {
         handle_memory_command(p, &args);
+    } else if *command == "ptype" {
+        handle_ptype_command(p, &args)?;
+    } else if "print".starts_with(command) {
+        handle_print_command(p, &args)?;
     } else if "disassemble".starts_with(command) {
         handle_disassemble_command(p, &args);
+    } else if "down".starts_with(command) {
+        handle_frame_command(p, &args)?;
     } else {
         return error(&format!("unrecognized command: {}", command));
     }
     Ok(())
 }
 
-fn main_loop(mut p: Process, mut cl: Copperline) {
+fn main_loop(p: Process, mut cl: Copperline) {
+    let mut inferiors = vec![p];
+    let mut current = 0;
     loop {
         let line = cl.read_line_ascii("gadb> ");
         let Ok(line) = line else {
@@ -396,10 +968,19 @@ fn main_loop(mut p: Process, mut cl: Copperline) {
             cl.add_history(line.clone());
         }
         if !exec_line.is_empty() {
-            let res = handle_command(&mut p, exec_line);
+            let res = if exec_line.split(' ').next() == Some("inferior") {
+                handle_inferior_command(&inferiors, &mut current, &exec_line.split(' ').collect::<Vec<_>>())
+            } else {
+                handle_command(&mut inferiors[current], exec_line)
+            };
             if res.is_err() {
                 println!("{}", res.err().unwrap());
             }
+            let forked = inferiors[current].take_forked();
+            for child in forked {
+                inferiors.push(child);
+                println!("added inferior {} (pid {})", inferiors.len(), inferiors.last().unwrap().pid);
+            }
         }
     }
 }
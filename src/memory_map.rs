@@ -0,0 +1,163 @@
+use crate::{
+    Result,
+    error,
+    VirtAddr
+};
+
+use nix::unistd::Pid;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+    pub shared: bool
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}{}{}",
+            if self.read { 'r' } else { '-' },
+            if self.write { 'w' } else { '-' },
+            if self.execute { 'x' } else { '-' },
+            if self.shared { 's' } else { 'p' })
+    }
+}
+
+/// One line of /proc/<pid>/maps
+#[derive(PartialEq, Clone, Debug)]
+pub struct MemoryRegion {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub perms: Permissions,
+    pub offset: u64,
+    pub device: String,
+    pub inode: u64,
+    pub path: Option<String>
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, va: VirtAddr) -> bool {
+        va >= self.start && va < self.end
+    }
+
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+
+    fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split_whitespace();
+        let (Some(range), Some(perms), Some(offset), Some(device), Some(inode)) =
+            (fields.next(), fields.next(), fields.next(), fields.next(), fields.next()) else {
+            return error(&format!("could not parse memory map line: {}", line));
+        };
+        let path: Vec<&str> = fields.collect();
+        let Some((start, end)) = range.split_once('-') else {
+            return error(&format!("could not parse memory range {}", range));
+        };
+        let perms: Vec<char> = perms.chars().collect();
+        if perms.len() != 4 {
+            return error(&format!("could not parse memory permissions in: {}", line));
+        }
+        let (Ok(start), Ok(end), Ok(offset), Ok(inode)) = (
+            u64::from_str_radix(start, 16),
+            u64::from_str_radix(end, 16),
+            u64::from_str_radix(offset, 16),
+            inode.parse::<u64>()
+        ) else {
+            return error(&format!("could not parse memory map line: {}", line));
+        };
+        Ok(Self {
+            start: start.into(),
+            end: end.into(),
+            perms: Permissions {
+                read: perms[0] == 'r',
+                write: perms[1] == 'w',
+                execute: perms[2] == 'x',
+                shared: perms[3] == 's'
+            },
+            offset,
+            device: device.to_string(),
+            inode,
+            path: if path.is_empty() { None } else { Some(path.join(" ")) }
+        })
+    }
+}
+
+impl std::fmt::Display for MemoryRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#014x}-{:#014x} {} {:08x} {} {:<8} {}",
+            self.start, self.end, self.perms, self.offset, self.device, self.inode,
+            self.path.as_deref().unwrap_or(""))
+    }
+}
+
+/// A snapshot of the inferior's address space
+#[derive(Clone, Debug)]
+pub struct MemoryMap {
+    pub regions: Vec<MemoryRegion>
+}
+
+impl MemoryMap {
+    pub fn read(pid: Pid) -> Result<Self> {
+        let Ok(data) = std::fs::read_to_string(format!("/proc/{}/maps", pid)) else {
+            return error("could not read process memory map");
+        };
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let regions = data.lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(MemoryRegion::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { regions })
+    }
+
+    pub fn region_containing(&self, va: VirtAddr) -> Option<&MemoryRegion> {
+        self.regions.iter().find(|r| r.contains(va))
+    }
+
+    /// Says where an address falls, such as for a faulting address: in which mapping and
+    /// with what permissions, or if it is unmapped, what it is closest to
+    pub fn describe_address(&self, va: VirtAddr) -> String {
+        let name = |r: &MemoryRegion| r.path.clone().unwrap_or_else(|| "an anonymous mapping".to_string());
+        if let Some(region) = self.region_containing(va) {
+            return format!("address {:#x} is in {} ({:#x}-{:#x} {})", va, name(region), region.start, region.end, region.perms);
+        }
+        let below = self.regions.iter().filter(|r| r.end <= va).max_by_key(|r| r.end);
+        let above = self.regions.iter().filter(|r| r.start > va).min_by_key(|r| r.start);
+        match (below, above) {
+            // within a page of a mapping is most likely running off the end of it, or for
+            // the stack, overflowing into its guard gap
+            (_, Some(above)) if above.start - va <= 0x1000 => {
+                format!("address {:#x} is not mapped, {:#x} bytes below {}", va, above.start - va, name(above))
+            },
+            (Some(below), _) if va - below.end <= 0x1000 => {
+                format!("address {:#x} is not mapped, {:#x} bytes past the end of {}", va, va - below.end, name(below))
+            },
+            _ => format!("address {:#x} is not mapped", va)
+        }
+    }
+
+    /// Explains why [start, start+len) can't be read or written, if some part of it is
+    /// unmapped or lacks the needed permission
+    pub fn check_access(&self, start: VirtAddr, len: usize, write: bool) -> Result<()> {
+        // a range running past the top of the address space stops at the top
+        let end = VirtAddr(start.0.saturating_add(len as u64));
+        let mut cur = start;
+        while cur < end {
+            let Some(region) = self.region_containing(cur) else {
+                return error(&format!("address {:#x} not mapped", cur));
+            };
+            if write && !region.perms.write {
+                return error(&format!("region {:#x}-{:#x} not writable", region.start, region.end));
+            }
+            if !write && !region.perms.read {
+                return error(&format!("region {:#x}-{:#x} not readable", region.start, region.end));
+            }
+            cur = region.end;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{
+    Result,
+    error,
+    FileAddr,
+    VirtAddr,
+    elf::{Elf, Symbol},
+    memory_map::MemoryMap,
+    line_table::{LineInfo, SourceLocation},
+    unwind::CallFrameInfo,
+    debug_info::DebugInfo
+};
+
+use nix::unistd::Pid;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// The parsed section, or nothing with the reason noted in `errors`
+fn or_empty<T: Default>(res: Result<T>, section: &str, errors: &mut Vec<String>) -> T {
+    res.unwrap_or_else(|e| {
+        errors.push(format!("{}: {}", section, e));
+        T::default()
+    })
+}
+
+/// An ELF file mapped into the inferior, along with the bias it was loaded at
+#[derive(Debug)]
+pub struct LoadedObject {
+    pub elf: Elf,
+    pub load_bias: u64,
+    pub lines: LineInfo,
+    pub frames: CallFrameInfo,
+    pub debug_info: DebugInfo,
+    /// Why any of the debug sections could not be read; those are left empty
+    pub debug_errors: Vec<String>
+}
+
+impl LoadedObject {
+    pub fn new(elf: Elf, load_bias: u64) -> Self {
+        let mut debug_errors = Vec::new();
+        let debug_info = or_empty(DebugInfo::parse(&elf), ".debug_info", &mut debug_errors);
+        let lines = or_empty(LineInfo::parse(&elf, &debug_info.comp_dirs()), ".debug_line", &mut debug_errors);
+        let frames = or_empty(CallFrameInfo::parse(&elf), "call frame information", &mut debug_errors);
+        Self {
+            elf,
+            load_bias,
+            lines,
+            frames,
+            debug_info,
+            debug_errors
+        }
+    }
+
+    /// Builds a LoadedObject from the address the file's offset-0 mapping starts at
+    pub fn from_mapping(elf: Elf, map_start: u64) -> Self {
+        let first_load = elf.segments.iter()
+            .filter(|seg| seg.p_type == libc::PT_LOAD)
+            .map(|seg| seg.vaddr & !(PAGE_SIZE - 1))
+            .min()
+            .unwrap_or(0);
+        Self::new(elf, map_start.wrapping_sub(first_load))
+    }
+
+    pub fn to_virt(&self, addr: FileAddr) -> VirtAddr {
+        addr.to_virt(self.load_bias)
+    }
+
+    pub fn to_file(&self, va: VirtAddr) -> FileAddr {
+        va.to_file(self.load_bias)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.elf.path
+    }
+
+    pub fn name(&self) -> String {
+        self.elf.path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.elf.path.display().to_string())
+    }
+
+    /// Runtime addresses of every defined symbol with the given name
+    pub fn symbol_addrs(&self, name: &str) -> Vec<VirtAddr> {
+        let mut out: Vec<VirtAddr> = self.elf.symbols_by_name(name).iter()
+            .filter(|sym| sym.shndx != 0 && sym.value != 0)
+            .map(|sym| self.to_virt(FileAddr(sym.value)))
+            .collect();
+        out.dedup();
+        out
+    }
+
+    pub fn contains(&self, va: VirtAddr) -> bool {
+        self.elf.segments.iter()
+            .filter(|seg| seg.p_type == libc::PT_LOAD)
+            .any(|seg| {
+                let addr = self.to_file(va).0;
+                addr >= seg.vaddr && addr < seg.vaddr + seg.memsz
+            })
+    }
+
+    pub fn source_location(&self, va: VirtAddr) -> Option<SourceLocation> {
+        self.lines.location(self.to_file(va))
+    }
+
+    /// The source location of the line-table row covering `va`, along with the address that row starts at
+    pub fn line_entry(&self, va: VirtAddr) -> Option<(VirtAddr, SourceLocation)> {
+        let (table, row) = self.lines.row_containing(self.to_file(va))?;
+        Some((self.to_virt(row.addr), SourceLocation {
+            file: table.file(row)?.to_path_buf(),
+            line: row.line
+        }))
+    }
+
+    pub fn addr_after_prologue(&self, entry: VirtAddr) -> Option<VirtAddr> {
+        self.lines.addr_after_prologue(self.to_file(entry)).map(|addr| self.to_virt(addr))
+    }
+
+    pub fn addrs_for_line(&self, file: &str, line: u64) -> Vec<VirtAddr> {
+        self.lines.addrs_for_line(file, line).into_iter().map(|addr| self.to_virt(addr)).collect()
+    }
+
+    /// The symbol covering a runtime address, and the offset of the address into it
+    pub fn symbol_at(&self, va: VirtAddr) -> Option<(&Symbol, u64)> {
+        let file_addr = self.to_file(va).0;
+        self.elf.symbol_containing_addr(file_addr).map(|sym| (sym, file_addr - sym.value))
+    }
+}
+
+/// Every ELF file in the memory map, with the address its offset-0 mapping starts at.
+/// Other mapped files, like locale-archive, are left out without being read.
+pub(crate) fn mapped_files(map: &MemoryMap) -> Vec<(PathBuf, u64)> {
+    let mut out: Vec<(PathBuf, u64)> = Vec::new();
+    for region in map.regions.iter().filter(|r| r.offset == 0) {
+        let Some(path) = region.path.as_ref().filter(|p| p.starts_with('/')) else {
+            continue;
+        };
+        let path = PathBuf::from(path);
+        if !out.iter().any(|(p, _)| *p == path) && Elf::is_elf_file(&path) {
+            out.push((path, region.start.0));
+        }
+    }
+    out
+}
+
+pub const AT_PHDR: u64 = 3;
+pub const AT_ENTRY: u64 = 9;
+
+/// Reads the auxiliary vector the kernel handed the process at exec
+pub(crate) fn read_auxv(pid: Pid) -> Result<HashMap<u64, u64>> {
+    let Ok(data) = std::fs::read(format!("/proc/{}/auxv", pid)) else {
+        return error("could not read process auxv");
+    };
+    let mut out = HashMap::new();
+    for entry in data.chunks_exact(16) {
+        let key = u64::from_le_bytes(entry[0..8].try_into().unwrap());
+        let val = u64::from_le_bytes(entry[8..16].try_into().unwrap());
+        if key == 0 {
+            break;
+        }
+        out.insert(key, val);
+    }
+    Ok(out)
+}
+
+/// Works out the executable's load bias from AT_ENTRY, falling back to AT_PHDR
+pub(crate) fn exe_load_bias(elf: &Elf, auxv: &HashMap<u64, u64>) -> Result<u64> {
+    if let Some(entry) = auxv.get(&AT_ENTRY) {
+        return Ok(entry.wrapping_sub(elf.header.e_entry));
+    }
+    let phdr_vaddr = elf.segments.iter()
+        .find(|seg| seg.p_type == libc::PT_PHDR)
+        .map(|seg| seg.vaddr);
+    match (auxv.get(&AT_PHDR), phdr_vaddr) {
+        (Some(phdr), Some(vaddr)) => Ok(phdr.wrapping_sub(vaddr)),
+        _ => error("could not determine executable load bias")
+    }
+}
@@ -98,4 +98,42 @@ fn parse_digit(ch: char, base: u64) -> Result<u64> {
         }
     }
     return error("could not parse digit");
-}
\ No newline at end of file
+}
+
+/// Where a breakpoint should go: a raw address, a symbol plus an offset, or a source line
+#[derive(PartialEq, Debug, Clone)]
+pub enum Location {
+    Address(u64),
+    Symbol(String, u64),
+    Line(String, u64)
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Address(addr) => write!(f, "{:#x}", addr),
+            Location::Symbol(name, 0) => write!(f, "{}", name),
+            Location::Symbol(name, offset) => write!(f, "{}+{:#x}", name, offset),
+            Location::Line(file, line) => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+pub fn parse_location(s: &str) -> Result<Location> {
+    if let Ok(addr) = parse_u64(s) {
+        return Ok(Location::Address(addr));
+    }
+    if let Some((file, line)) = s.rsplit_once(':') {
+        if let (false, Ok(line)) = (file.is_empty(), parse_u64(line)) {
+            return Ok(Location::Line(file.to_string(), line));
+        }
+    }
+    let (name, offset) = match s.split_once('+') {
+        Some((name, offset)) => (name, parse_u64(offset)?),
+        None => (s, 0)
+    };
+    if name.is_empty() || !name.chars().all(|ch| ch.is_alphanumeric() || "_.@$".contains(ch)) {
+        return error(&format!("could not parse location {}", s));
+    }
+    Ok(Location::Symbol(name.to_string(), offset))
+}
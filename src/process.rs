@@ -1,12 +1,26 @@
-use crate::breakpoints::{BreakSite, VirtAddr, StopPoint};
+use crate::breakpoints::{Breakpoint, BreakSite, FileAddr, VirtAddr, StopPoint, Watchpoint, WatchMode};
 use crate::{
     Result,
+    GadbErr,
     error,
     error_os,
     os_error_with_prefix,
     registers::*,
     register_info::*,
-    pipe::Pipe
+    pipe::Pipe,
+    disassembler::disassemble,
+    unwind::{backtrace, current_cfa, StackFrame},
+    elf::Elf,
+    objects::{LoadedObject, mapped_files, read_auxv, exe_load_bias},
+    loader::{SharedLibrary, RT_CONSISTENT, find_r_debug, read_r_debug, shared_libraries},
+    memory_map::MemoryMap,
+    threads::{Thread, task_ids, thread_group},
+    signals::{SignalPolicy, SignalInfo, default_signal_policy},
+    syscalls::{SyscallCatchPolicy, SyscallData, SyscallInfo},
+    syscall_trace::{SyscallTrace, SyscallRecord},
+    syscall_faults::{SyscallFault, FaultAction},
+    parsing::Location,
+    line_table::SourceLocation
 };
 
 use nix::{
@@ -26,13 +40,44 @@ use libc::{
 };
 
 use std::cmp::min;
-use std::collections::HashMap;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
 use std::ffi::CString;
-use std::io::IoSliceMut;
+use std::path::PathBuf;
+use std::io::{IoSliceMut, Write};
 use std::process::exit;
 
 const INT3: u8 = 0xcc;
 
+thread_local! {
+    /// Wait statuses collected by one Process that belong to another traced from the same
+    /// thread, such as a child that has just been forked
+    static UNCLAIMED: RefCell<Vec<wait::WaitStatus>> = const { RefCell::new(Vec::new()) };
+}
+
+/// waitpid for one particular task, taking its status from those set aside if it is there
+fn wait_task(tid: Pid) -> Result<wait::WaitStatus> {
+    let claimed = UNCLAIMED.with_borrow_mut(|statuses| {
+        let idx = statuses.iter().position(|s| s.pid() == Some(tid))?;
+        Some(statuses.remove(idx))
+    });
+    if let Some(status) = claimed {
+        return Ok(status);
+    }
+    match wait::waitpid(tid, Some(wait::WaitPidFlag::__WALL)) {
+        Ok(status) => Ok(status),
+        Err(_) => error_os("could not wait on task")
+    }
+}
+
+fn tgkill(pid: Pid, tid: Pid, sig: signal::Signal) -> Result<()> {
+    let res = unsafe { libc::syscall(libc::SYS_tgkill, pid.as_raw(), tid.as_raw(), sig as i32) };
+    if res < 0 {
+        return error_os("could not send signal to thread");
+    }
+    Ok(())
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub enum ProcessState {
     Stopped,
@@ -71,6 +116,29 @@ impl StopReason {
             _ => false
         }
     }
+    pub fn trap(&self) -> Option<TrapType> {
+        self.trap
+    }
+    pub fn thread(&self) -> Option<usize> {
+        self.thread
+    }
+    /// The syscall and its arguments or result, for syscall stops
+    pub fn syscall(&self) -> Option<SyscallInfo> {
+        self.syscall
+    }
+    /// What the kernel reported about the signal the inferior stopped with, such as the
+    /// address that faulted or who sent it
+    pub fn siginfo(&self) -> Option<SignalInfo> {
+        self.siginfo
+    }
+    /// The signal the inferior stopped to receive, as opposed to the SIGTRAPs ptrace
+    /// itself stops it with
+    pub fn delivered_signal(&self) -> Option<signal::Signal> {
+        match (&self.newstate, &self.info) {
+            (ProcessState::Stopped, StopInfo::Signal(sig)) if *sig != signal::Signal::SIGTRAP => Some(*sig),
+            _ => None
+        }
+    }
 }
 
 impl std::fmt::Display for StopInfo {
@@ -82,10 +150,43 @@ impl std::fmt::Display for StopInfo {
     }
 }
 
+/// What caused a SIGTRAP stop, along with the id of the stop point that fired, if any
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TrapType {
+    SoftwareBreak(Option<usize>),
+    HardwareBreak(Option<usize>),
+    /// A single step, or a stepping operation built on top of one, has completed
+    SingleStep,
+    Syscall,
+    /// The process forked while following both sides; the child is waiting in take_forked
+    Fork(Pid),
+    Unknown
+}
+
+impl std::fmt::Display for TrapType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TrapType::SoftwareBreak(Some(id)) => write!(f, "breakpoint {}", id),
+            TrapType::SoftwareBreak(None) => write!(f, "breakpoint"),
+            TrapType::HardwareBreak(Some(id)) => write!(f, "watchpoint {}", id),
+            TrapType::HardwareBreak(None) => write!(f, "hardware breakpoint"),
+            TrapType::SingleStep => write!(f, "single step"),
+            TrapType::Syscall => write!(f, "syscall"),
+            TrapType::Fork(child) => write!(f, "fork of child {}", child),
+            TrapType::Unknown => write!(f, "unknown trap"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StopReason {
     newstate: ProcessState,
-    info: StopInfo
+    info: StopInfo,
+    trap: Option<TrapType>,
+    /// Id of the thread the stop happened in
+    thread: Option<usize>,
+    syscall: Option<SyscallInfo>,
+    siginfo: Option<SignalInfo>
 }
 
 impl StopReason {
@@ -94,19 +195,41 @@ impl StopReason {
             wait::WaitStatus::Exited(_, code) => {
                 Self {
                     newstate: ProcessState::Exited,
-                    info: StopInfo::ExitCode(code)
+                    info: StopInfo::ExitCode(code),
+                    trap: None,
+                    thread: None,
+                    syscall: None,
+                    siginfo: None
                 }
             },
             wait::WaitStatus::Signaled(_, signal, _) => {
                 Self {
                     newstate: ProcessState::Terminated,
-                    info: StopInfo::Signal(signal)
+                    info: StopInfo::Signal(signal),
+                    trap: None,
+                    thread: None,
+                    syscall: None,
+                    siginfo: None
                 }
             },
-            wait::WaitStatus::Stopped(_, signal) => {
+            wait::WaitStatus::PtraceSyscall(_) => {
                 Self {
                     newstate: ProcessState::Stopped,
-                    info: StopInfo::Signal(signal)
+                    info: StopInfo::Signal(signal::Signal::SIGTRAP),
+                    trap: Some(TrapType::Syscall),
+                    thread: None,
+                    syscall: None,
+                    siginfo: None
+                }
+            },
+            wait::WaitStatus::Stopped(_, signal) | wait::WaitStatus::PtraceEvent(_, signal, _) => {
+                Self {
+                    newstate: ProcessState::Stopped,
+                    info: StopInfo::Signal(signal),
+                    trap: None,
+                    thread: None,
+                    syscall: None,
+                    siginfo: None
                 }
             },
             _ => { panic!("unknown status: {:?}", status) }
@@ -125,15 +248,85 @@ impl std::fmt::Display for StopReason {
     }
 }
 
+/// Which side of a fork or vfork gadb stays attached to
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ForkMode {
+    /// Keep debugging the parent and let the child run free
+    Parent,
+    /// Switch to the child and let the parent run free
+    Child,
+    /// Keep debugging the parent and hand the child out as a new Process
+    Both
+}
+
+impl std::str::FromStr for ForkMode {
+    type Err = GadbErr;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "parent" => Ok(ForkMode::Parent),
+            "child" => Ok(ForkMode::Child),
+            "both" => Ok(ForkMode::Both),
+            _ => error("fork mode must be parent, child or both")
+        }
+    }
+}
+
+impl std::fmt::Display for ForkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ForkMode::Parent => write!(f, "parent"),
+            ForkMode::Child => write!(f, "child"),
+            ForkMode::Both => write!(f, "both")
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Process {
     pub pid: Pid,
     pub autoterm: bool,
     pub attached: bool,
     pub state: ProcessState,
-    registers: Registers,
+    pub follow_fork: ForkMode,
+    /// Every thread of the inferior, keyed by its user-visible id
+    threads: BTreeMap<usize, Thread>,
+    /// The thread that stopped last, or that the user switched to; registers, stepping and
+    /// the backtrace all refer to it
+    current_thread: usize,
+    next_thread_id: usize,
     breaksites: HashMap::<usize, BreakSite>,
-    next_breaksite_id: usize
+    next_breaksite_id: usize,
+    next_internal_breaksite_id: usize,
+    breakpoints: HashMap<usize, Breakpoint>,
+    next_breakpoint_id: usize,
+    watchpoints: HashMap::<usize, Watchpoint>,
+    next_watchpoint_id: usize,
+    /// The executable followed by every shared object mapped into the inferior
+    objects: Vec<LoadedObject>,
+    /// The loader's link_map list as of the last time it was consistent
+    libraries: Vec<SharedLibrary>,
+    /// Internal breaksite on the loader's r_brk, hit whenever objects are loaded or unloaded
+    loader_breaksite: Option<usize>,
+    /// Index into the backtrace of the frame that register commands apply to
+    selected_frame: usize,
+    /// Children kept under control in ForkMode::Both that have not been taken yet
+    forked: Vec<Process>,
+    /// Breaksites lifted from the memory a vfork child shares with us, put back once the
+    /// parent reports that the child has let go of it
+    vfork_parked: Vec<usize>,
+    /// Signals whose handling the user has changed from the defaults
+    signal_policies: HashMap<signal::Signal, SignalPolicy>,
+    /// Signals that arrived without stopping but that the user wants to hear about
+    noted_signals: Vec<(usize, signal::Signal)>,
+    /// A single step is in progress, so signals that do not stop are left for the step to
+    /// deliver rather than resuming every thread
+    stepping: bool,
+    syscall_catch_policy: SyscallCatchPolicy,
+    syscall_trace: Option<SyscallTrace>,
+    /// Rules for tampering with syscalls, keyed by the id the user refers to them by
+    syscall_faults: BTreeMap<usize, SyscallFault>,
+    next_syscall_fault_id: usize
 }
 
 trait BreakSiteId {
@@ -185,8 +378,9 @@ impl Process {
         return Self::launch_args(cmd, Vec::new(), false, None);
     }
 
+    /// User-visible breaksites; internal ones used for stepping are left out
     pub fn breaksites(&self) -> Vec::<&BreakSite> {
-        self.breaksites.values().collect()
+        self.breaksites.values().filter(|bs| !bs.internal).collect()
     }
 
     pub fn breaksite_by_id(&self, id: usize) -> Option<&BreakSite> {
@@ -220,11 +414,11 @@ impl Process {
     }
 
     pub fn get_pc(&self) -> VirtAddr {
-        self.registers.userdata.regs.rip.into()
+        self.regs().userdata.regs.rip.into()
     }
 
     pub fn enable_breaksite_by<T: BreakSiteId>(&mut self, id: T) -> Result<()> {
-        let pid = self.pid.clone();
+        let pid = self.tid();
         let Some(bs) = id.find_breaksite_mut(self) else {
             return error("could not find breaksite");
         };
@@ -245,7 +439,7 @@ impl Process {
     }
 
     pub fn disable_breaksite_by<T: BreakSiteId>(&mut self, id: T) -> Result<()> {
-        let pid = self.pid.clone();
+        let pid = self.tid();
         let Some(bs) = id.find_breaksite_mut(self) else {
             return error("could not find breaksite");
         };
@@ -281,6 +475,261 @@ impl Process {
         Ok(id)
     }
 
+    /// Creates a breaksite for the debugger's own use. Internal ids count down from
+    /// usize::MAX so they never collide with user breakpoint ids.
+    pub fn create_internal_breaksite(&mut self, va: VirtAddr) -> Result<usize> {
+        if self.breaksite_at_va(va).is_some() {
+            return error("breakpoint already exists at that address");
+        }
+        let id = self.next_internal_breaksite_id;
+        self.next_internal_breaksite_id -= 1;
+        let mut bs = BreakSite::new(id, va);
+        bs.internal = true;
+        self.breaksites.insert(id, bs);
+        Ok(id)
+    }
+
+    /// User breakpoints, ordered by id
+    pub fn breakpoints(&self) -> Vec<&Breakpoint> {
+        let mut out: Vec<&Breakpoint> = self.breakpoints.values().collect();
+        out.sort_by_key(|bp| bp.id);
+        out
+    }
+
+    pub fn breakpoint_by_id(&self, id: usize) -> Option<&Breakpoint> {
+        self.breakpoints.get(&id)
+    }
+
+    /// The user breakpoint a breaksite was inserted for, the enabled one with the lowest id
+    /// if several share it
+    pub fn breakpoint_for_site(&self, site: usize) -> Option<&Breakpoint> {
+        let mut owners: Vec<&Breakpoint> = self.breaksite_by_id(site)?.breakpoints.iter()
+            .filter_map(|id| self.breakpoints.get(id))
+            .collect();
+        owners.sort_by_key(|bp| (!bp.enabled, bp.id));
+        owners.first().copied()
+    }
+
+    /// Creates a breakpoint with a breaksite at every address the location currently resolves
+    /// to. Symbols and lines that are not loaded yet leave the breakpoint pending until a
+    /// library providing them is.
+    pub fn create_breakpoint(&mut self, location: Location) -> Result<usize> {
+        self.refresh_loaded_objects()?;
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.insert(id, Breakpoint::new(id, location));
+        if let Err(e) = self.insert_breakpoint_sites(id) {
+            let _ = self.clear_breakpoint(id);
+            return Err(e);
+        }
+        Ok(id)
+    }
+
+    /// Adds breaksites for addresses the breakpoint's location resolves to that it does not
+    /// have one at yet, sharing those another breakpoint already has there
+    fn insert_breakpoint_sites(&mut self, id: usize) -> Result<()> {
+        let Some(bp) = self.breakpoints.get(&id) else {
+            return error("could not find breakpoint");
+        };
+        for va in self.location_addrs(&bp.location.clone()) {
+            let site = match self.breaksite_at_va(va) {
+                Some(bs) if bs.internal || bs.breakpoints.contains(&id) => continue,
+                Some(bs) => bs.id,
+                None => {
+                    let site = self.create_breaksite(va)?;
+                    self.breaksites.get_mut(&site).unwrap().set_disabled();
+                    site
+                }
+            };
+            self.breaksites.get_mut(&site).unwrap().breakpoints.push(id);
+            self.breakpoints.get_mut(&id).unwrap().sites.push(site);
+            if let Err(e) = self.sync_breakpoint_site(site) {
+                // leave the address unresolved so a later load can try it again
+                self.breakpoints.get_mut(&id).unwrap().sites.retain(|s| *s != site);
+                let bs = self.breaksites.get_mut(&site).unwrap();
+                bs.breakpoints.retain(|owner| *owner != id);
+                if bs.breakpoints.is_empty() {
+                    self.breaksites.remove(&site);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Enables a breaksite shared by user breakpoints while any of them is enabled, and
+    /// disables it otherwise
+    fn sync_breakpoint_site(&mut self, site: usize) -> Result<()> {
+        let Some(bs) = self.breaksites.get(&site) else {
+            return Ok(());
+        };
+        let wanted = bs.breakpoints.iter().any(|id| self.breakpoints.get(id).is_some_and(|bp| bp.enabled));
+        match (wanted, bs.enabled()) {
+            (true, false) => self.enable_breaksite_by(site),
+            (false, true) => self.disable_breaksite_by(site),
+            _ => Ok(())
+        }
+    }
+
+    pub fn enable_breakpoint(&mut self, id: usize) -> Result<()> {
+        self.set_breakpoint_enabled(id, true)
+    }
+
+    pub fn disable_breakpoint(&mut self, id: usize) -> Result<()> {
+        self.set_breakpoint_enabled(id, false)
+    }
+
+    fn set_breakpoint_enabled(&mut self, id: usize, enable: bool) -> Result<()> {
+        let Some(bp) = self.breakpoints.get_mut(&id) else {
+            return error("could not find breakpoint");
+        };
+        bp.enabled = enable;
+        for site in bp.sites.clone() {
+            self.sync_breakpoint_site(site)?;
+        }
+        Ok(())
+    }
+
+    /// Removes a breakpoint along with the breaksites it does not share with another
+    pub fn clear_breakpoint(&mut self, id: usize) -> Result<()> {
+        let Some(bp) = self.breakpoints.remove(&id) else {
+            return error("could not find breakpoint");
+        };
+        let mut res = Ok(());
+        for site in bp.sites {
+            let Some(bs) = self.breaksites.get_mut(&site) else {
+                continue;
+            };
+            bs.breakpoints.retain(|owner| *owner != id);
+            if !bs.breakpoints.is_empty() {
+                res = res.and(self.sync_breakpoint_site(site));
+            } else if let Some(mut bs) = self.breaksites.remove(&site) {
+                if bs.enabled() {
+                    res = res.and(Self::disable_breaksite(self.tid(), &mut bs));
+                }
+            }
+        }
+        res
+    }
+
+    pub fn watchpoints(&self) -> Vec::<&Watchpoint> {
+        self.watchpoints.values().collect()
+    }
+
+    pub fn watchpoint_by_id(&self, id: usize) -> Option<&Watchpoint> {
+        self.watchpoints.get(&id)
+    }
+
+    pub fn watchpoint_at_va(&self, va: VirtAddr) -> Option<&Watchpoint> {
+        self.watchpoints.values().find(|wp| wp.addr() == va)
+    }
+
+    pub fn create_watchpoint(&mut self, va: VirtAddr, size: usize, mode: WatchMode) -> Result<usize> {
+        if let Some(existing) = self.watchpoint_at_va(va) {
+            return error(&format!("watchpoint already exists at that address (id {})", existing.id));
+        }
+        let id = self.next_watchpoint_id;
+        let wp = Watchpoint::new(id, va, size, mode)?;
+        self.next_watchpoint_id += 1;
+        self.watchpoints.insert(id, wp);
+        Ok(id)
+    }
+
+    pub fn enable_watchpoint(&mut self, id: usize) -> Result<()> {
+        let Some(wp) = self.watchpoints.get(&id) else {
+            return error("could not find watchpoint");
+        };
+        if wp.enabled() {
+            return Ok(());
+        }
+        let (va, size, mode) = (wp.va, wp.size, wp.mode);
+        let slot = self.set_hardware_stoppoint(va, size, mode)?;
+        let wp = self.watchpoints.get_mut(&id).unwrap();
+        wp.slot = Some(slot);
+        wp.set_enabled();
+        Ok(())
+    }
+
+    pub fn disable_watchpoint(&mut self, id: usize) -> Result<()> {
+        let Some(wp) = self.watchpoints.get(&id) else {
+            return error("could not find watchpoint");
+        };
+        if let Some(slot) = wp.slot {
+            self.clear_hardware_stoppoint(slot)?;
+        }
+        let wp = self.watchpoints.get_mut(&id).unwrap();
+        wp.slot = None;
+        wp.set_disabled();
+        Ok(())
+    }
+
+    pub fn clear_watchpoint(&mut self, id: usize) -> Result<()> {
+        self.disable_watchpoint(id)?;
+        self.watchpoints.remove(&id);
+        Ok(())
+    }
+
+    pub fn clear_all_watchpoints(&mut self) -> usize {
+        let ids: Vec<usize> = self.watchpoints.keys().copied().collect();
+        ids.into_iter().filter(|id| self.clear_watchpoint(*id).is_ok()).count()
+    }
+
+    /// Programs a free debug register with the given address and returns its index
+    fn set_hardware_stoppoint(&mut self, va: VirtAddr, size: usize, mode: WatchMode) -> Result<usize> {
+        let dr7 = self.regs().read_as_id::<u64>(RegisterId::dr7);
+        let Some(slot) = (0..4).find(|i| dr7 & (0b11 << (i * 2)) == 0) else {
+            return error("no free hardware debug registers");
+        };
+        let len_bits: u64 = match size {
+            1 => 0b00,
+            2 => 0b01,
+            8 => 0b10,
+            4 => 0b11,
+            _ => return error("invalid hardware stoppoint size")
+        };
+        self.write_debug_reg(slot, va.0)?;
+        let mask = (0b11 << (slot * 2)) | (0b1111 << (16 + slot * 4));
+        let bits = (1 << (slot * 2))
+            | (mode.dr7_bits() << (16 + slot * 4))
+            | (len_bits << (18 + slot * 4));
+        self.write_debug_reg(7, (dr7 & !mask) | bits)?;
+        Ok(slot)
+    }
+
+    fn clear_hardware_stoppoint(&mut self, slot: usize) -> Result<()> {
+        let dr7 = self.regs().read_as_id::<u64>(RegisterId::dr7);
+        let mask = (0b11 << (slot * 2)) | (0b1111 << (16 + slot * 4));
+        self.write_debug_reg(7, dr7 & !mask)?;
+        self.write_debug_reg(slot, 0)
+    }
+
+    /// Debug registers are per thread, so watchpoints are programmed into every one of them
+    fn write_debug_reg(&mut self, idx: usize, val: u64) -> Result<()> {
+        let ri = register_by_id(DR_IDS[idx]).unwrap();
+        for thread in self.threads.values_mut() {
+            if ptrace::write_user(thread.tid, ri.offset as *mut libc::c_void, val as i64).is_err() {
+                return error_os("could not write debug register");
+            }
+            thread.registers.write(&RValue::from(val, ri));
+        }
+        Ok(())
+    }
+
+    /// New threads start without the watchpoints the rest of the process has
+    fn copy_debug_regs_to(&mut self, id: usize) -> Result<()> {
+        let from = self.regs().userdata.u_debugreg;
+        let thread = self.threads.get_mut(&id).unwrap();
+        // dr7 goes last so no watchpoint is enabled before its address is set
+        for idx in [0, 1, 2, 3, 7] {
+            let ri = register_by_id(DR_IDS[idx]).unwrap();
+            if ptrace::write_user(thread.tid, ri.offset as *mut libc::c_void, from[idx] as i64).is_err() {
+                return error_os("could not write debug register");
+            }
+            thread.registers.userdata.u_debugreg[idx] = from[idx];
+        }
+        Ok(())
+    }
+
     pub fn launch_args(cmd: &str, args: Vec::<String>, attach: bool, stdout: Option<std::os::fd::RawFd>) -> Result<Self> {
         let Ok(cmd_c) = CString::new(cmd) else {
             return error("could not read cmd");
@@ -342,14 +791,37 @@ impl Process {
         let mut p = Self {
             pid: Pid::from_raw(pid),
             autoterm: true,
+            follow_fork: ForkMode::Parent,
             attached: attach,
             state: ProcessState::Running,
-            registers: Registers::empty(),
+            threads: BTreeMap::from([(1, Thread::new(1, Pid::from_raw(pid)))]),
+            current_thread: 1,
+            next_thread_id: 2,
             breaksites: HashMap::new(),
-            next_breaksite_id: 0
+            next_breaksite_id: 0,
+            next_internal_breaksite_id: usize::MAX,
+            breakpoints: HashMap::new(),
+            next_breakpoint_id: 1,
+            watchpoints: HashMap::new(),
+            next_watchpoint_id: 0,
+            objects: Vec::new(),
+            libraries: Vec::new(),
+            loader_breaksite: None,
+            selected_frame: 0,
+            forked: Vec::new(),
+            vfork_parked: Vec::new(),
+            signal_policies: HashMap::new(),
+            noted_signals: Vec::new(),
+            stepping: false,
+            syscall_catch_policy: SyscallCatchPolicy::None,
+            syscall_trace: None,
+            syscall_faults: BTreeMap::new(),
+            next_syscall_fault_id: 1
         };
         if attach {
             let _ = p.wait_on_signal();
+            p.set_ptrace_options()?;
+            let _ = p.load_elf();
         }
         Ok(p)
     }
@@ -366,67 +838,1276 @@ impl Process {
         let mut p = Self {
             pid,
             autoterm: false,
+            follow_fork: ForkMode::Parent,
             attached: true,
             state: ProcessState::Running,
-            registers: Registers::empty(),
+            threads: BTreeMap::from([(1, Thread::new(1, pid))]),
+            current_thread: 1,
+            next_thread_id: 2,
             breaksites: HashMap::new(),
-            next_breaksite_id: 0
+            next_breaksite_id: 0,
+            next_internal_breaksite_id: usize::MAX,
+            breakpoints: HashMap::new(),
+            next_breakpoint_id: 1,
+            watchpoints: HashMap::new(),
+            next_watchpoint_id: 0,
+            objects: Vec::new(),
+            libraries: Vec::new(),
+            loader_breaksite: None,
+            selected_frame: 0,
+            forked: Vec::new(),
+            vfork_parked: Vec::new(),
+            signal_policies: HashMap::new(),
+            noted_signals: Vec::new(),
+            stepping: false,
+            syscall_catch_policy: SyscallCatchPolicy::None,
+            syscall_trace: None,
+            syscall_faults: BTreeMap::new(),
+            next_syscall_fault_id: 1
         };
         let _ = p.wait_on_signal();
+        // the SIGSTOP ptrace sent to attach is ours, not one to pass on
+        p.thread_mut().pending_signal = None;
+        p.attach_threads()?;
+        let _ = p.load_elf();
         Ok(p)
     }
 
-    pub fn wait_on_signal(&mut self) -> Result<StopReason> {
-        // passing None is equivalent to using 0 for the options
-        let res = wait::waitpid(self.pid, None);
-        let Ok(status) = res else {
-            return error_os("could not wait on signal");
+    /// Attaches to the rest of the tasks of a process whose main thread has just been
+    /// attached to. Threads can be created while this runs, so /proc is read again until
+    /// every task there is stopped and will report its own clones.
+    fn attach_threads(&mut self) -> Result<()> {
+        self.set_ptrace_options()?;
+        loop {
+            let new: Vec<Pid> = task_ids(self.pid)?.into_iter()
+                .filter(|tid| self.thread_by_tid(*tid).is_none())
+                .collect();
+            if new.is_empty() {
+                return Ok(());
+            }
+            for tid in new {
+                // the thread may have exited since /proc was read
+                if ptrace::attach(tid).is_ok() {
+                    self.add_thread(tid, true);
+                }
+            }
+            self.stop_other_threads()?;
+            self.set_ptrace_options()?;
+        }
+    }
+
+    /// Asks to be told about new threads, forks and execs; new tasks are then traced
+    /// automatically. Options are per task, but tasks created through a reported clone or
+    /// fork inherit them.
+    fn set_ptrace_options(&mut self) -> Result<()> {
+        let options = ptrace::Options::PTRACE_O_TRACECLONE
+            | ptrace::Options::PTRACE_O_TRACEFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORK
+            | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+            | ptrace::Options::PTRACE_O_TRACEEXEC
+            | ptrace::Options::PTRACE_O_TRACESYSGOOD;
+        for thread in self.threads.values() {
+            if ptrace::setoptions(thread.tid, options).is_err() {
+                return error_os("could not set ptrace options");
+            }
+        }
+        Ok(())
+    }
+
+    /// The executable the inferior is running
+    pub fn elf(&self) -> Option<&Elf> {
+        self.objects.first().map(|o| &o.elf)
+    }
+
+    pub fn loaded_objects(&self) -> &[LoadedObject] {
+        &self.objects
+    }
+
+    fn load_elf(&mut self) -> Result<()> {
+        self.load_objects()?;
+        self.track_loader()
+    }
+
+    /// Reads the executable and every object mapped alongside it
+    fn load_objects(&mut self) -> Result<()> {
+        self.objects.clear();
+        let path = std::fs::read_link(format!("/proc/{}/exe", self.pid))
+            .unwrap_or_else(|_| format!("/proc/{}/exe", self.pid).into());
+        let elf = Elf::load(&path)?;
+        let load_bias = exe_load_bias(&elf, &read_auxv(self.pid)?)?;
+        self.objects = vec![LoadedObject::new(elf, load_bias)];
+        self.refresh_loaded_objects()
+    }
+
+    /// Sets an internal breakpoint where the dynamic loader reports changes to its list of
+    /// objects. Before the loader has run r_brk is not filled in yet, so `_dl_debug_state`,
+    /// which it points to, is used instead.
+    fn track_loader(&mut self) -> Result<()> {
+        let Some(r_debug) = find_r_debug(self) else {
+            return Ok(());
+        };
+        let brk = match read_r_debug(self, r_debug)?.brk {
+            VirtAddr(0) => match self.find_symbol("_dl_debug_state").first() {
+                Some(va) => *va,
+                None => return Ok(())
+            },
+            brk => brk
+        };
+        let id = self.create_internal_breaksite(brk)?;
+        self.enable_breaksite_by(id)?;
+        self.loader_breaksite = Some(id);
+        Ok(())
+    }
+
+    /// Shared objects in the order the loader loaded them, including the loader itself
+    pub fn shared_libraries(&self) -> &[SharedLibrary] {
+        &self.libraries
+    }
+
+    pub fn memory_map(&self) -> Result<MemoryMap> {
+        MemoryMap::read(self.pid)
+    }
+
+    /// The auxiliary vector entries the kernel passed to the inferior, keyed by AT_* type
+    pub fn auxv(&self) -> Result<HashMap<u64, u64>> {
+        read_auxv(self.pid)
+    }
+
+    pub fn object_by_path(&self, path: &std::path::Path) -> Option<&LoadedObject> {
+        self.objects.iter().find(|o| o.path() == path)
+    }
+
+    pub fn object_containing(&self, va: VirtAddr) -> Option<&LoadedObject> {
+        self.objects.iter().find(|o| o.contains(va))
+    }
+
+    /// Converts an address in one of the loaded ELF files to where it lives in the inferior
+    pub fn file_to_virt(&self, path: &std::path::Path, addr: FileAddr) -> Result<VirtAddr> {
+        match self.object_by_path(path) {
+            Some(o) => Ok(o.to_virt(addr)),
+            None => error(&format!("{} is not loaded", path.display()))
+        }
+    }
+
+    /// Finds which loaded object a runtime address belongs to and its address within that file
+    pub fn virt_to_file(&self, va: VirtAddr) -> Option<(&LoadedObject, FileAddr)> {
+        self.object_containing(va).map(|o| (o, o.to_file(va)))
+    }
+
+    /// Picks up shared objects loaded or unloaded since the last call. The loader's link_map
+    /// list is used once it has one; until then, files in the memory map are.
+    pub fn refresh_loaded_objects(&mut self) -> Result<()> {
+        let libraries = shared_libraries(self);
+        let files: Vec<(PathBuf, u64)> = match &libraries {
+            Some(libs) => libs.iter()
+                .filter(|lib| !lib.name.is_empty())
+                .map(|lib| (std::fs::canonicalize(&lib.name).unwrap_or_else(|_| PathBuf::from(&lib.name)), lib.base))
+                .collect(),
+            None => mapped_files(&self.memory_map()?)
         };
-        let reason = StopReason::from_wait_status(status);
-        self.state = reason.newstate.clone();
+        let known: Vec<PathBuf> = self.objects.iter().map(|o| o.path().to_path_buf()).collect();
+        let mut previous = std::mem::take(&mut self.objects);
+        let mut objects: Vec<LoadedObject> = Vec::new();
+        if !previous.is_empty() {
+            objects.push(previous.remove(0));
+        }
+        for (path, start) in files {
+            if objects.iter().any(|o| o.path() == path) {
+                continue;
+            }
+            if let Some(idx) = previous.iter().position(|o| o.path() == path) {
+                objects.push(previous.remove(idx));
+                continue;
+            }
+            if let Ok(elf) = Elf::load(&path) {
+                objects.push(match libraries {
+                    Some(_) => LoadedObject::new(elf, start),
+                    None => LoadedObject::from_mapping(elf, start)
+                });
+            }
+        }
+
+        // breaksites in unloaded objects are gone along with the code they patched. Those
+        // belonging to a breakpoint are dropped so it can be resolved again on a later load.
+        let unloaded: Vec<usize> = self.breaksites.values()
+            .filter(|bs| previous.iter().any(|o| o.contains(bs.addr())) && !objects.iter().any(|o| o.contains(bs.addr())))
+            .map(|bs| bs.id)
+            .collect();
+        for site in unloaded {
+            let bs = self.breaksites.get_mut(&site).unwrap();
+            bs.saved_data = None;
+            bs.set_disabled();
+            if !bs.breakpoints.is_empty() {
+                for id in std::mem::take(&mut bs.breakpoints) {
+                    if let Some(bp) = self.breakpoints.get_mut(&id) {
+                        bp.sites.retain(|s| *s != site);
+                    }
+                }
+                self.breaksites.remove(&site);
+            }
+        }
+        let loaded_new = objects.iter().any(|o| !known.iter().any(|path| path == o.path()));
+        self.objects = objects;
+        if let Some(libs) = libraries {
+            self.libraries = libs;
+        }
+        if loaded_new {
+            let ids: Vec<usize> = self.breakpoints.keys().copied().collect();
+            for id in ids {
+                let _ = self.insert_breakpoint_sites(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runtime addresses of a symbol in the executable and every loaded library, in load order
+    pub fn find_symbol(&self, name: &str) -> Vec<VirtAddr> {
+        self.objects.iter().flat_map(|o| o.symbol_addrs(name)).collect()
+    }
+
+    /// The symbol covering a runtime address, with the offset of the address into it
+    pub fn symbol_at(&self, va: VirtAddr) -> Option<(String, u64)> {
+        self.object_containing(va)
+            .and_then(|o| o.symbol_at(va))
+            .map(|(sym, offset)| (sym.name.clone(), offset))
+    }
+
+    pub fn resolve_location(&mut self, loc: &Location) -> Result<VirtAddr> {
+        if !matches!(loc, Location::Address(_)) {
+            self.refresh_loaded_objects()?;
+        }
+        match (self.location_addrs(loc).first(), loc) {
+            (Some(va), _) => Ok(*va),
+            (None, Location::Line(file, line)) => error(&format!("no code found for {}:{}", file, line)),
+            (None, _) => error(&format!("could not find symbol {}", loc))
+        }
+    }
+
+    /// Every address a location currently resolves to: a symbol's definition in each loaded
+    /// object that has one, and the start of a line in each object with code for it
+    pub fn location_addrs(&self, loc: &Location) -> Vec<VirtAddr> {
+        match loc {
+            Location::Address(addr) => vec![VirtAddr(*addr)],
+            Location::Symbol(name, offset) => self.find_symbol(name).into_iter().map(|va| va + *offset).collect(),
+            Location::Line(file, line) => self.objects.iter()
+                .filter_map(|o| o.addrs_for_line(file, *line).first().copied())
+                .collect()
+        }
+    }
+
+    /// The source file and line the given address was compiled from, if there is line info for it
+    pub fn source_location(&self, va: VirtAddr) -> Option<SourceLocation> {
+        self.object_containing(va).and_then(|o| o.source_location(va))
+    }
+
+    pub fn wait_on_signal(&mut self) -> Result<StopReason> {
+        if !self.attached {
+            // passing None is equivalent to using 0 for the options
+            let Ok(status) = wait::waitpid(self.pid, None) else {
+                return error_os("could not wait on signal");
+            };
+            let reason = StopReason::from_wait_status(status);
+            self.state = reason.newstate.clone();
+            return Ok(reason);
+        }
+        loop {
+            let (id, status) = self.wait_for_event()?;
+            let mut reason = StopReason::from_wait_status(status);
+            reason.thread = Some(id);
+            self.selected_frame = 0;
+            if reason.newstate != ProcessState::Stopped {
+                self.state = reason.newstate.clone();
+                for thread in self.threads.values_mut() {
+                    thread.state = self.state.clone();
+                }
+                if let Some(trace) = &mut self.syscall_trace {
+                    trace.finish();
+                }
+                return Ok(reason);
+            }
+
+            // stops that are not reported are dealt with in the thread that made them while
+            // the others carry on, so tracing syscalls does not stop every thread each time
+            if let wait::WaitStatus::PtraceSyscall(_) = status {
+                self.read_thread_registers(id)?;
+                let info = self.handle_syscall_stop(id)?;
+                if !self.syscall_catch_policy.catches(info.id) {
+                    self.resume_thread(id)?;
+                    continue;
+                }
+                reason.syscall = Some(info);
+            }
+            if status == wait::WaitStatus::Stopped(self.threads[&id].tid, signal::Signal::SIGTRAP) && self.is_loader_trap(id)? {
+                self.handle_loader_trap(id)?;
+                continue;
+            }
+            if let Some(sig) = reason.delivered_signal() {
+                let policy = self.signal_policy(sig);
+                if policy.pass {
+                    self.threads.get_mut(&id).unwrap().pending_signal = Some(sig);
+                }
+                if !policy.stop {
+                    if policy.print {
+                        self.noted_signals.push((id, sig));
+                    }
+                    // only the thread being stepped reports a quiet signal, so another
+                    // thread's does not end the step
+                    if !self.stepping || id != self.current_thread {
+                        self.resume_thread(id)?;
+                        continue;
+                    }
+                }
+            }
 
-        if self.attached && self.state == ProcessState::Stopped {
+            self.state = ProcessState::Stopped;
+            self.current_thread = id;
+            if matches!(status, wait::WaitStatus::Stopped(..)) {
+                reason.siginfo = ptrace::getsiginfo(self.tid()).ok().and_then(|info| SignalInfo::from_siginfo(&info));
+            }
+            self.stop_other_threads()?;
             let _ = self.read_all_registers();
+            if matches!(status, wait::WaitStatus::PtraceEvent(..)) {
+                reason.trap = self.forked.last().map(|child| TrapType::Fork(child.pid));
+            } else if reason.syscall.is_none() && reason.info == StopInfo::Signal(signal::Signal::SIGTRAP) {
+                reason.trap = Some(self.classify_trap());
+            }
+            return Ok(reason);
         }
+    }
+
+    /// Whether a thread's SIGTRAP is the int3 on the loader's r_brk
+    fn is_loader_trap(&mut self, id: usize) -> Result<bool> {
+        let Some(addr) = self.loader_breaksite.and_then(|bs| self.breaksite_by_id(bs)).map(|bs| bs.addr()) else {
+            return Ok(false);
+        };
+        self.read_thread_registers(id)?;
+        let pc = VirtAddr(self.threads[&id].registers.read_as_id::<u64>(RegisterId::rip));
+        let is_break = ptrace::getsiginfo(self.threads[&id].tid)
+            .is_ok_and(|info| info.si_code == libc::SI_KERNEL || info.si_code == libc::TRAP_BRKPT);
+        Ok(is_break && pc - 1u64 == addr)
+    }
 
-        let instr_begin = self.get_pc() - 1u64;
-        if reason.info == StopInfo::Signal(signal::Signal::SIGTRAP) && self.breaksite_at_va(instr_begin).is_some() {
-            self.set_pc(instr_begin);
+    /// Brings the list of loaded objects up to date after a thread hit r_brk, then moves
+    /// it past the breaksite and lets it carry on, leaving every other thread running. The
+    /// loader only calls r_brk with its lock held, so no other thread can get there while
+    /// the breaksite is lifted.
+    fn handle_loader_trap(&mut self, id: usize) -> Result<()> {
+        self.current_thread = id;
+        let pc = VirtAddr(self.threads[&id].registers.read_as_id::<u64>(RegisterId::rip)) - 1u64;
+        self.write_thread_reg(id, RegisterId::rip, pc.0)?;
+        self.handle_loader_event()?;
+
+        let tid = self.threads[&id].tid;
+        self.disable_breaksite_by(pc)?;
+        let mut deferred = Vec::new();
+        let stepped = loop {
+            if ptrace::step(tid, None).is_err() {
+                break error_os("could not PTRACE_SINGLESTEP");
+            }
+            match wait_task(tid) {
+                Ok(wait::WaitStatus::Stopped(_, signal::Signal::SIGTRAP)) => break Ok(()),
+                // a signal that arrives first is sent again once the thread is on its way,
+                // so it is reported like any other
+                Ok(wait::WaitStatus::Stopped(_, sig)) => deferred.push(sig),
+                Ok(_) => break error("thread went away while stepping over r_brk"),
+                Err(e) => break Err(e)
+            }
+        };
+        if self.breaksite_at_va(pc).is_some() {
+            self.enable_breaksite_by(pc)?;
+        }
+        stepped?;
+        self.resume_thread(id)?;
+        for sig in deferred {
+            tgkill(self.pid, tid, sig)?;
+        }
+        Ok(())
+    }
+
+    /// Works out which side of a syscall a thread has stopped at; ptrace reports both the
+    /// same way, so they are told apart by alternating. Fault rules are applied, and the
+    /// call as tampered with goes into the syscall trace if there is one.
+    fn handle_syscall_stop(&mut self, thread: usize) -> Result<SyscallInfo> {
+        const ARGS: [RegisterId; 6] = [RegisterId::rdi, RegisterId::rsi, RegisterId::rdx, RegisterId::r10, RegisterId::r8, RegisterId::r9];
+        let regs = self.threads[&thread].regs();
+        let mut id = regs.read_as_id::<u64>(RegisterId::orig_rax);
+        let data = if self.threads[&thread].in_syscall {
+            let mut ret = regs.read_as_id::<u64>(RegisterId::rax) as i64;
+            if let Some((real_id, injected)) = self.threads.get_mut(&thread).unwrap().injected_return.take() {
+                id = real_id;
+                ret = injected;
+                self.write_thread_reg(thread, RegisterId::rax, ret as u64)?;
+            }
+            SyscallData::Ret(ret)
+        } else {
+            let mut args = ARGS.map(|reg| regs.read_as_id::<u64>(reg));
+            let actions: Vec<FaultAction> = self.syscall_faults.values_mut()
+                .filter_map(|fault| fault.fires(id, &args).then_some(fault.action))
+                .collect();
+            for action in actions {
+                match action {
+                    FaultAction::SetArg(idx, val) => {
+                        args[idx] = val;
+                        self.write_thread_reg(thread, ARGS[idx], val)?;
+                    },
+                    FaultAction::Error(errno) => {
+                        // a syscall number of -1 makes the kernel skip the call
+                        self.write_thread_reg(thread, RegisterId::orig_rax, u64::MAX)?;
+                        self.threads.get_mut(&thread).unwrap().injected_return = Some((id, -(errno as i64)));
+                    },
+                    FaultAction::Return(ret) => {
+                        self.threads.get_mut(&thread).unwrap().injected_return = Some((id, ret));
+                    }
+                }
+            }
+            SyscallData::Args(args)
+        };
+        let tid = self.threads[&thread].tid;
+        let t = self.threads.get_mut(&thread).unwrap();
+        t.in_syscall = !t.in_syscall;
+        let info = SyscallInfo { id, data };
+        if let Some(mut trace) = self.syscall_trace.take() {
+            trace.record(self, thread, tid, &info);
+            self.syscall_trace = Some(trace);
+        }
+        Ok(info)
+    }
+
+    /// Writes a general purpose register of a stopped thread
+    fn write_thread_reg(&mut self, thread: usize, reg: RegisterId, val: u64) -> Result<()> {
+        let rv = RValue::from_id(val, reg);
+        let t = self.threads.get_mut(&thread).unwrap();
+        t.registers.write(&rv);
+        if ptrace::write_user(t.tid, rv.ri.offset as *mut libc::c_void, val as i64).is_err() {
+            return error_os(&format!("could not write {}", rv.ri.name));
+        }
+        Ok(())
+    }
+
+    pub fn syscall_catch_policy(&self) -> &SyscallCatchPolicy {
+        &self.syscall_catch_policy
+    }
+
+    /// Chooses the syscalls to stop at; takes effect the next time the inferior is resumed
+    pub fn set_syscall_catch_policy(&mut self, policy: SyscallCatchPolicy) {
+        self.syscall_catch_policy = policy;
+    }
+
+    /// Records every syscall the inferior makes from the next resume on, without stopping
+    /// for any that are not caught. Each call is written to `output` as it returns, or kept
+    /// for `take_syscall_records` if there is no output.
+    pub fn trace_syscalls(&mut self, output: Option<Box<dyn Write>>) {
+        self.syscall_trace = Some(SyscallTrace::new(output));
+    }
+
+    pub fn stop_tracing_syscalls(&mut self) {
+        self.syscall_trace = None;
+    }
+
+    pub fn is_tracing_syscalls(&self) -> bool {
+        self.syscall_trace.is_some()
+    }
+
+    /// The syscalls recorded since the last call, when tracing without an output
+    pub fn take_syscall_records(&mut self) -> Vec<SyscallRecord> {
+        self.syscall_trace.as_mut().map(|trace| trace.take_records()).unwrap_or_default()
+    }
+
+    /// Adds a rule for tampering with syscalls, from the next syscall entry on, and returns
+    /// its id
+    pub fn add_syscall_fault(&mut self, fault: SyscallFault) -> usize {
+        let id = self.next_syscall_fault_id;
+        self.next_syscall_fault_id += 1;
+        self.syscall_faults.insert(id, fault);
+        id
+    }
+
+    pub fn remove_syscall_fault(&mut self, id: usize) -> Result<()> {
+        match self.syscall_faults.remove(&id) {
+            Some(_) => Ok(()),
+            None => error(&format!("no syscall fault rule {}", id))
+        }
+    }
+
+    pub fn syscall_faults(&self) -> &BTreeMap<usize, SyscallFault> {
+        &self.syscall_faults
+    }
+
+    /// Whether threads are resumed to their syscall stops as well as to signals
+    fn stops_at_syscalls(&self) -> bool {
+        self.syscall_catch_policy != SyscallCatchPolicy::None || self.syscall_trace.is_some()
+            || !self.syscall_faults.is_empty()
+    }
+
+    /// How the inferior's receiving `sig` is handled
+    pub fn signal_policy(&self, sig: signal::Signal) -> SignalPolicy {
+        self.signal_policies.get(&sig).copied().unwrap_or_else(|| default_signal_policy(sig))
+    }
+
+    pub fn set_signal_policy(&mut self, sig: signal::Signal, policy: SignalPolicy) {
+        self.signal_policies.insert(sig, policy);
+    }
+
+    /// Signals that went by without stopping since the last call, for those whose policy is
+    /// to print them, along with the thread that received each
+    pub fn take_noted_signals(&mut self) -> Vec<(usize, signal::Signal)> {
+        std::mem::take(&mut self.noted_signals)
+    }
+
+    /// Resumes with `sig` delivered to the current thread in place of whatever signal it
+    /// stopped with; None resumes it with no signal at all
+    pub fn resume_with_signal(&mut self, sig: Option<signal::Signal>) -> Result<()> {
+        if self.state != ProcessState::Stopped {
+            return error("could not resume: the process is not stopped");
+        }
+        self.thread_mut().pending_signal = sig;
+        self.resume()
+    }
+
+    /// Waits until some thread stops for a reason the user should see, or the process exits.
+    /// Thread creation and exit, and the SIGSTOPs gadb itself caused, are dealt with here and
+    /// waited through.
+    fn wait_for_event(&mut self) -> Result<(usize, wait::WaitStatus)> {
+        loop {
+            let status = self.wait_any()?;
+            let Some(tid) = status.pid() else {
+                continue;
+            };
+            let id = match self.thread_by_tid(tid) {
+                Some(thread) => thread.id,
+                // a new thread can report its first stop before its creator's clone event
+                None => self.add_thread(tid, true)
+            };
+            match status {
+                wait::WaitStatus::Exited(..) | wait::WaitStatus::Signaled(..) => {
+                    if tid == self.pid || self.threads.len() == 1 {
+                        return Ok((id, status));
+                    }
+                    self.remove_thread(id);
+                },
+                wait::WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    if let Ok(new_tid) = ptrace::getevent(tid) {
+                        let new_tid = Pid::from_raw(new_tid as i32);
+                        if self.thread_by_tid(new_tid).is_none() {
+                            self.add_thread(new_tid, true);
+                        }
+                    }
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::PtraceEvent(_, _, event @ (libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK)) => {
+                    let Ok(child) = ptrace::getevent(tid) else {
+                        return error_os("could not read the forked child's pid");
+                    };
+                    self.threads.get_mut(&id).unwrap().state = ProcessState::Stopped;
+                    let next = self.handle_fork(id, Pid::from_raw(child as i32), event == libc::PTRACE_EVENT_VFORK, self.follow_fork)?;
+                    if self.follow_fork == ForkMode::Both {
+                        return Ok((id, status));
+                    }
+                    self.resume_thread(next)?;
+                },
+                wait::WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK_DONE) => {
+                    self.unpark_breaksites()?;
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_EXEC) => {
+                    self.handle_exec()?;
+                    self.resume_thread(self.current_thread)?;
+                },
+                wait::WaitStatus::Stopped(_, signal::Signal::SIGSTOP) if self.threads[&id].sigstop_pending => {
+                    self.threads.get_mut(&id).unwrap().sigstop_pending = false;
+                    self.threads.get_mut(&id).unwrap().state = ProcessState::Stopped;
+                    self.copy_debug_regs_to(id)?;
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::Stopped(..) | wait::WaitStatus::PtraceSyscall(_) => {
+                    self.threads.get_mut(&id).unwrap().state = ProcessState::Stopped;
+                    return Ok((id, status));
+                },
+                _ => self.resume_thread(id)?
+            }
+        }
+    }
+
+    /// waitpid for any task this thread of gadb traces. Statuses for tasks belonging to
+    /// another Process are set aside for it to pick up.
+    fn wait_any(&mut self) -> Result<wait::WaitStatus> {
+        let claimed = UNCLAIMED.with_borrow_mut(|statuses| {
+            let idx = statuses.iter().position(|s| s.pid().is_some_and(|tid| self.owns_task(tid)))?;
+            Some(statuses.remove(idx))
+        });
+        if let Some(status) = claimed {
+            return Ok(status);
+        }
+        loop {
+            let flags = wait::WaitPidFlag::__WALL | wait::WaitPidFlag::__WNOTHREAD;
+            let Ok(status) = wait::waitpid(Pid::from_raw(-1), Some(flags)) else {
+                return error_os("could not wait on signal");
+            };
+            match status.pid() {
+                Some(tid) if !self.owns_task(tid) => UNCLAIMED.with_borrow_mut(|statuses| statuses.push(status)),
+                _ => return Ok(status)
+            }
+        }
+    }
+
+    fn owns_task(&self, tid: Pid) -> bool {
+        tid == self.pid || self.thread_by_tid(tid).is_some() || thread_group(tid).is_ok_and(|tgid| tgid == self.pid)
+    }
+
+    /// All-stop: once one thread has stopped, every other running thread is stopped too.
+    /// A thread that hits a breakpoint on the way has its pc put back so it hits it again
+    /// when resumed; other signals are kept to be delivered then.
+    fn stop_other_threads(&mut self) -> Result<()> {
+        self.stop_threads(Some(self.current_thread))
+    }
+
+    /// Stops a process that is running, for when gadb needs to let go of it
+    fn stop_all_threads(&mut self) -> Result<()> {
+        self.stop_threads(None)?;
+        self.state = ProcessState::Stopped;
+        Ok(())
+    }
+
+    fn stop_threads(&mut self, except: Option<usize>) -> Result<()> {
+        loop {
+            let Some(id) = self.threads.values()
+                .find(|t| Some(t.id) != except && t.state == ProcessState::Running)
+                .map(|t| t.id) else {
+                return Ok(());
+            };
+            let tid = self.threads[&id].tid;
+            if !self.threads[&id].sigstop_pending {
+                if tgkill(self.pid, tid, signal::Signal::SIGSTOP).is_err() {
+                    self.remove_thread(id);
+                    continue;
+                }
+                self.threads.get_mut(&id).unwrap().sigstop_pending = true;
+            }
+            let Ok(status) = wait::waitpid(tid, Some(wait::WaitPidFlag::__WALL)) else {
+                self.remove_thread(id);
+                continue;
+            };
+            match status {
+                wait::WaitStatus::Exited(..) | wait::WaitStatus::Signaled(..) => self.remove_thread(id),
+                wait::WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_CLONE) => {
+                    if let Ok(new_tid) = ptrace::getevent(tid) {
+                        let new_tid = Pid::from_raw(new_tid as i32);
+                        if self.thread_by_tid(new_tid).is_none() {
+                            self.add_thread(new_tid, true);
+                        }
+                    }
+                    // still has the SIGSTOP queued, so this stops again straight away
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::PtraceEvent(_, _, event @ (libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK)) => {
+                    // switching to a child halfway through stopping the parent is not worth
+                    // it, so a fork that races with the stop keeps the parent
+                    let mode = match self.follow_fork {
+                        ForkMode::Child => ForkMode::Parent,
+                        mode => mode
+                    };
+                    if let Ok(child) = ptrace::getevent(tid) {
+                        self.handle_fork(id, Pid::from_raw(child as i32), event == libc::PTRACE_EVENT_VFORK, mode)?;
+                    }
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::PtraceEvent(_, _, libc::PTRACE_EVENT_VFORK_DONE) => {
+                    self.unpark_breaksites()?;
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::PtraceSyscall(_) => {
+                    // the syscall goes unreported, but the next stop is still the other side
+                    // of it, and fault rules and the trace should not miss it
+                    self.read_thread_registers(id)?;
+                    self.handle_syscall_stop(id)?;
+                    self.resume_thread(id)?;
+                },
+                wait::WaitStatus::Stopped(_, sig) => {
+                    let pass = self.signal_policy(sig).pass;
+                    let thread = self.threads.get_mut(&id).unwrap();
+                    thread.state = ProcessState::Stopped;
+                    if sig == signal::Signal::SIGSTOP && thread.sigstop_pending {
+                        // new threads are caught here too, before they have run anything
+                        thread.sigstop_pending = false;
+                        self.copy_debug_regs_to(id)?;
+                    } else if sig != signal::Signal::SIGTRAP && pass {
+                        thread.pending_signal = Some(sig);
+                    }
+                    self.read_thread_registers(id)?;
+                    if sig == signal::Signal::SIGTRAP {
+                        self.rewind_breakpoint_trap(id)?;
+                    }
+                },
+                _ => self.resume_thread(id)?
+            }
+        }
+    }
+    /// Undoes a breakpoint trap in a thread that is not the one being reported, so that it
+    /// runs the breakpoint again on resuming. Any other SIGTRAP is dropped.
+    fn rewind_breakpoint_trap(&mut self, id: usize) -> Result<()> {
+        let tid = self.threads[&id].tid;
+        let Ok(info) = ptrace::getsiginfo(tid) else {
+            return Ok(());
+        };
+        let pc = VirtAddr(self.threads[&id].registers.read_as_id::<u64>(RegisterId::rip));
+        let is_break = info.si_code == libc::SI_KERNEL || info.si_code == libc::TRAP_BRKPT;
+        if is_break && self.breaksite_at_va(pc - 1u64).is_some_and(|bs| bs.enabled()) {
+            let rv = RValue::from_id((pc - 1u64).0, RegisterId::rip);
+            self.threads.get_mut(&id).unwrap().registers.write(&rv);
+            if ptrace::write_user(tid, rv.ri.offset as *mut libc::c_void, (pc - 1u64).0 as i64).is_err() {
+                return error_os("could not write rip");
+            }
+        }
+        Ok(())
+    }
+
+    /// A thread forked. The child starts out traced and stopped with a copy of our memory,
+    /// breaksites included, or for vfork the very same memory; it has no watchpoints.
+    /// Returns the thread to resume once the caller is done, in whichever process was kept.
+    fn handle_fork(&mut self, id: usize, child: Pid, vfork: bool, mode: ForkMode) -> Result<usize> {
+        // the child's first stop, before it has run anything
+        wait_task(child)?;
+        match mode {
+            ForkMode::Parent => {
+                self.lift_breaksites(child, vfork)?;
+                let _ = ptrace::detach(child, None);
+                return Ok(id);
+            },
+            ForkMode::Both => {
+                let forked = self.fork_child(child)?;
+                self.forked.push(forked);
+                return Ok(id);
+            },
+            ForkMode::Child => {
+                self.current_thread = id;
+                self.stop_other_threads()?;
+                let debugregs = self.regs().userdata.u_debugreg;
+                self.lift_breaksites(self.pid, vfork)?;
+                // the parent only lets go of shared memory once we have stopped watching it,
+                // so these stay out until the child execs
+                self.vfork_parked.clear();
+                for thread in self.threads.values() {
+                    let _ = ptrace::detach(thread.tid, thread.pending_signal);
+                }
+                self.pid = child;
+                let mut thread = Thread::new(1, child);
+                thread.state = ProcessState::Stopped;
+                self.threads = BTreeMap::from([(1, thread)]);
+                self.current_thread = 1;
+                self.next_thread_id = 2;
+                for idx in [0, 1, 2, 3, 7] {
+                    self.write_debug_reg(idx, debugregs[idx])?;
+                }
+            }
+        }
+        Ok(self.current_thread)
+    }
+
+    /// Takes our breaksites out of the memory of a process we are letting go of. When it
+    /// shares our memory, as after vfork, they are parked until VFORK_DONE.
+    fn lift_breaksites(&mut self, pid: Pid, shared: bool) -> Result<()> {
+        let enabled: Vec<usize> = self.breaksites.values()
+            .filter(|bs| bs.enabled())
+            .map(|bs| bs.id)
+            .collect();
+        for site in enabled {
+            if shared {
+                self.disable_breaksite_by(site)?;
+                self.vfork_parked.push(site);
+            } else {
+                let mut copy = self.breaksites[&site].clone();
+                Self::disable_breaksite(pid, &mut copy)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn unpark_breaksites(&mut self) -> Result<()> {
+        for site in std::mem::take(&mut self.vfork_parked) {
+            self.enable_breaksite_by(site)?;
+        }
+        Ok(())
+    }
+
+    /// A Process for a child forked while following both sides, stopped before it has run
+    fn fork_child(&self, child: Pid) -> Result<Process> {
+        let mut thread = Thread::new(1, child);
+        thread.state = ProcessState::Stopped;
+        let mut p = Process {
+            pid: child,
+            autoterm: self.autoterm,
+            attached: true,
+            state: ProcessState::Stopped,
+            follow_fork: self.follow_fork,
+            threads: BTreeMap::from([(1, thread)]),
+            current_thread: 1,
+            next_thread_id: 2,
+            breaksites: self.breaksites.clone(),
+            next_breaksite_id: self.next_breaksite_id,
+            next_internal_breaksite_id: self.next_internal_breaksite_id,
+            breakpoints: self.breakpoints.clone(),
+            next_breakpoint_id: self.next_breakpoint_id,
+            watchpoints: HashMap::new(),
+            next_watchpoint_id: self.next_watchpoint_id,
+            objects: Vec::new(),
+            libraries: self.libraries.clone(),
+            loader_breaksite: self.loader_breaksite,
+            selected_frame: 0,
+            forked: Vec::new(),
+            vfork_parked: Vec::new(),
+            signal_policies: self.signal_policies.clone(),
+            noted_signals: Vec::new(),
+            stepping: false,
+            syscall_catch_policy: self.syscall_catch_policy.clone(),
+            // the trace's output belongs to the parent
+            syscall_trace: None,
+            // the child runs the same code, so the same calls should fail, counted afresh
+            syscall_faults: self.syscall_faults.iter()
+                .map(|(id, fault)| (*id, SyscallFault { matched: 0, ..fault.clone() }))
+                .collect(),
+            next_syscall_fault_id: self.next_syscall_fault_id
+        };
+        p.read_all_registers()?;
+        p.load_objects()?;
+        Ok(p)
+    }
+
+    /// Children forked since the last call while following both sides of forks. Each is
+    /// stopped at its first instruction after the fork.
+    pub fn take_forked(&mut self) -> Vec<Process> {
+        std::mem::take(&mut self.forked)
+    }
+
+    /// The process replaced its image. Only the thread that called exec is left, now with
+    /// the process id as its tid, and every address we knew about is meaningless: objects
+    /// are loaded again, breakpoints resolved again, and watchpoints dropped.
+    fn handle_exec(&mut self) -> Result<()> {
+        let mut thread = Thread::new(1, self.pid);
+        thread.state = ProcessState::Stopped;
+        // when syscalls are stopped at, the entry stop of execve was seen before the exec
+        thread.in_syscall = self.stops_at_syscalls();
+        self.threads = BTreeMap::from([(1, thread)]);
+        self.current_thread = 1;
+        self.next_thread_id = 2;
+        self.selected_frame = 0;
+        self.breaksites.clear();
+        self.vfork_parked.clear();
+        self.breakpoints.values_mut().for_each(|bp| bp.sites.clear());
+        self.watchpoints.clear();
+        self.loader_breaksite = None;
+        self.libraries.clear();
+        self.read_all_registers()?;
+        let _ = self.load_elf();
+        // addresses in libraries are left for the loader's event, once it has mapped them
+        let ids: Vec<usize> = self.breakpoints.values()
+            .filter(|bp| match bp.location {
+                Location::Address(addr) => self.object_containing(VirtAddr(addr)).is_some(),
+                _ => true
+            })
+            .map(|bp| bp.id)
+            .collect();
+        for id in ids {
+            let _ = self.insert_breakpoint_sites(id);
+        }
+        Ok(())
+    }
+
+    /// Takes every breaksite out of memory and turns off the watchpoints in every thread, so
+    /// the process can carry on without gadb
+    fn remove_stop_points(&mut self) {
+        let tid = self.tid();
+        for bs in self.breaksites.values_mut() {
+            if bs.enabled() {
+                let _ = Self::disable_breaksite(tid, bs);
+            }
+        }
+        let _ = self.write_debug_reg(7, 0);
+    }
+
+    /// Resumes one thread, to its next syscall stop too if syscalls are being caught, traced
+    /// or tampered with. The thread being stepped carries on with its step.
+    fn resume_thread(&mut self, id: usize) -> Result<()> {
+        let stops_at_syscalls = self.stops_at_syscalls();
+        let stepping = self.stepping && id == self.current_thread;
+        let thread = self.threads.get_mut(&id).unwrap();
+        // a fault rule that has fired still needs the exit stop to set the result, even if
+        // the rules have been cleared since
+        let res = if stepping {
+            // an event the stepped instruction caused, like the clone of a new thread
+            ptrace::step(thread.tid, thread.pending_signal.take())
+        } else if stops_at_syscalls || thread.injected_return.is_some() {
+            ptrace::syscall(thread.tid, thread.pending_signal.take())
+        } else {
+            // the exit stop of a syscall the thread is in will not be seen
+            thread.in_syscall = false;
+            ptrace::cont(thread.tid, thread.pending_signal.take())
+        };
+        if res.is_err() {
+            return error("could not resume");
+        }
+        thread.state = ProcessState::Running;
+        Ok(())
+    }
+
+    /// Starts tracking a thread and returns its id. `sigstop_pending` is set for threads
+    /// that have just been created and will stop before running
+    fn add_thread(&mut self, tid: Pid, sigstop_pending: bool) -> usize {
+        let id = self.next_thread_id;
+        self.next_thread_id += 1;
+        let mut thread = Thread::new(id, tid);
+        thread.sigstop_pending = sigstop_pending;
+        self.threads.insert(id, thread);
+        id
+    }
+
+    fn remove_thread(&mut self, id: usize) {
+        self.threads.remove(&id);
+        if self.current_thread == id {
+            self.current_thread = self.threads.keys().next().copied().unwrap_or(id);
+        }
+    }
+
+    fn thread_by_tid(&self, tid: Pid) -> Option<&Thread> {
+        self.threads.values().find(|t| t.tid == tid)
+    }
+
+    fn thread(&self) -> &Thread {
+        &self.threads[&self.current_thread]
+    }
+
+    fn thread_mut(&mut self) -> &mut Thread {
+        self.threads.get_mut(&self.current_thread).unwrap()
+    }
+
+    /// The kernel task id of the current thread, which ptrace requests for it go to
+    pub fn tid(&self) -> Pid {
+        self.threads.get(&self.current_thread).map(|t| t.tid).unwrap_or(self.pid)
+    }
+
+    /// Every thread of the inferior, in the order they were created
+    pub fn threads(&self) -> Vec<&Thread> {
+        self.threads.values().collect()
+    }
+
+    pub fn current_thread(&self) -> usize {
+        self.current_thread
+    }
+
+    /// Makes another thread the one that registers, stepping and backtraces refer to
+    pub fn select_thread(&mut self, id: usize) -> Result<()> {
+        if !self.threads.contains_key(&id) {
+            return error(&format!("no thread {}", id));
+        }
+        self.current_thread = id;
+        self.selected_frame = 0;
+        Ok(())
+    }
+
+    /// The loader hit r_brk. It calls it both before and after changing the link_map list,
+    /// so objects are only reloaded once the list is consistent again.
+    fn handle_loader_event(&mut self) -> Result<()> {
+        let Some(r_debug) = find_r_debug(self) else {
+            return Ok(());
+        };
+        if read_r_debug(self, r_debug)?.state == RT_CONSISTENT {
+            self.refresh_loaded_objects()?;
+        }
+        Ok(())
+    }
+
+    /// Works out what raised the current SIGTRAP from its si_code and DR6. Rewinds the pc
+    /// past the int3 when a software breakpoint fired.
+    fn classify_trap(&mut self) -> TrapType {
+        let Ok(info) = ptrace::getsiginfo(self.tid()) else {
+            return TrapType::Unknown;
+        };
+        match info.si_code {
+            libc::SI_KERNEL | libc::TRAP_BRKPT => {
+                let instr_begin = self.get_pc() - 1u64;
+                let id = self.breaksite_at_va(instr_begin).map(|bs| bs.id);
+                if id.is_some() {
+                    self.set_pc(instr_begin);
+                }
+                TrapType::SoftwareBreak(id)
+            },
+            libc::TRAP_TRACE => TrapType::SingleStep,
+            libc::TRAP_HWBKPT => {
+                let dr6 = self.regs().read_as_id::<u64>(RegisterId::dr6);
+                let Some(slot) = (0..4).find(|i| dr6 & (1 << i) != 0) else {
+                    return TrapType::SingleStep;
+                };
+                TrapType::HardwareBreak(self.watchpoints.values().find(|wp| wp.slot == Some(slot)).map(|wp| wp.id))
+            },
+            code if code == libc::SIGTRAP || code == libc::SIGTRAP | 0x80 => TrapType::Syscall,
+            _ => TrapType::Unknown
         }
-        Ok(reason)
     }
 
     pub fn res(&mut self) {
         let _ = self.resume();
     }
 
+    /// Resumes every thread, first stepping the current one off any breakpoint it is sitting on
     pub fn resume(&mut self) -> Result<()> {
+        if self.state != ProcessState::Stopped {
+            return error("could not resume: the process is not stopped");
+        }
+        if !self.attached {
+            if ptrace::cont(self.pid, None).is_err() {
+                return error("could not resume");
+            }
+            self.state = ProcessState::Running;
+            return Ok(());
+        }
+        let pc = self.get_pc();
+        if self.breaksite_at_va(pc).is_some_and(|bs| bs.enabled()) {
+            self.step_instruction()?;
+            if self.state != ProcessState::Stopped {
+                return error("process exited while stepping over a breakpoint");
+            }
+        }
+        let stopped: Vec<usize> = self.threads.values()
+            .filter(|t| t.state == ProcessState::Stopped)
+            .map(|t| t.id)
+            .collect();
+        for id in stopped {
+            self.resume_thread(id)?;
+        }
+        self.state = ProcessState::Running;
+        Ok(())
+    }
+
+    /// Executes a single instruction, temporarily lifting any enabled breaksite at the pc
+    pub fn step_instruction(&mut self) -> Result<StopReason> {
         let pc = self.get_pc();
-        if let Some(_) = self.breaksite_at_va_mut(pc) {
+        let reenable = self.breaksite_at_va(pc).is_some_and(|bs| bs.enabled());
+        if reenable {
             self.disable_breaksite_by(pc)?;
-            if ptrace::step(self.pid, None).is_err() {
+        }
+        self.stepping = true;
+        let id = self.current_thread;
+        let reason = loop {
+            let thread = self.thread_mut();
+            thread.in_syscall = false;
+            // a call a fault rule made the kernel skip completes during the step, and is
+            // given the result the rule chose afterwards
+            let injected = thread.injected_return.take();
+            if ptrace::step(thread.tid, thread.pending_signal.take()).is_err() {
+                self.stepping = false;
                 return error("could not PTRACE_SINGLESTEP");
             }
-            if wait::waitpid(self.pid, None).is_err() {
-                return error_os("could not waitpid");
+            thread.state = ProcessState::Running;
+            self.state = ProcessState::Running;
+            let reason = self.wait_on_signal();
+            if let Some((_, ret)) = injected.filter(|_| self.threads.get(&id).is_some_and(|t| t.state == ProcessState::Stopped)) {
+                if let Err(e) = self.write_thread_reg(id, RegisterId::rax, ret as u64) {
+                    self.stepping = false;
+                    return Err(e);
+                }
+            }
+            // a signal that does not stop arrived before the instruction ran, so it is
+            // delivered and the step tried again
+            let quiet = reason.as_ref().is_ok_and(|r| r.delivered_signal().is_some_and(|sig| !self.signal_policy(sig).stop));
+            if !quiet || reason.as_ref().is_ok_and(|r| r.thread != Some(id)) {
+                break reason;
             }
+        };
+        self.stepping = false;
+        let reason = reason?;
+        if reenable && self.state == ProcessState::Stopped {
             self.enable_breaksite_by(pc)?;
         }
-        let res = ptrace::cont(self.pid, None);
-        if res.is_err() {
-            return error("could not resume");
+        Ok(reason)
+    }
+
+    /// Executes one instruction, running any called function to completion
+    pub fn step_over_instruction(&mut self) -> Result<StopReason> {
+        let pc = self.get_pc();
+        let inst = disassemble(self, pc, Some(1))?;
+        let Some(inst) = inst.first().filter(|i| i.is_call()) else {
+            return self.step_instruction();
+        };
+        let return_addr = pc + inst.opcodes.len();
+        let rsp = self.regs().read_as_id::<u64>(RegisterId::rsp);
+        loop {
+            let mut reason = self.run_until(return_addr)?;
+            if self.state != ProcessState::Stopped || self.get_pc() != return_addr
+                || !matches!(reason.trap, Some(TrapType::SoftwareBreak(_))) {
+                return Ok(reason);
+            }
+            // A recursive call made from the same place returned; keep going until ours does
+            if self.regs().read_as_id::<u64>(RegisterId::rsp) >= rsp {
+                reason.trap = Some(TrapType::SingleStep);
+                return Ok(reason);
+            }
         }
-        self.state = ProcessState::Running;
-        Ok(())
+    }
+
+    /// Source-level step: runs until the pc reaches the start of a different source line.
+    /// Calls into functions with line info are entered and their prologue skipped; calls into
+    /// functions without it (PLT stubs, libraries without debug info) are stepped over. With
+    /// `over_calls` set, every call is stepped over.
+    pub fn step_line(&mut self, over_calls: bool) -> Result<StopReason> {
+        let Some((_, start)) = self.line_entry(self.get_pc()) else {
+            return error("no line information for the current location");
+        };
+        loop {
+            let pc = self.get_pc();
+            let inst = disassemble(self, pc, Some(1))?;
+            let Some(inst) = inst.first() else {
+                return error("could not disassemble at pc");
+            };
+            let is_ret = inst.flow == iced_x86::FlowControl::Return;
+            let reason = if inst.is_call() && over_calls {
+                self.step_over_instruction()?
+            } else if inst.is_call() {
+                let return_addr = pc + inst.opcodes.len();
+                let reason = self.step_instruction()?;
+                if reason.trap != Some(TrapType::SingleStep) {
+                    return Ok(reason);
+                }
+                let entry = self.get_pc();
+                let body = self.object_containing(entry)
+                    .filter(|o| o.line_entry(entry).is_some())
+                    .map(|o| o.addr_after_prologue(entry).unwrap_or(entry));
+                let target = match body {
+                    Some(body) if body == entry => return Ok(reason),
+                    Some(body) => body,
+                    None => return_addr
+                };
+                let mut reason = self.run_until(target)?;
+                if self.state == ProcessState::Stopped && self.get_pc() == target
+                    && matches!(reason.trap, Some(TrapType::SoftwareBreak(_))) {
+                    reason.trap = Some(TrapType::SingleStep);
+                    if body.is_some() {
+                        return Ok(reason);
+                    }
+                }
+                reason
+            } else {
+                self.step_instruction()?
+            };
+            if self.state != ProcessState::Stopped {
+                return Ok(reason);
+            }
+            let pc = self.get_pc();
+            if reason.trap != Some(TrapType::SingleStep) {
+                return Ok(reason);
+            }
+            match self.line_entry(pc) {
+                // Returned into the middle of the caller's line
+                Some(_) if is_ret => return Ok(reason),
+                Some((row_start, loc)) if row_start == pc && loc.line != 0 && loc != start => return Ok(reason),
+                Some(_) => {},
+                // Returned into code without line info, e.g. from main into libc
+                None if is_ret => return self.step_out(),
+                None => {}
+            }
+        }
+    }
+
+    /// The line-table row covering `va` in whichever loaded object contains it
+    pub fn line_entry(&self, va: VirtAddr) -> Option<(VirtAddr, SourceLocation)> {
+        self.object_containing(va).and_then(|o| o.line_entry(va))
+    }
+
+    /// Runs until the current function returns to its caller
+    pub fn step_out(&mut self) -> Result<StopReason> {
+        let slot = self.return_address_slot()?;
+        let return_addr = VirtAddr(u64::from_le_bytes(self.read_memory(slot, 8)?[..].try_into().unwrap()));
+        loop {
+            let mut reason = self.run_until(return_addr)?;
+            if self.state != ProcessState::Stopped || self.get_pc() != return_addr
+                || !matches!(reason.trap, Some(TrapType::SoftwareBreak(_))) {
+                return Ok(reason);
+            }
+            // A recursive call to the same function returned; keep going until our frame is popped
+            if self.regs().read_as_id::<u64>(RegisterId::rsp) > slot.0 {
+                reason.trap = Some(TrapType::SingleStep);
+                return Ok(reason);
+            }
+        }
+    }
+
+    /// Finds where the current function's return address is stored. The CFA from the
+    /// unwind information is used when there is some; otherwise only a pc sitting in the
+    /// prologue or on the ret, where the slot is known from rsp, is allowed for
+    fn return_address_slot(&self) -> Result<VirtAddr> {
+        const ENDBR64: [u8; 4] = [0xf3, 0x0f, 0x1e, 0xfa];
+        const PUSH_RBP: [u8; 1] = [0x55];
+        const MOV_RSP_RBP: [u8; 3] = [0x48, 0x89, 0xe5];
+        const RET: [u8; 1] = [0xc3];
+
+        if let Some(cfa) = current_cfa(self) {
+            return Ok(cfa - 8u64);
+        }
+        let rsp = VirtAddr(self.regs().read_as_id::<u64>(RegisterId::rsp));
+        let inst = disassemble(self, self.get_pc(), Some(1))?;
+        let Some(inst) = inst.first() else {
+            return error("could not disassemble at pc");
+        };
+        let opcodes = &inst.opcodes[..];
+        if opcodes == ENDBR64 || opcodes == PUSH_RBP || opcodes == RET {
+            Ok(rsp)
+        } else if opcodes == MOV_RSP_RBP {
+            Ok(rsp + 8u64)
+        } else {
+            error("no unwind information for the current function")
+        }
+    }
+
+    /// Continues until the next stop, making sure there is an enabled breaksite at `va`.
+    /// Any breaksite added or enabled for this is put back the way it was afterwards.
+    fn run_until(&mut self, va: VirtAddr) -> Result<StopReason> {
+        let temp = match self.breaksite_at_va(va) {
+            Some(bs) if bs.enabled() => None,
+            Some(bs) => {
+                let id = bs.id;
+                self.enable_breaksite_by(id)?;
+                Some((id, false))
+            },
+            None => {
+                let id = self.create_internal_breaksite(va)?;
+                self.enable_breaksite_by(id)?;
+                Some((id, true))
+            }
+        };
+        let thread = self.current_thread;
+        let mut res = self.resume().and_then(|_| self.wait_on_signal());
+        // other threads running the same code can hit the site first; only this one counts
+        while let Some((id, _)) = temp {
+            let other_hit = res.as_ref().is_ok_and(|reason| reason.trap == Some(TrapType::SoftwareBreak(Some(id))))
+                && self.current_thread != thread;
+            if !other_hit {
+                break;
+            }
+            res = self.resume().and_then(|_| self.wait_on_signal());
+        }
+        if let Some((id, remove)) = temp {
+            if self.state != ProcessState::Stopped {
+                if remove {
+                    self.breaksites.remove(&id);
+                }
+            } else if remove {
+                self.clear_breaksite(id)?;
+            } else {
+                self.disable_breaksite_by(id)?;
+            }
+        }
+        res
     }
 
     pub fn get_fpregs(&self) -> Result<user_fpregs_struct> {
+        Self::get_thread_fpregs(self.tid())
+    }
+
+    fn get_thread_fpregs(tid: Pid) -> Result<user_fpregs_struct> {
         //ptrace_get_data::<user_regs_struct>(Request::PTRACE_GETREGS, pid)
         let mut data = std::mem::MaybeUninit::<user_fpregs_struct>::uninit();
         let res = unsafe {
             libc::ptrace(
                 ptrace::Request::PTRACE_GETFPREGS as libc::c_uint,
-                libc::pid_t::from(self.pid),
+                libc::pid_t::from(tid),
                 std::ptr::null_mut::<user_fpregs_struct>(),
                 data.as_mut_ptr(),
             )
@@ -439,17 +2120,24 @@ impl Process {
     }
 
     fn read_all_registers(&mut self) -> Result<()> {
-        if let Ok(regs) = ptrace::getregs(self.pid) {
-            self.registers.userdata.regs = regs;
+        self.read_thread_registers(self.current_thread)
+    }
+
+    fn read_thread_registers(&mut self, id: usize) -> Result<()> {
+        let tid = self.threads[&id].tid;
+        let fpregs = Self::get_thread_fpregs(tid);
+        let regs = &mut self.threads.get_mut(&id).unwrap().registers;
+        if let Ok(gprs) = ptrace::getregs(tid) {
+            regs.userdata.regs = gprs;
         }
-        if let Ok(fpregs) = self.get_fpregs() {
-            self.registers.userdata.i387 = fpregs;
+        if let Ok(fpregs) = fpregs {
+            regs.userdata.i387 = fpregs;
         }
-        
+
         for (i, id) in DR_IDS.iter().enumerate() {
             let ri = register_by_id(*id).unwrap();
-            if let Ok(val) = ptrace::read_user(self.pid, ri.offset as *mut libc::c_void) {
-                self.registers.userdata.u_debugreg[i] = val as u64;
+            if let Ok(val) = ptrace::read_user(tid, ri.offset as *mut libc::c_void) {
+                regs.userdata.u_debugreg[i] = val as u64;
             } else {
                 return error_os("could not read debug register");
             }
@@ -458,21 +2146,73 @@ impl Process {
     }
 
     pub fn write_reg(&mut self, rv: &RValue) {
-        self.registers.write(rv);
+        self.regs_mut().write(rv);
         if rv.ri.rtype == RegisterType::Fpr {
-            let _ = self.write_fprs(self.registers.userdata.i387.clone());
+            let _ = self.write_fprs(self.regs().userdata.i387);
             return;
         }
         let offset = rv.ri.offset & !0b111;
-        let bytes = self.registers.get_clong_at(offset);
-        let _ = ptrace::write_user(self.pid, offset as *mut libc::c_void, bytes);
+        let bytes = self.regs().get_clong_at(offset);
+        let _ = ptrace::write_user(self.tid(), offset as *mut libc::c_void, bytes);
+    }
+
+    pub fn selected_frame(&self) -> usize {
+        self.selected_frame
+    }
+
+    /// Makes frame `idx` of the current backtrace the selected one and returns it
+    pub fn select_frame(&mut self, idx: usize) -> Result<StackFrame> {
+        let mut frames = backtrace(self)?;
+        if idx >= frames.len() {
+            return error(&format!("no frame {}, the stack has {} frames", idx, frames.len()));
+        }
+        self.selected_frame = idx;
+        Ok(frames.swap_remove(idx))
+    }
+
+    /// The registers of frame `idx` of the backtrace, unwound from a copy of the live ones
+    pub fn frame_registers(&self, idx: usize) -> Result<Registers> {
+        if idx == 0 {
+            return Ok(self.regs().clone());
+        }
+        match backtrace(self)?.into_iter().nth(idx) {
+            Some(frame) => Ok(frame.regs),
+            None => error(&format!("no frame {}", idx))
+        }
+    }
+
+    /// Writes a register as frame `idx` sees it. In outer frames only callee-saved registers
+    /// can be written: either the stack slot a callee saved it to, or the live register if
+    /// nothing in between has touched it.
+    pub fn write_frame_reg(&mut self, idx: usize, rv: &RValue) -> Result<()> {
+        const CALLEE_SAVED: [u16; 6] = [3, 6, 12, 13, 14, 15];
+
+        if idx == 0 {
+            self.write_reg(rv);
+            return Ok(());
+        }
+        let Some(frame) = backtrace(self)?.into_iter().nth(idx) else {
+            return error(&format!("no frame {}", idx));
+        };
+        if rv.ri.rtype != RegisterType::Gpr || rv.ri.size != 8 || rv.ri.dwarf_id < 0 {
+            return error(&format!("{} cannot be written in an outer frame", rv.ri.name));
+        }
+        let dwarf_id = rv.ri.dwarf_id as u16;
+        if let Some(slot) = frame.saved_regs.get(&dwarf_id) {
+            return self.write_memory(*slot, rv.read_as::<u64>().to_le_bytes().to_vec());
+        }
+        if CALLEE_SAVED.contains(&dwarf_id) {
+            self.write_reg(rv);
+            return Ok(());
+        }
+        error(&format!("{} was not saved in frame {}", rv.ri.name, idx))
     }
 
     pub fn write_fprs(&mut self, fpregs: user_fpregs_struct) -> Result<()> {
         let res = unsafe {
             let res = libc::ptrace(
                 ptrace::Request::PTRACE_SETFPREGS as libc::c_uint,
-                libc::pid_t::from(self.pid),
+                libc::pid_t::from(self.tid()),
                 std::ptr::null_mut::<libc::c_void>(),
                 &fpregs as *const user_fpregs_struct as *const libc::c_void,
             );
@@ -486,20 +2226,27 @@ impl Process {
     }
 
     pub fn write_gprs(&mut self, regs: &user_regs_struct) -> Result<()> {
-        if ptrace::setregs(self.pid, regs.clone()).is_err() {
+        if ptrace::setregs(self.tid(), *regs).is_err() {
             return error("error calling PTRACE_SETREGS");
         }
         Ok(())
     }
 
+    /// The current thread's registers
     pub fn regs(&self) -> &Registers {
-        &self.registers
+        &self.thread().registers
+    }
+
+    fn regs_mut(&mut self) -> &mut Registers {
+        &mut self.thread_mut().registers
     }
     
     pub fn enable_all_breaksites(&mut self) -> usize {
+        self.breakpoints.values_mut().for_each(|bp| bp.enabled = true);
+        let pid = self.tid();
         let mut out = 0;
         for (_, bs) in self.breaksites.iter_mut() {
-            if bs.enabled() && Self::disable_breaksite(self.pid, bs).is_ok() {
+            if !bs.internal && !bs.enabled() && Self::enable_breaksite(pid, bs).is_ok() {
                 out += 1;
             }
         }
@@ -507,19 +2254,22 @@ impl Process {
     }
 
     pub fn disable_all_breaksites(&mut self) -> usize {
+        self.breakpoints.values_mut().for_each(|bp| bp.enabled = false);
+        let pid = self.tid();
         let mut out = 0;
         for (_, bs) in self.breaksites.iter_mut() {
-            if !bs.enabled() && Self::enable_breaksite(self.pid, bs).is_ok() {
+            if !bs.internal && bs.enabled() && Self::disable_breaksite(pid, bs).is_ok() {
                 out += 1;
             }
         }
         out
     }
-    
+
     pub fn clear_all_breaksites(&mut self) -> usize {
-        let sz = self.breaksites.values().filter(|bs| bs.enabled()).count();
+        let sz = self.breaksites.values().filter(|bs| !bs.internal).count();
         self.disable_all_breaksites();
-        self.breaksites.clear();
+        self.breaksites.retain(|_, bs| bs.internal);
+        self.breakpoints.clear();
         sz
     }
 
@@ -527,7 +2277,12 @@ impl Process {
         let Some(mut bs) = self.breaksites.remove(&id) else {
             return error("could not find breaksite by id");
         };
-        Self::disable_breaksite(self.pid, &mut bs)
+        for bp in &bs.breakpoints {
+            if let Some(bp) = self.breakpoints.get_mut(bp) {
+                bp.sites.retain(|site| *site != id);
+            }
+        }
+        Self::disable_breaksite(self.tid(), &mut bs)
     }
 
     pub fn read_memory(&self, start: VirtAddr, count: usize) -> Result<Vec::<u8>> {
@@ -544,7 +2299,7 @@ impl Process {
                     base: ptr,
                     len: page
                 });
-                ptr += page;
+                ptr = ptr.wrapping_add(page);
                 remaining -= page;
             }
             let res = uio::process_vm_readv(
@@ -558,14 +2313,14 @@ impl Process {
                 }
                 return Ok(out)
             }
-            return error(&format!("{}", res.err().unwrap()));
+            self.memory_access_error(start, count, false, res.err().unwrap())
         }
     }
 
     pub fn read_memory_clean(&self, start: VirtAddr, count: usize) -> Result<Vec<u8>> {
         let mut mem = self.read_memory(start, count)?;
         for bp in self.breaksites.values() {
-            if bp.enabled() && bp.in_range(start, start + mem.len()) {
+            if bp.enabled() && bp.addr() >= start && bp.addr() < start + mem.len() {
                 if let Some(data) = &bp.saved_data {
                     let slice_start = (bp.va - start) as usize;
                     &mut mem[slice_start..slice_start + data.len()].copy_from_slice(&data[..]);
@@ -582,26 +2337,35 @@ impl Process {
             let write_sz = min(8, remaining);
             let mut bytes = [0u8; 8];
             if write_sz < 8 {
-                let out = self.read_memory((start + vec_idx).into(), 8);
+                let out = self.read_memory(start + vec_idx, 8);
                 if out.is_err() {
-                    return error(&format!("{}", out.err().unwrap()));
+                    return Err(out.err().unwrap());
                 }
                 &mut bytes[0..8].copy_from_slice(&out.unwrap()[0..8]);
             }
             &mut bytes[0..write_sz].copy_from_slice(&data[vec_idx..vec_idx+write_sz]);
             let res = ptrace::write(
-                self.pid,
+                self.tid(),
                 (start + vec_idx).into(),
                 i64::from_le_bytes(bytes)
             );
-            if res.is_err() {
-                return error(&format!("{}", res.err().unwrap()));
+            if let Err(errno) = res {
+                return self.memory_access_error(start + vec_idx, write_sz, true, errno);
             }
             vec_idx += write_sz;
             remaining -= write_sz;
         }
         Ok(())
     }
+
+    /// Turns a failed memory access into a message naming the unmapped address or the
+    /// region lacking permission, falling back to the raw errno
+    fn memory_access_error<T>(&self, start: VirtAddr, len: usize, write: bool, errno: nix::errno::Errno) -> Result<T> {
+        if let Ok(map) = self.memory_map() {
+            map.check_access(start, len, write)?;
+        }
+        error(&format!("{}", errno))
+    }
 }
 
 impl Drop for Process {
@@ -610,16 +2374,27 @@ impl Drop for Process {
             return;
         }
         if self.attached {
-            if self.state == ProcessState::Running {
+            if self.state == ProcessState::Running && self.autoterm {
                 let _ = signal::kill(self.pid, signal::Signal::SIGKILL);
-                let _ = wait::waitpid(self.pid, None);
+                for thread in self.threads.values() {
+                    let _ = wait::waitpid(thread.tid, Some(wait::WaitPidFlag::__WALL));
+                }
+            } else if self.state == ProcessState::Running {
+                let _ = self.stop_all_threads();
+            }
+            if !self.autoterm && self.state == ProcessState::Stopped {
+                self.remove_stop_points();
+            }
+            for thread in self.threads.values() {
+                let _ = ptrace::detach(thread.tid, thread.pending_signal);
             }
-            let _ = ptrace::detach(self.pid, None);
             let _ = signal::kill(self.pid, signal::Signal::SIGCONT);
         }
         if self.autoterm {
             let _ = signal::kill(self.pid, signal::Signal::SIGKILL);
-            let _ = wait::waitpid(self.pid, None);
+            for thread in self.threads.values() {
+                let _ = wait::waitpid(thread.tid, Some(wait::WaitPidFlag::__WALL));
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -196,6 +196,7 @@ struct _RValue {
     rtype: RegisterType
 }
 
+#[derive(Clone)]
 pub struct Registers {
     pub userdata: user
 }
@@ -0,0 +1,196 @@
+use crate::{
+    Result,
+    error,
+    parse_u64,
+    VirtAddr
+};
+
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+use std::str::FromStr;
+
+/// What to do when the inferior receives a signal, as set by gdb's `handle` command
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct SignalPolicy {
+    /// Stop and hand control back to the user
+    pub stop: bool,
+    /// Tell the user the signal arrived, even if it does not stop
+    pub print: bool,
+    /// Deliver the signal to the inferior when it is resumed
+    pub pass: bool
+}
+
+impl SignalPolicy {
+    /// Applies one of gdb's `handle` keywords. As in gdb, stopping implies printing and not
+    /// printing implies not stopping.
+    pub fn apply(&mut self, keyword: &str) -> Result<()> {
+        match keyword {
+            "stop" => { self.stop = true; self.print = true; },
+            "nostop" => self.stop = false,
+            "print" => self.print = true,
+            "noprint" => { self.print = false; self.stop = false; },
+            "pass" | "noignore" => self.pass = true,
+            "nopass" | "ignore" => self.pass = false,
+            _ => return error(&format!("unknown signal action {} (expected [no]stop, [no]print or [no]pass)", keyword))
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for SignalPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |b: bool| if b { "yes" } else { "no" };
+        write!(f, "{:<6}{:<7}{}", yes_no(self.stop), yes_no(self.print), yes_no(self.pass))
+    }
+}
+
+/// gdb's defaults: signals a debugger uses itself are not passed on, and those programs
+/// receive routinely in normal operation go by without stopping
+pub fn default_signal_policy(sig: Signal) -> SignalPolicy {
+    match sig {
+        Signal::SIGTRAP | Signal::SIGINT => SignalPolicy { stop: true, print: true, pass: false },
+        Signal::SIGCHLD | Signal::SIGWINCH | Signal::SIGURG | Signal::SIGPROF | Signal::SIGALRM
+            | Signal::SIGVTALRM | Signal::SIGIO => SignalPolicy { stop: false, print: false, pass: true },
+        _ => SignalPolicy { stop: true, print: true, pass: true }
+    }
+}
+
+/// Reads a signal given by name, with or without the SIG prefix, or by number
+pub fn parse_signal(text: &str) -> Result<Signal> {
+    if let Ok(num) = parse_u64(text) {
+        return match Signal::try_from(num as i32) {
+            Ok(sig) => Ok(sig),
+            Err(_) => error(&format!("no signal numbered {}", num))
+        };
+    }
+    let upper = text.to_uppercase();
+    let name = if upper.starts_with("SIG") { upper } else { format!("SIG{}", upper) };
+    match Signal::from_str(&name) {
+        Ok(sig) => Ok(sig),
+        Err(_) => error(&format!("unknown signal {}", text))
+    }
+}
+
+/// The si_code values any signal can carry, for signals sent rather than raised by a fault
+const GENERIC_CODES: &[(i32, &str)] = &[
+    (0, "SI_USER"),
+    (0x80, "SI_KERNEL"),
+    (-1, "SI_QUEUE"),
+    (-2, "SI_TIMER"),
+    (-3, "SI_MESGQ"),
+    (-4, "SI_ASYNCIO"),
+    (-5, "SI_SIGIO"),
+    (-6, "SI_TKILL")
+];
+
+const SEGV_CODES: &[(i32, &str)] = &[
+    (1, "SEGV_MAPERR"),
+    (2, "SEGV_ACCERR"),
+    (3, "SEGV_BNDERR"),
+    (4, "SEGV_PKUERR")
+];
+
+const BUS_CODES: &[(i32, &str)] = &[
+    (1, "BUS_ADRALN"),
+    (2, "BUS_ADRERR"),
+    (3, "BUS_OBJERR"),
+    (4, "BUS_MCEERR_AR"),
+    (5, "BUS_MCEERR_AO")
+];
+
+const FPE_CODES: &[(i32, &str)] = &[
+    (1, "FPE_INTDIV"),
+    (2, "FPE_INTOVF"),
+    (3, "FPE_FLTDIV"),
+    (4, "FPE_FLTOVF"),
+    (5, "FPE_FLTUND"),
+    (6, "FPE_FLTRES"),
+    (7, "FPE_FLTINV"),
+    (8, "FPE_FLTSUB")
+];
+
+const ILL_CODES: &[(i32, &str)] = &[
+    (1, "ILL_ILLOPC"),
+    (2, "ILL_ILLOPN"),
+    (3, "ILL_ILLADR"),
+    (4, "ILL_ILLTRP"),
+    (5, "ILL_PRVOPC"),
+    (6, "ILL_PRVREG"),
+    (7, "ILL_COPROC"),
+    (8, "ILL_BADSTK")
+];
+
+const TRAP_CODES: &[(i32, &str)] = &[
+    (1, "TRAP_BRKPT"),
+    (2, "TRAP_TRACE"),
+    (3, "TRAP_BRANCH"),
+    (4, "TRAP_HWBKPT")
+];
+
+const CLD_CODES: &[(i32, &str)] = &[
+    (1, "CLD_EXITED"),
+    (2, "CLD_KILLED"),
+    (3, "CLD_DUMPED"),
+    (4, "CLD_TRAPPED"),
+    (5, "CLD_STOPPED"),
+    (6, "CLD_CONTINUED")
+];
+
+/// What the kernel says about a signal the inferior stopped with, from PTRACE_GETSIGINFO
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct SignalInfo {
+    pub signal: Signal,
+    /// si_code: how the signal came about
+    pub code: i32,
+    /// The address that faulted, for signals raised by the hardware
+    pub addr: Option<VirtAddr>,
+    /// The pid and uid of the process that sent the signal, if one did
+    pub sender: Option<(Pid, u32)>
+}
+
+impl SignalInfo {
+    pub fn from_siginfo(info: &libc::siginfo_t) -> Option<SignalInfo> {
+        let signal = Signal::try_from(info.si_signo).ok()?;
+        let code = info.si_code;
+        let faults = matches!(signal, Signal::SIGSEGV | Signal::SIGBUS | Signal::SIGFPE | Signal::SIGILL);
+        // codes above 0 other than SI_KERNEL come from the kernel and use the fault
+        // layout of the union; those from kill and friends use the sender layout
+        let addr = (faults && code > 0 && code != libc::SI_KERNEL)
+            .then(|| VirtAddr(unsafe { info.si_addr() } as u64));
+        let sent = matches!(code, libc::SI_USER | libc::SI_QUEUE | libc::SI_TKILL) || signal == Signal::SIGCHLD;
+        let sender = sent.then(|| unsafe { (Pid::from_raw(info.si_pid()), info.si_uid()) });
+        Some(SignalInfo { signal, code, addr, sender })
+    }
+
+    /// The name of si_code, such as SEGV_MAPERR, if it is one we know
+    pub fn code_name(&self) -> Option<&'static str> {
+        let specific = match self.signal {
+            Signal::SIGSEGV => SEGV_CODES,
+            Signal::SIGBUS => BUS_CODES,
+            Signal::SIGFPE => FPE_CODES,
+            Signal::SIGILL => ILL_CODES,
+            Signal::SIGTRAP => TRAP_CODES,
+            Signal::SIGCHLD => CLD_CODES,
+            _ => &[]
+        };
+        let table = if self.code > 0 && self.code != libc::SI_KERNEL { specific } else { GENERIC_CODES };
+        table.iter().find(|(code, _)| *code == self.code).map(|(_, name)| *name)
+    }
+}
+
+impl std::fmt::Display for SignalInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code_name() {
+            Some(name) => write!(f, "{}: {}", self.signal, name)?,
+            None => write!(f, "{}: si_code {}", self.signal, self.code)?
+        }
+        if let Some(addr) = self.addr {
+            write!(f, " at address {:#x}", addr)?;
+        }
+        if let Some((pid, uid)) = self.sender {
+            write!(f, " from pid {} uid {}", pid, uid)?;
+        }
+        Ok(())
+    }
+}
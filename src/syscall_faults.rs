@@ -0,0 +1,194 @@
+use crate::{
+    Result,
+    error,
+    parse_u64,
+    parse_syscall,
+    syscall_name
+};
+
+use nix::errno::Errno;
+
+use std::str::FromStr;
+
+/// Which of the calls a fault rule matches it fires on, counting from 1: only the `first`,
+/// or with a `step`, the first and every step-th one after it. Written as in strace's
+/// `when=`: `3`, `3+` or `3+3`.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Occurrence {
+    pub first: u64,
+    pub step: Option<u64>
+}
+
+impl Occurrence {
+    /// Every call
+    pub const ALWAYS: Occurrence = Occurrence { first: 1, step: Some(1) };
+
+    pub fn includes(&self, n: u64) -> bool {
+        match self.step {
+            Some(step) => n >= self.first && (n - self.first).is_multiple_of(step),
+            None => n == self.first
+        }
+    }
+}
+
+impl FromStr for Occurrence {
+    type Err = crate::GadbErr;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (first, step) = match s.split_once('+') {
+            Some((first, "")) => (first, Some(1)),
+            Some((first, step)) => (first, Some(parse_u64(step)?)),
+            None => (s, None)
+        };
+        let first = parse_u64(first)?;
+        if first == 0 || step == Some(0) {
+            return error("occurrences are counted from 1");
+        }
+        Ok(Occurrence { first, step })
+    }
+}
+
+impl std::fmt::Display for Occurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.step {
+            None => write!(f, "{}", self.first),
+            Some(1) => write!(f, "{}+", self.first),
+            Some(step) => write!(f, "{}+{}", self.first, step)
+        }
+    }
+}
+
+/// What a fault rule does to a call it fires on
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FaultAction {
+    /// Skip the call and fail it with this error
+    Error(Errno),
+    /// Let the call run, then replace what it returns
+    Return(i64),
+    /// Rewrite an argument register before the call runs
+    SetArg(usize, u64)
+}
+
+impl std::fmt::Display for FaultAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaultAction::Error(errno) => write!(f, "error={:?}", errno),
+            FaultAction::Return(ret) => write!(f, "retval={}", ret),
+            FaultAction::SetArg(idx, val) => write!(f, "arg{}={:#x}", idx, val)
+        }
+    }
+}
+
+/// A rule for tampering with syscalls: which syscall, optionally only when an argument has
+/// a given value, on which of the matching calls, and what to do to it
+#[derive(PartialEq, Clone, Debug)]
+pub struct SyscallFault {
+    pub syscall: u64,
+    /// Only calls whose argument at this index has this value are matched
+    pub arg_match: Option<(usize, u64)>,
+    pub when: Occurrence,
+    pub action: FaultAction,
+    /// How many calls the rule has matched so far
+    pub matched: u64
+}
+
+impl SyscallFault {
+    pub fn new(syscall: u64, action: FaultAction) -> SyscallFault {
+        SyscallFault { syscall, arg_match: None, when: Occurrence::ALWAYS, action, matched: 0 }
+    }
+
+    /// Counts a syscall entry against the rule and says whether the rule fires on it
+    pub(crate) fn fires(&mut self, id: u64, args: &[u64; 6]) -> bool {
+        if id != self.syscall || self.arg_match.is_some_and(|(idx, val)| args[idx] != val) {
+            return false;
+        }
+        self.matched += 1;
+        self.when.includes(self.matched)
+    }
+}
+
+impl std::fmt::Display for SyscallFault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match syscall_name(self.syscall) {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "{}", self.syscall)?
+        }
+        if let Some((idx, val)) = self.arg_match {
+            write!(f, " arg{}=={:#x}", idx, val)?;
+        }
+        if self.when != Occurrence::ALWAYS {
+            write!(f, " when={}", self.when)?;
+        }
+        write!(f, " {}", self.action)
+    }
+}
+
+/// Reads an error given by name, such as EINTR, or by number
+pub fn parse_errno(text: &str) -> Result<Errno> {
+    if let Ok(num) = parse_u64(text) {
+        return match i32::try_from(num).map(Errno::from_raw) {
+            Ok(Errno::UnknownErrno) | Err(_) => error(&format!("no error numbered {}", num)),
+            Ok(errno) => Ok(errno)
+        };
+    }
+    let upper = text.to_uppercase();
+    (1..4096).map(Errno::from_raw)
+        .find(|errno| *errno != Errno::UnknownErrno && format!("{:?}", errno) == upper)
+        .map_or_else(|| error(&format!("unknown error {}", text)), Ok)
+}
+
+/// Reads a value a syscall returns, which may be negative
+fn parse_retval(text: &str) -> Result<i64> {
+    let ret = match text.strip_prefix('-') {
+        Some(neg) => 0i64.checked_sub_unsigned(parse_u64(neg)?),
+        None => i64::try_from(parse_u64(text)?).ok()
+    };
+    match ret {
+        Some(ret) => Ok(ret),
+        None => error(&format!("return value {} is out of range", text))
+    }
+}
+
+fn parse_arg_index(text: &str) -> Result<usize> {
+    match text.strip_prefix("arg").and_then(|idx| idx.parse::<usize>().ok()) {
+        Some(idx) if idx < 6 => Ok(idx),
+        _ => error(&format!("expected arg0 to arg5, got {}", text))
+    }
+}
+
+/// Reads a rule written as `syscall [argN==value] [when=occurrence] action`, where the
+/// action is `error=ERRNO`, `retval=value` or `argN=value`
+pub fn parse_syscall_fault(words: &[&str]) -> Result<SyscallFault> {
+    let Some((syscall, rest)) = words.split_first() else {
+        return error("expected a syscall");
+    };
+    let syscall = parse_syscall(syscall)?;
+    let mut arg_match = None;
+    let mut when = Occurrence::ALWAYS;
+    let mut action = None;
+    for word in rest {
+        if let Some((arg, val)) = word.split_once("==") {
+            arg_match = Some((parse_arg_index(arg)?, parse_u64(val)?));
+            continue;
+        }
+        let Some((key, val)) = word.split_once('=') else {
+            return error(&format!("expected key=value, got {}", word));
+        };
+        let parsed = match key {
+            "when" => {
+                when = val.parse::<Occurrence>()?;
+                continue;
+            },
+            "error" => FaultAction::Error(parse_errno(val)?),
+            "retval" => FaultAction::Return(parse_retval(val)?),
+            _ => FaultAction::SetArg(parse_arg_index(key)?, parse_u64(val)?)
+        };
+        if action.replace(parsed).is_some() {
+            return error("a rule takes one action");
+        }
+    }
+    let Some(action) = action else {
+        return error("expected an action: error=ERRNO, retval=value or argN=value");
+    };
+    Ok(SyscallFault { syscall, arg_match, when, action, matched: 0 })
+}
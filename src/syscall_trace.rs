@@ -0,0 +1,504 @@
+use crate::{
+    Process,
+    VirtAddr,
+    SyscallInfo,
+    SyscallData,
+    syscall_name
+};
+
+use nix::errno::Errno;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+/// How many bytes of a string or buffer argument are shown, as with strace's default -s 32
+const MAX_STRING: usize = 32;
+/// How many elements of an argv array are shown
+const MAX_ARGV: usize = 32;
+
+/// How a syscall argument is shown in a trace
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum ArgType {
+    Int,
+    Uint,
+    Hex,
+    /// A pointer shown as an address, or NULL
+    Ptr,
+    Fd,
+    /// A directory fd for the *at calls, which may be AT_FDCWD
+    DirFd,
+    /// A NUL-terminated string the program passes in
+    Str,
+    /// A string the kernel fills in, shown once the call returns
+    OutStr,
+    /// A buffer the program passes in, whose length is the given argument
+    InBuf(usize),
+    /// A buffer the kernel fills in, whose length is the return value
+    OutBuf,
+    /// A NULL-terminated array of strings, such as execve's argv
+    Argv,
+    /// A NULL-terminated array of strings only counted, such as execve's envp
+    Envp,
+    /// The two fds pipe and pipe2 fill in
+    FdPair,
+    /// A struct timespec the program passes in
+    Timespec,
+    /// A struct timespec the kernel fills in
+    OutTimespec,
+    /// A pointer to some other struct, shown as an address with the struct's name
+    Struct(&'static str),
+    OpenFlags,
+    Mode,
+    Prot,
+    MmapFlags,
+    AtFlags,
+    AccessMode,
+    Whence,
+    SignalNumber
+}
+
+impl ArgType {
+    /// Whether the argument only means something once the call has returned
+    fn is_output(&self) -> bool {
+        matches!(self, ArgType::OutStr | ArgType::OutBuf | ArgType::FdPair | ArgType::OutTimespec)
+    }
+}
+
+/// How a syscall's return value is shown in a trace
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum RetType {
+    Int,
+    /// An address, as returned by mmap and brk
+    Hex
+}
+
+use ArgType::*;
+
+/// The argument types of the syscalls traces know about. Other syscalls have all six of
+/// their argument registers shown in hex.
+fn signature(name: &str) -> Option<(&'static [ArgType], RetType)> {
+    let args: &'static [ArgType] = match name {
+        "read" => &[Fd, OutBuf, Uint],
+        "write" => &[Fd, InBuf(2), Uint],
+        "open" => &[Str, OpenFlags, Mode],
+        "close" => &[Fd],
+        "stat" | "lstat" => &[Str, Struct("stat")],
+        "fstat" => &[Fd, Struct("stat")],
+        "poll" => &[Struct("pollfd"), Uint, Int],
+        "lseek" => &[Fd, Int, Whence],
+        "mmap" => return Some((&[Ptr, Uint, Prot, MmapFlags, Fd, Hex], RetType::Hex)),
+        "mprotect" => &[Ptr, Uint, Prot],
+        "munmap" => &[Ptr, Uint],
+        "madvise" => &[Ptr, Uint, Int],
+        "brk" => return Some((&[Ptr], RetType::Hex)),
+        "rt_sigaction" => &[SignalNumber, Struct("sigaction"), Struct("sigaction"), Uint],
+        "rt_sigprocmask" => &[Int, Struct("sigset_t"), Struct("sigset_t"), Uint],
+        "rt_sigreturn" => &[],
+        "ioctl" => &[Fd, Hex, Ptr],
+        "pread64" => &[Fd, OutBuf, Uint, Int],
+        "pwrite64" => &[Fd, InBuf(2), Uint, Int],
+        "readv" | "writev" => &[Fd, Struct("iovec"), Uint],
+        "access" => &[Str, AccessMode],
+        "pipe" => &[FdPair],
+        "pipe2" => &[FdPair, OpenFlags],
+        "sched_yield" | "pause" | "fork" | "vfork" | "getpid" | "getppid" | "gettid" | "getuid"
+            | "geteuid" | "getgid" | "getegid" | "setsid" | "getpgrp" => &[],
+        "dup" => &[Fd],
+        "dup2" => &[Fd, Fd],
+        "dup3" => &[Fd, Fd, OpenFlags],
+        "nanosleep" => &[Timespec, Ptr],
+        "clock_nanosleep" => &[Int, Hex, Timespec, Ptr],
+        "clock_gettime" => &[Int, OutTimespec],
+        "socket" => &[Int, Int, Int],
+        "connect" | "bind" => &[Fd, Struct("sockaddr"), Uint],
+        "clone" => &[Hex, Ptr, Ptr, Ptr, Hex],
+        "clone3" => &[Struct("clone_args"), Uint],
+        "execve" => &[Str, Argv, Envp],
+        "exit" | "exit_group" => &[Int],
+        "wait4" => &[Int, Ptr, Hex, Ptr],
+        "kill" => &[Int, SignalNumber],
+        "tkill" => &[Int, SignalNumber],
+        "tgkill" => &[Int, Int, SignalNumber],
+        "uname" => &[Struct("utsname")],
+        "fcntl" => &[Fd, Int, Hex],
+        "getcwd" => &[OutStr, Uint],
+        "chdir" | "rmdir" | "unlink" => &[Str],
+        "fchdir" => &[Fd],
+        "rename" => &[Str, Str],
+        "mkdir" => &[Str, Mode],
+        "readlink" => &[Str, OutBuf, Uint],
+        "getdents64" => &[Fd, Struct("linux_dirent64"), Uint],
+        "arch_prctl" => &[Hex, Hex],
+        "set_tid_address" => &[Ptr],
+        "futex" => &[Ptr, Int, Int, Ptr],
+        "openat" => &[DirFd, Str, OpenFlags, Mode],
+        "mkdirat" => &[DirFd, Str, Mode],
+        "newfstatat" => &[DirFd, Str, Struct("stat"), AtFlags],
+        "unlinkat" => &[DirFd, Str, AtFlags],
+        "readlinkat" => &[DirFd, Str, OutBuf, Uint],
+        "faccessat" => &[DirFd, Str, AccessMode],
+        "faccessat2" => &[DirFd, Str, AccessMode, AtFlags],
+        "statx" => &[DirFd, Str, AtFlags, Hex, Struct("statx")],
+        "set_robust_list" => &[Ptr, Uint],
+        "prlimit64" => &[Int, Int, Struct("rlimit"), Struct("rlimit")],
+        "getrandom" => &[OutBuf, Uint, Hex],
+        "rseq" => &[Ptr, Uint, Hex, Hex],
+        "close_range" => &[Fd, Fd, Hex],
+        _ => return None
+    };
+    Some((args, RetType::Int))
+}
+
+/// One syscall made by a traced inferior
+#[derive(PartialEq, Clone, Debug)]
+pub struct SyscallRecord {
+    /// The user-visible id of the thread that made the call
+    pub thread: usize,
+    pub tid: Pid,
+    pub id: u64,
+    pub args: [u64; 6],
+    /// What the call returned, or None if the inferior went away before it did
+    pub ret: Option<i64>,
+    /// The arguments decoded by type, as strace would show them
+    pub decoded_args: Vec<String>
+}
+
+impl SyscallRecord {
+    pub fn name(&self) -> Option<&'static str> {
+        syscall_name(self.id)
+    }
+
+    /// The error the call failed with, if it did
+    pub fn errno(&self) -> Option<Errno> {
+        self.ret.filter(|ret| (-4095..0).contains(ret)).map(|ret| Errno::from_raw(-ret as i32))
+    }
+}
+
+impl std::fmt::Display for SyscallRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}({})", name, self.decoded_args.join(", "))?,
+            None => write!(f, "syscall_{}({})", self.id, self.decoded_args.join(", "))?
+        }
+        let ret_type = self.name().and_then(signature).map(|(_, ret)| ret).unwrap_or(RetType::Int);
+        match (self.ret, self.errno()) {
+            (None, _) => write!(f, " = ?"),
+            (Some(ret), _) if restart_error(ret).is_some() => {
+                let (name, desc) = restart_error(ret).unwrap();
+                write!(f, " = ? {} ({})", name, desc)
+            },
+            (Some(_), Some(errno)) => write!(f, " = -1 {:?} ({})", errno, errno.desc()),
+            (Some(ret), None) if ret_type == RetType::Hex => write!(f, " = {:#x}", ret),
+            (Some(ret), None) => write!(f, " = {}", ret)
+        }
+    }
+}
+
+/// The errors the kernel uses to restart a call interrupted by a signal. Programs never
+/// see them, but a tracer does when the interruption is reported.
+fn restart_error(ret: i64) -> Option<(&'static str, &'static str)> {
+    match -ret {
+        512 => Some(("ERESTARTSYS", "To be restarted if SA_RESTART is set")),
+        513 => Some(("ERESTARTNOINTR", "To be restarted")),
+        514 => Some(("ERESTARTNOHAND", "To be restarted if no handler")),
+        516 => Some(("ERESTART_RESTARTBLOCK", "Interrupted by signal")),
+        _ => None
+    }
+}
+
+/// Where `trace syscalls` is sending its records. Calls are decoded on entry, when their
+/// inputs are still in place, and written out on exit along with whatever the kernel
+/// filled in.
+pub(crate) struct SyscallTrace {
+    output: Option<Box<dyn Write>>,
+    records: Vec<SyscallRecord>,
+    /// Calls each thread has entered but not yet returned from, keyed by thread id
+    unfinished: HashMap<usize, SyscallRecord>,
+    /// Once the inferior has had more than one thread, every line says which made the call
+    show_threads: bool
+}
+
+impl std::fmt::Debug for SyscallTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SyscallTrace")
+            .field("output", &self.output.is_some())
+            .field("records", &self.records)
+            .field("unfinished", &self.unfinished)
+            .field("show_threads", &self.show_threads)
+            .finish()
+    }
+}
+
+impl SyscallTrace {
+    pub(crate) fn new(output: Option<Box<dyn Write>>) -> SyscallTrace {
+        SyscallTrace { output, records: Vec::new(), unfinished: HashMap::new(), show_threads: false }
+    }
+
+    /// Takes note of a syscall stop of the given thread, whose registers are up to date
+    pub(crate) fn record(&mut self, p: &Process, thread: usize, tid: Pid, info: &SyscallInfo) {
+        self.show_threads |= p.threads().len() > 1;
+        match info.data {
+            SyscallData::Args(args) => {
+                // a call whose exit stop never came, as when a thread execs
+                if let Some(old) = self.unfinished.remove(&thread) {
+                    self.emit(old);
+                }
+                let decoded_args = match syscall_name(info.id).and_then(signature) {
+                    Some((types, _)) => types.iter().enumerate()
+                        .map(|(i, ty)| if ty.is_output() { String::new() } else { decode_arg(p, *ty, args[i], &args, None) })
+                        .collect(),
+                    None => args.iter().map(|arg| format!("{:#x}", arg)).collect()
+                };
+                self.unfinished.insert(thread, SyscallRecord { thread, tid, id: info.id, args, ret: None, decoded_args });
+            },
+            SyscallData::Ret(ret) => {
+                let Some(mut record) = self.unfinished.remove(&thread) else {
+                    return;
+                };
+                if let Some((types, _)) = syscall_name(record.id).and_then(signature) {
+                    for (i, ty) in types.iter().enumerate().filter(|(_, ty)| ty.is_output()) {
+                        record.decoded_args[i] = decode_arg(p, *ty, record.args[i], &record.args, Some(ret));
+                    }
+                }
+                record.ret = Some(ret);
+                self.emit(record);
+            }
+        }
+    }
+
+    /// Records the calls that will never return because the inferior is gone
+    pub(crate) fn finish(&mut self) {
+        let mut unfinished: Vec<SyscallRecord> = self.unfinished.drain().map(|(_, record)| record).collect();
+        unfinished.sort_by_key(|record| record.thread);
+        for record in unfinished {
+            self.emit(record);
+        }
+    }
+
+    fn emit(&mut self, record: SyscallRecord) {
+        match &mut self.output {
+            Some(out) => {
+                let _ = if self.show_threads {
+                    writeln!(out, "[thread {}] {}", record.thread, record)
+                } else {
+                    writeln!(out, "{}", record)
+                };
+                let _ = out.flush();
+            },
+            None => self.records.push(record)
+        }
+    }
+
+    pub(crate) fn take_records(&mut self) -> Vec<SyscallRecord> {
+        std::mem::take(&mut self.records)
+    }
+}
+
+fn decode_arg(p: &Process, ty: ArgType, val: u64, args: &[u64; 6], ret: Option<i64>) -> String {
+    let failed = ret.is_some_and(|ret| ret < 0);
+    match ty {
+        Int => (val as i64).to_string(),
+        Uint => val.to_string(),
+        Hex if val == 0 => "0".to_string(),
+        Hex => format!("{:#x}", val),
+        Fd => (val as i32).to_string(),
+        DirFd if val as i32 == libc::AT_FDCWD => "AT_FDCWD".to_string(),
+        DirFd => (val as i32).to_string(),
+        OpenFlags => open_flags(val),
+        Mode => format!("0{:o}", val),
+        Prot => flags(val, PROT_FLAGS, "PROT_NONE"),
+        MmapFlags => flags(val, MMAP_FLAGS, "0"),
+        AtFlags => flags(val, AT_FLAGS, "0"),
+        AccessMode => flags(val, ACCESS_MODES, "F_OK"),
+        Whence => match val as i32 {
+            libc::SEEK_SET => "SEEK_SET".to_string(),
+            libc::SEEK_CUR => "SEEK_CUR".to_string(),
+            libc::SEEK_END => "SEEK_END".to_string(),
+            _ => val.to_string()
+        },
+        SignalNumber => match Signal::try_from(val as i32) {
+            Ok(sig) => sig.to_string(),
+            Err(_) => val.to_string()
+        },
+        _ if val == 0 => "NULL".to_string(),
+        Ptr => format!("{:#x}", val),
+        Struct(name) => format!("{:#x} /* struct {} */", val, name),
+        OutStr | OutBuf | FdPair | OutTimespec if failed => format!("{:#x}", val),
+        Str | OutStr => read_string(p, val),
+        InBuf(len_arg) => read_buffer(p, val, args[len_arg] as usize),
+        OutBuf => read_buffer(p, val, ret.unwrap_or(0) as usize),
+        Argv => read_argv(p, val),
+        Envp => format!("{:#x} /* {} vars */", val, read_pointers(p, val, usize::MAX).len()),
+        FdPair => match read_u32s(p, val, 2) {
+            Some(fds) => format!("[{}, {}]", fds[0] as i32, fds[1] as i32),
+            None => format!("{:#x}", val)
+        },
+        Timespec | OutTimespec => match p.read_memory(VirtAddr(val), 16) {
+            Ok(mem) => format!("{{tv_sec={}, tv_nsec={}}}",
+                i64::from_le_bytes(mem[..8].try_into().unwrap()), i64::from_le_bytes(mem[8..].try_into().unwrap())),
+            _ => format!("{:#x}", val)
+        }
+    }
+}
+
+const PROT_FLAGS: &[(i32, &str)] = &[
+    (libc::PROT_READ, "PROT_READ"),
+    (libc::PROT_WRITE, "PROT_WRITE"),
+    (libc::PROT_EXEC, "PROT_EXEC")
+];
+
+const MMAP_FLAGS: &[(i32, &str)] = &[
+    (libc::MAP_SHARED, "MAP_SHARED"),
+    (libc::MAP_PRIVATE, "MAP_PRIVATE"),
+    (libc::MAP_FIXED, "MAP_FIXED"),
+    (libc::MAP_ANONYMOUS, "MAP_ANONYMOUS"),
+    (libc::MAP_GROWSDOWN, "MAP_GROWSDOWN"),
+    (libc::MAP_DENYWRITE, "MAP_DENYWRITE"),
+    (libc::MAP_NORESERVE, "MAP_NORESERVE"),
+    (libc::MAP_POPULATE, "MAP_POPULATE"),
+    (libc::MAP_STACK, "MAP_STACK"),
+    (libc::MAP_FIXED_NOREPLACE, "MAP_FIXED_NOREPLACE")
+];
+
+const AT_FLAGS: &[(i32, &str)] = &[
+    (libc::AT_SYMLINK_NOFOLLOW, "AT_SYMLINK_NOFOLLOW"),
+    (libc::AT_REMOVEDIR, "AT_REMOVEDIR"),
+    (libc::AT_SYMLINK_FOLLOW, "AT_SYMLINK_FOLLOW"),
+    (libc::AT_NO_AUTOMOUNT, "AT_NO_AUTOMOUNT"),
+    (libc::AT_EMPTY_PATH, "AT_EMPTY_PATH")
+];
+
+const ACCESS_MODES: &[(i32, &str)] = &[
+    (libc::R_OK, "R_OK"),
+    (libc::W_OK, "W_OK"),
+    (libc::X_OK, "X_OK")
+];
+
+/// Every open flag but the access mode. O_SYNC and O_TMPFILE come before the flags they
+/// include so those are not shown twice.
+const OPEN_FLAGS: &[(i32, &str)] = &[
+    (libc::O_SYNC, "O_SYNC"),
+    (libc::O_TMPFILE, "O_TMPFILE"),
+    (libc::O_CREAT, "O_CREAT"),
+    (libc::O_EXCL, "O_EXCL"),
+    (libc::O_NOCTTY, "O_NOCTTY"),
+    (libc::O_TRUNC, "O_TRUNC"),
+    (libc::O_APPEND, "O_APPEND"),
+    (libc::O_NONBLOCK, "O_NONBLOCK"),
+    (libc::O_DSYNC, "O_DSYNC"),
+    (libc::O_DIRECT, "O_DIRECT"),
+    (libc::O_LARGEFILE, "O_LARGEFILE"),
+    (libc::O_DIRECTORY, "O_DIRECTORY"),
+    (libc::O_NOFOLLOW, "O_NOFOLLOW"),
+    (libc::O_NOATIME, "O_NOATIME"),
+    (libc::O_CLOEXEC, "O_CLOEXEC"),
+    (libc::O_PATH, "O_PATH")
+];
+
+/// Names the bits set in `val`, with any left over in hex, or `zero` if none are set
+fn flags(val: u64, table: &[(i32, &str)], zero: &str) -> String {
+    let mut rest = val;
+    let mut names = Vec::new();
+    for &(bit, name) in table {
+        // some flags, like O_LARGEFILE, are 0 on x86_64
+        let bit = bit as u64;
+        if bit != 0 && rest & bit == bit {
+            names.push(name.to_string());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        names.push(format!("{:#x}", rest));
+    }
+    if names.is_empty() {
+        return zero.to_string();
+    }
+    names.join("|")
+}
+
+fn open_flags(val: u64) -> String {
+    let mode = match val as i32 & libc::O_ACCMODE {
+        libc::O_RDONLY => "O_RDONLY".to_string(),
+        libc::O_WRONLY => "O_WRONLY".to_string(),
+        libc::O_RDWR => "O_RDWR".to_string(),
+        other => format!("{:#x}", other)
+    };
+    match val & !(libc::O_ACCMODE as u64) {
+        0 => mode,
+        rest => format!("{}|{}", mode, flags(rest, OPEN_FLAGS, "0"))
+    }
+}
+
+/// Quotes bytes the way strace does, with C escapes for anything unprintable
+fn quote(bytes: &[u8], truncated: bool) -> String {
+    let mut s = String::from("\"");
+    for &b in bytes {
+        match b {
+            b'\n' => s.push_str("\\n"),
+            b'\t' => s.push_str("\\t"),
+            b'\r' => s.push_str("\\r"),
+            b'"' => s.push_str("\\\""),
+            b'\\' => s.push_str("\\\\"),
+            0x20..=0x7e => s.push(b as char),
+            _ => s.push_str(&format!("\\{:o}", b))
+        }
+    }
+    s.push('"');
+    if truncated {
+        s.push_str("...");
+    }
+    s
+}
+
+fn read_string(p: &Process, addr: u64) -> String {
+    // a read running into an unmapped page comes back short and padded with zeros, which
+    // ends the string where the mapping does
+    let Ok(bytes) = p.read_memory(VirtAddr(addr), MAX_STRING + 1) else {
+        return format!("{:#x}", addr);
+    };
+    match bytes.iter().position(|&b| b == 0) {
+        Some(end) => quote(&bytes[..end], false),
+        None => quote(&bytes[..MAX_STRING], true)
+    }
+}
+
+fn read_buffer(p: &Process, addr: u64, len: usize) -> String {
+    match p.read_memory(VirtAddr(addr), len.min(MAX_STRING)) {
+        Ok(bytes) => quote(&bytes, len > MAX_STRING),
+        Err(_) => format!("{:#x}", addr)
+    }
+}
+
+/// Reads pointers up to the first NULL, or `max` of them
+fn read_pointers(p: &Process, addr: u64, max: usize) -> Vec<u64> {
+    let mut pointers = Vec::new();
+    let mut at = addr;
+    while pointers.len() < max {
+        let Ok(mem) = p.read_memory(VirtAddr(at), 8) else {
+            break;
+        };
+        let pointer = u64::from_le_bytes(mem.try_into().unwrap());
+        if pointer == 0 {
+            break;
+        }
+        pointers.push(pointer);
+        at += 8;
+    }
+    pointers
+}
+
+fn read_argv(p: &Process, addr: u64) -> String {
+    let pointers = read_pointers(p, addr, MAX_ARGV + 1);
+    let mut items: Vec<String> = pointers.iter().take(MAX_ARGV).map(|s| read_string(p, *s)).collect();
+    if pointers.len() > MAX_ARGV {
+        items.push("...".to_string());
+    }
+    format!("[{}]", items.join(", "))
+}
+
+fn read_u32s(p: &Process, addr: u64, count: usize) -> Option<Vec<u32>> {
+    let mem = p.read_memory(VirtAddr(addr), count * 4).ok()?;
+    Some(mem.chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect())
+}
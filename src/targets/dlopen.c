@@ -0,0 +1,12 @@
+#include <dlfcn.h>
+
+int main() {
+    void *handle = dlopen(PLUGIN_PATH, RTLD_NOW);
+    if (!handle) {
+        return 1;
+    }
+    int (*answer)(int) = (int (*)(int))dlsym(handle, "plugin_answer");
+    int result = answer(20);
+    dlclose(handle);
+    return result;
+}
@@ -0,0 +1,16 @@
+#include <errno.h>
+#include <unistd.h>
+
+int main() {
+    int interrupted = 0;
+    int empty = 0;
+    for (int i = 0; i < 6; i++) {
+        ssize_t n = write(STDOUT_FILENO, "x", 1);
+        if (n < 0 && errno == EINTR) {
+            interrupted += 1;
+        } else if (n == 0) {
+            empty += 1;
+        }
+    }
+    return interrupted + 10 * empty + 100 * (getppid() == 4242);
+}
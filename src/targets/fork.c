@@ -0,0 +1,19 @@
+#include <sys/wait.h>
+#include <unistd.h>
+
+int child_work(int x) {
+    return x + 1;
+}
+
+int main() {
+    pid_t pid = fork();
+    if (pid == 0) {
+        return child_work(2);
+    }
+    int status;
+    waitpid(pid, &status, 0);
+    if (!WIFEXITED(status)) {
+        return 1;
+    }
+    return WEXITSTATUS(status) + 10;
+}
@@ -0,0 +1,16 @@
+#include <dlfcn.h>
+
+static int twice(int x) {
+    return x * 2;
+}
+
+int main() {
+    void *handle = dlopen(PLUGIN_PATH, RTLD_NOW);
+    if (!handle) {
+        return 1;
+    }
+    int (*answer)(int) = (int (*)(int))dlsym(handle, "plugin_answer");
+    int result = answer(twice(10));
+    dlclose(handle);
+    return result;
+}
@@ -0,0 +1,10 @@
+int plugin_calls = 0;
+
+static int twice(int x) {
+    return x * 2;
+}
+
+int plugin_answer(int x) {
+    plugin_calls++;
+    return twice(x) + 2;
+}
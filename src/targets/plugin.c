@@ -0,0 +1,6 @@
+int plugin_calls = 0;
+
+int plugin_answer(int x) {
+    plugin_calls++;
+    return x * 2 + 2;
+}
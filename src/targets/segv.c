@@ -0,0 +1,12 @@
+#include <signal.h>
+#include <unistd.h>
+
+void on_usr1(int sig) {
+}
+
+int main() {
+    signal(SIGUSR1, on_usr1);
+    kill(getpid(), SIGUSR1);
+    volatile int *p = 0;
+    return *p;
+}
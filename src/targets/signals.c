@@ -0,0 +1,16 @@
+#include <signal.h>
+#include <unistd.h>
+
+int got = 0;
+
+void on_usr1(int sig) {
+    got += 1;
+}
+
+int main() {
+    signal(SIGUSR1, on_usr1);
+    raise(SIGUSR1);
+    raise(SIGUSR1);
+    raise(SIGUSR1);
+    return got;
+}
@@ -0,0 +1,18 @@
+#include <stdio.h>
+
+int add(int a, int b) {
+    int sum = a + b;
+    return sum;
+}
+
+int twice(int x) {
+    int doubled = add(x, x);
+    return doubled;
+}
+
+int main() {
+    int a = 3;
+    int b = twice(a);
+    printf("%d\n", b);
+    return 0;
+}
@@ -0,0 +1,12 @@
+#include <sys/syscall.h>
+#include <unistd.h>
+
+#define NUM_CALLS 5000
+
+int main() {
+    long ppid = 0;
+    for (int i = 0; i < NUM_CALLS; i++) {
+        ppid = syscall(SYS_getppid);
+    }
+    return ppid == getppid() ? 0 : 1;
+}
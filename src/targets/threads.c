@@ -0,0 +1,25 @@
+#include <pthread.h>
+
+#define NUM_THREADS 3
+
+int finished = 0;
+pthread_mutex_t lock = PTHREAD_MUTEX_INITIALIZER;
+
+void *worker(void *arg) {
+    long id = (long)arg;
+    pthread_mutex_lock(&lock);
+    finished += id;
+    pthread_mutex_unlock(&lock);
+    return arg;
+}
+
+int main() {
+    pthread_t threads[NUM_THREADS];
+    for (long i = 0; i < NUM_THREADS; i++) {
+        pthread_create(&threads[i], NULL, worker, (void *)(i + 1));
+    }
+    for (int i = 0; i < NUM_THREADS; i++) {
+        pthread_join(threads[i], NULL);
+    }
+    return finished;
+}
@@ -0,0 +1,19 @@
+#include <pthread.h>
+#include <unistd.h>
+
+#define NUM_THREADS 3
+
+void *idle(void *arg) {
+    for (;;) {
+        pause();
+    }
+    return arg;
+}
+
+int main() {
+    pthread_t threads[NUM_THREADS];
+    for (int i = 0; i < NUM_THREADS; i++) {
+        pthread_create(&threads[i], NULL, idle, NULL);
+    }
+    idle(NULL);
+}
@@ -0,0 +1,49 @@
+#include <stdio.h>
+
+enum color { RED, GREEN = 5, BLUE };
+
+struct point {
+    int x;
+    int y;
+};
+
+union number {
+    int i;
+    float f;
+};
+
+struct node {
+    int value;
+    struct node *next;
+};
+
+typedef struct {
+    char name[16];
+    struct point origin;
+    enum color color;
+    unsigned int flags : 3;
+    int delta : 5;
+    double scale;
+    union number num;
+} shape_t;
+
+int counter = 42;
+const char *greeting = "hello";
+int grid[2][3] = {{1, 2, 3}, {4, 5, 6}};
+
+int describe(shape_t *shape, int argc) {
+    struct node second = { 2, NULL };
+    struct node first = { 1, &second };
+    int total = shape->origin.x + shape->origin.y;
+    for (int i = 0; i < argc; i++) {
+        long scaled = total * 10L;
+        total += (int)scaled;
+    }
+    return total + first.next->value;
+}
+
+int main(int argc, char **argv) {
+    shape_t shape = { "square", { 3, 4 }, BLUE, 5, -3, 1.5, { .i = 7 } };
+    printf("%d\n", describe(&shape, argc));
+    return counter;
+}
@@ -0,0 +1,78 @@
+use crate::{
+    Result,
+    error,
+    registers::Registers,
+    process::ProcessState
+};
+
+use nix::{
+    sys::signal::Signal,
+    unistd::Pid
+};
+
+/// A thread of the inferior, with the registers it had when it last stopped
+#[derive(Debug)]
+pub struct Thread {
+    /// Small number the user refers to the thread by, counting up from 1 in creation order
+    pub id: usize,
+    pub tid: Pid,
+    pub state: ProcessState,
+    pub(crate) registers: Registers,
+    /// A signal the thread stopped with while other threads were being stopped, delivered
+    /// when it is next resumed
+    pub(crate) pending_signal: Option<Signal>,
+    /// A SIGSTOP has been sent to this thread, or it is new and the kernel has queued one,
+    /// and its stop has not been seen yet
+    pub(crate) sigstop_pending: bool,
+    /// The thread is between the entry and exit stops of a syscall
+    pub(crate) in_syscall: bool,
+    /// A fault rule fired on the syscall the thread is in: its number, in case the rule
+    /// skipped it, and what it returns instead
+    pub(crate) injected_return: Option<(u64, i64)>
+}
+
+impl Thread {
+    pub(crate) fn new(id: usize, tid: Pid) -> Self {
+        Self {
+            id,
+            tid,
+            state: ProcessState::Running,
+            registers: Registers::empty(),
+            pending_signal: None,
+            sigstop_pending: false,
+            in_syscall: false,
+            injected_return: None
+        }
+    }
+
+    pub fn regs(&self) -> &Registers {
+        &self.registers
+    }
+}
+
+/// The thread group a task belongs to, from /proc/<tid>/status
+pub(crate) fn thread_group(tid: Pid) -> Result<Pid> {
+    let Ok(status) = std::fs::read_to_string(format!("/proc/{}/status", tid)) else {
+        return error("could not read task status");
+    };
+    let tgid = status.lines()
+        .find_map(|line| line.strip_prefix("Tgid:"))
+        .and_then(|val| val.trim().parse::<i32>().ok());
+    match tgid {
+        Some(tgid) => Ok(Pid::from_raw(tgid)),
+        None => error("could not find Tgid in task status")
+    }
+}
+
+/// Every task currently in the thread group of `pid`, from /proc/<pid>/task
+pub(crate) fn task_ids(pid: Pid) -> Result<Vec<Pid>> {
+    let Ok(dir) = std::fs::read_dir(format!("/proc/{}/task", pid)) else {
+        return error("could not list process tasks");
+    };
+    let mut out: Vec<Pid> = dir
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<i32>().ok())
+        .map(Pid::from_raw)
+        .collect();
+    out.sort();
+    Ok(out)
+}
@@ -0,0 +1,673 @@
+use crate::{
+    Result,
+    error,
+    FileAddr,
+    VirtAddr,
+    Process,
+    Registers,
+    RValue,
+    register_by_dwarf,
+    elf::Elf,
+    dwarf::Cursor,
+    dwarf_expr::{evaluate, ExprContext, ExprResult},
+    line_table::SourceLocation
+};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+const DW_CFA_ADVANCE_LOC: u8 = 0x40;
+const DW_CFA_OFFSET: u8 = 0x80;
+const DW_CFA_RESTORE: u8 = 0xc0;
+const DW_CFA_NOP: u8 = 0x00;
+const DW_CFA_SET_LOC: u8 = 0x01;
+const DW_CFA_ADVANCE_LOC1: u8 = 0x02;
+const DW_CFA_ADVANCE_LOC2: u8 = 0x03;
+const DW_CFA_ADVANCE_LOC4: u8 = 0x04;
+const DW_CFA_OFFSET_EXTENDED: u8 = 0x05;
+const DW_CFA_RESTORE_EXTENDED: u8 = 0x06;
+const DW_CFA_UNDEFINED: u8 = 0x07;
+const DW_CFA_SAME_VALUE: u8 = 0x08;
+const DW_CFA_REGISTER: u8 = 0x09;
+const DW_CFA_REMEMBER_STATE: u8 = 0x0a;
+const DW_CFA_RESTORE_STATE: u8 = 0x0b;
+const DW_CFA_DEF_CFA: u8 = 0x0c;
+const DW_CFA_DEF_CFA_REGISTER: u8 = 0x0d;
+const DW_CFA_DEF_CFA_OFFSET: u8 = 0x0e;
+const DW_CFA_DEF_CFA_EXPRESSION: u8 = 0x0f;
+const DW_CFA_EXPRESSION: u8 = 0x10;
+const DW_CFA_OFFSET_EXTENDED_SF: u8 = 0x11;
+const DW_CFA_DEF_CFA_SF: u8 = 0x12;
+const DW_CFA_DEF_CFA_OFFSET_SF: u8 = 0x13;
+const DW_CFA_VAL_OFFSET: u8 = 0x14;
+const DW_CFA_VAL_OFFSET_SF: u8 = 0x15;
+const DW_CFA_VAL_EXPRESSION: u8 = 0x16;
+const DW_CFA_GNU_ARGS_SIZE: u8 = 0x2e;
+const DW_CFA_GNU_NEGATIVE_OFFSET_EXTENDED: u8 = 0x2f;
+
+const DW_EH_PE_OMIT: u8 = 0xff;
+const DW_EH_PE_ABSPTR: u8 = 0x00;
+const DW_EH_PE_ULEB128: u8 = 0x01;
+const DW_EH_PE_UDATA2: u8 = 0x02;
+const DW_EH_PE_UDATA4: u8 = 0x03;
+const DW_EH_PE_UDATA8: u8 = 0x04;
+const DW_EH_PE_SLEB128: u8 = 0x09;
+const DW_EH_PE_SDATA2: u8 = 0x0a;
+const DW_EH_PE_SDATA4: u8 = 0x0b;
+const DW_EH_PE_SDATA8: u8 = 0x0c;
+const DW_EH_PE_PCREL: u8 = 0x10;
+
+const DWARF_RSP: u16 = 7;
+const DWARF_RBP: u16 = 6;
+const DWARF_RIP: u16 = 16;
+
+const MAX_FRAMES: usize = 1024;
+
+/// A Common Information Entry: the parts of a frame description shared by many FDEs
+#[derive(Clone, Debug)]
+struct Cie {
+    code_align: u64,
+    data_align: i64,
+    ra_reg: u16,
+    fde_encoding: u8,
+    has_aug_data: bool,
+    is_signal: bool,
+    instructions: Range<usize>
+}
+
+/// A Frame Description Entry covering [pc_begin, pc_end) in file addresses
+#[derive(Clone, Debug)]
+struct Fde {
+    cie: usize,
+    pc_begin: u64,
+    pc_end: u64,
+    instructions: Range<usize>
+}
+
+/// How to recover a register's value in the caller
+#[derive(PartialEq, Clone, Debug)]
+pub enum RegRule {
+    Undefined,
+    SameValue,
+    /// Saved at CFA+N
+    Offset(i64),
+    /// The value is CFA+N
+    ValOffset(i64),
+    /// Held in another register
+    Register(u16),
+    /// Saved at the address the expression computes
+    Expression(Vec<u8>),
+    /// The value is what the expression computes
+    ValExpression(Vec<u8>)
+}
+
+/// How to compute the canonical frame address
+#[derive(PartialEq, Clone, Debug)]
+pub enum CfaRule {
+    RegOffset(u16, i64),
+    Expression(Vec<u8>)
+}
+
+/// One row of the CFI table: the rules in effect at a particular pc
+#[derive(Clone, Debug)]
+pub struct UnwindRow {
+    pub cfa: CfaRule,
+    pub regs: HashMap<u16, RegRule>,
+    pub ra_reg: u16,
+    pub is_signal: bool
+}
+
+/// The CIEs and FDEs from one of .eh_frame or .debug_frame
+#[derive(Debug)]
+struct FrameTable {
+    data: Vec<u8>,
+    cies: Vec<Cie>,
+    fdes: Vec<Fde>
+}
+
+impl FrameTable {
+    fn parse(data: &[u8], section_addr: u64, is_eh: bool) -> Result<Self> {
+        let mut table = Self {
+            data: data.to_vec(),
+            cies: Vec::new(),
+            fdes: Vec::new()
+        };
+        let mut cie_offsets: HashMap<usize, usize> = HashMap::new();
+        let mut cur = Cursor::new(data);
+        while !cur.finished() {
+            let (len, is_64) = cur.unit_length()?;
+            if len == 0 {
+                if is_eh {
+                    break;
+                }
+                continue;
+            }
+            // an entry longer than the section leaves nothing after it to find
+            let Some(end) = cur.pos().checked_add(len as usize).filter(|end| *end <= data.len()) else {
+                break;
+            };
+            let id_pos = cur.pos();
+            let id = cur.offset(is_64)?;
+            let is_cie = if is_eh {
+                id == 0
+            } else {
+                id == if is_64 { u64::MAX } else { 0xffffffff }
+            };
+            if !is_cie {
+                let cie_offset = if is_eh { id_pos.wrapping_sub(id as usize) } else { id as usize };
+                let cie = match cie_offsets.get(&cie_offset) {
+                    Some(idx) => *idx,
+                    None => {
+                        let Ok(cie) = Self::parse_cie(data, cie_offset, is_eh) else {
+                            cur.seek(end);
+                            continue;
+                        };
+                        table.cies.push(cie);
+                        cie_offsets.insert(cie_offset, table.cies.len() - 1);
+                        table.cies.len() - 1
+                    }
+                };
+                if let Ok(fde) = Self::parse_fde(&mut cur, &table.cies[cie], cie, section_addr, is_eh, end) {
+                    // FDEs for code discarded at link time are left pointing at 0
+                    if fde.pc_begin != 0 {
+                        table.fdes.push(fde);
+                    }
+                }
+            }
+            cur.seek(end);
+        }
+        table.fdes.sort_by_key(|fde| fde.pc_begin);
+        Ok(table)
+    }
+
+    fn parse_cie(data: &[u8], offset: usize, is_eh: bool) -> Result<Cie> {
+        let mut cur = Cursor::at(data, offset);
+        let (len, is_64) = cur.unit_length()?;
+        let Some(end) = cur.pos().checked_add(len as usize).filter(|end| *end <= data.len()) else {
+            return error("CIE runs past the end of the section");
+        };
+        cur.offset(is_64)?;
+        let version = cur.u8()?;
+        let augmentation = cur.cstr()?;
+        if version >= 4 {
+            let address_size = cur.u8()?;
+            let _segment_size = cur.u8()?;
+            if address_size != 8 {
+                return error("only 64-bit call frame information is supported");
+            }
+        }
+        let code_align = cur.uleb128()?;
+        let data_align = cur.sleb128()?;
+        let ra_reg = if version == 1 { cur.u8()? as u16 } else { cur.uleb128()? as u16 };
+
+        let mut cie = Cie {
+            code_align,
+            data_align,
+            ra_reg,
+            fde_encoding: if is_eh { DW_EH_PE_ABSPTR } else { DW_EH_PE_UDATA8 },
+            has_aug_data: false,
+            is_signal: false,
+            instructions: 0..0
+        };
+        let mut aug_end = None;
+        for (idx, ch) in augmentation.chars().enumerate() {
+            match ch {
+                'z' if idx == 0 => {
+                    let aug_len = cur.uleb128()? as usize;
+                    aug_end = Some(cur.pos() + aug_len);
+                    cie.has_aug_data = true;
+                },
+                'R' => cie.fde_encoding = cur.u8()?,
+                'L' => { cur.u8()?; },
+                'P' => {
+                    let encoding = cur.u8()?;
+                    read_encoded(&mut cur, encoding, 0)?;
+                },
+                'S' => cie.is_signal = true,
+                'B' => {},
+                _ if aug_end.is_some() => break,
+                _ => return error(&format!("unsupported CIE augmentation {}", augmentation))
+            }
+        }
+        if let Some(aug_end) = aug_end {
+            cur.seek(aug_end);
+        }
+        if cur.pos() > end {
+            return error("CIE header runs past its length");
+        }
+        cie.instructions = cur.pos()..end;
+        Ok(cie)
+    }
+
+    fn parse_fde(cur: &mut Cursor, cie: &Cie, cie_idx: usize, section_addr: u64, is_eh: bool, end: usize) -> Result<Fde> {
+        let pc_begin = read_encoded(cur, cie.fde_encoding, section_addr)?;
+        let pc_range = read_encoded(cur, cie.fde_encoding & 0x0f, 0)?;
+        if is_eh && cie.has_aug_data {
+            let aug_len = cur.uleb128()? as usize;
+            cur.skip(aug_len)?;
+        }
+        if cur.pos() > end {
+            return error("FDE header runs past its length");
+        }
+        Ok(Fde {
+            cie: cie_idx,
+            pc_begin,
+            pc_end: pc_begin.wrapping_add(pc_range),
+            instructions: cur.pos()..end
+        })
+    }
+
+    fn fde_containing(&self, addr: u64) -> Option<&Fde> {
+        let idx = self.fdes.partition_point(|fde| fde.pc_begin <= addr);
+        self.fdes[..idx].iter().rev()
+            .take(4)
+            .find(|fde| addr < fde.pc_end)
+    }
+
+    /// Runs the CIE's initial instructions then the FDE's up to `addr`
+    fn row_at(&self, fde: &Fde, addr: u64) -> Result<UnwindRow> {
+        let cie = &self.cies[fde.cie];
+        let mut row = UnwindRow {
+            cfa: CfaRule::RegOffset(DWARF_RSP, 8),
+            regs: HashMap::new(),
+            ra_reg: cie.ra_reg,
+            is_signal: cie.is_signal
+        };
+        self.execute(cie, &self.data[cie.instructions.clone()], &mut row, None, fde.pc_begin, u64::MAX)?;
+        let initial = row.regs.clone();
+        self.execute(cie, &self.data[fde.instructions.clone()], &mut row, Some(&initial), fde.pc_begin, addr)?;
+        Ok(row)
+    }
+
+    fn execute(&self, cie: &Cie, insts: &[u8], row: &mut UnwindRow, initial: Option<&HashMap<u16, RegRule>>,
+            mut loc: u64, target: u64) -> Result<()> {
+        let mut cur = Cursor::new(insts);
+        let mut saved: Vec<(CfaRule, HashMap<u16, RegRule>)> = Vec::new();
+        let restore = |row: &mut UnwindRow, reg: u16| {
+            match initial.and_then(|i| i.get(&reg)) {
+                Some(rule) => { row.regs.insert(reg, rule.clone()); },
+                None => { row.regs.remove(&reg); }
+            }
+        };
+        while !cur.finished() {
+            let op = cur.u8()?;
+            let (high, low) = (op & 0xc0, op & 0x3f);
+            let advance = match (high, op) {
+                (DW_CFA_ADVANCE_LOC, _) => Some(low as u64),
+                (0, DW_CFA_ADVANCE_LOC1) => Some(cur.u8()? as u64),
+                (0, DW_CFA_ADVANCE_LOC2) => Some(cur.u16()? as u64),
+                (0, DW_CFA_ADVANCE_LOC4) => Some(cur.u32()? as u64),
+                _ => None
+            };
+            if let Some(delta) = advance {
+                loc = loc.wrapping_add(delta * cie.code_align);
+                if loc > target {
+                    return Ok(());
+                }
+                continue;
+            }
+            match (high, op) {
+                (DW_CFA_OFFSET, _) => {
+                    let off = cur.uleb128()? as i64 * cie.data_align;
+                    row.regs.insert(low as u16, RegRule::Offset(off));
+                },
+                (DW_CFA_RESTORE, _) => restore(row, low as u16),
+                (_, DW_CFA_NOP) => {},
+                (_, DW_CFA_SET_LOC) => {
+                    loc = cur.u64()?;
+                    if loc > target {
+                        return Ok(());
+                    }
+                },
+                (_, DW_CFA_OFFSET_EXTENDED) => {
+                    let reg = cur.uleb128()? as u16;
+                    let off = cur.uleb128()? as i64 * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                },
+                (_, DW_CFA_OFFSET_EXTENDED_SF) => {
+                    let reg = cur.uleb128()? as u16;
+                    let off = cur.sleb128()? * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                },
+                (_, DW_CFA_GNU_NEGATIVE_OFFSET_EXTENDED) => {
+                    let reg = cur.uleb128()? as u16;
+                    let off = -(cur.uleb128()? as i64) * cie.data_align;
+                    row.regs.insert(reg, RegRule::Offset(off));
+                },
+                (_, DW_CFA_VAL_OFFSET) => {
+                    let reg = cur.uleb128()? as u16;
+                    let off = cur.uleb128()? as i64 * cie.data_align;
+                    row.regs.insert(reg, RegRule::ValOffset(off));
+                },
+                (_, DW_CFA_VAL_OFFSET_SF) => {
+                    let reg = cur.uleb128()? as u16;
+                    let off = cur.sleb128()? * cie.data_align;
+                    row.regs.insert(reg, RegRule::ValOffset(off));
+                },
+                (_, DW_CFA_RESTORE_EXTENDED) => {
+                    let reg = cur.uleb128()? as u16;
+                    restore(row, reg);
+                },
+                (_, DW_CFA_UNDEFINED) => { row.regs.insert(cur.uleb128()? as u16, RegRule::Undefined); },
+                (_, DW_CFA_SAME_VALUE) => { row.regs.insert(cur.uleb128()? as u16, RegRule::SameValue); },
+                (_, DW_CFA_REGISTER) => {
+                    let reg = cur.uleb128()? as u16;
+                    let other = cur.uleb128()? as u16;
+                    row.regs.insert(reg, RegRule::Register(other));
+                },
+                (_, DW_CFA_REMEMBER_STATE) => saved.push((row.cfa.clone(), row.regs.clone())),
+                (_, DW_CFA_RESTORE_STATE) => {
+                    let Some((cfa, regs)) = saved.pop() else {
+                        return error("DW_CFA_restore_state without matching remember_state");
+                    };
+                    row.cfa = cfa;
+                    row.regs = regs;
+                },
+                (_, DW_CFA_DEF_CFA) => {
+                    let reg = cur.uleb128()? as u16;
+                    row.cfa = CfaRule::RegOffset(reg, cur.uleb128()? as i64);
+                },
+                (_, DW_CFA_DEF_CFA_SF) => {
+                    let reg = cur.uleb128()? as u16;
+                    row.cfa = CfaRule::RegOffset(reg, cur.sleb128()? * cie.data_align);
+                },
+                (_, DW_CFA_DEF_CFA_REGISTER) => {
+                    let reg = cur.uleb128()? as u16;
+                    let CfaRule::RegOffset(_, off) = row.cfa else {
+                        return error("DW_CFA_def_cfa_register applied to an expression CFA");
+                    };
+                    row.cfa = CfaRule::RegOffset(reg, off);
+                },
+                (_, DW_CFA_DEF_CFA_OFFSET) | (_, DW_CFA_DEF_CFA_OFFSET_SF) => {
+                    let off = if op == DW_CFA_DEF_CFA_OFFSET {
+                        cur.uleb128()? as i64
+                    } else {
+                        cur.sleb128()? * cie.data_align
+                    };
+                    let CfaRule::RegOffset(reg, _) = row.cfa else {
+                        return error("DW_CFA_def_cfa_offset applied to an expression CFA");
+                    };
+                    row.cfa = CfaRule::RegOffset(reg, off);
+                },
+                (_, DW_CFA_DEF_CFA_EXPRESSION) => {
+                    let len = cur.uleb128()? as usize;
+                    row.cfa = CfaRule::Expression(cur.bytes(len)?.to_vec());
+                },
+                (_, DW_CFA_EXPRESSION) | (_, DW_CFA_VAL_EXPRESSION) => {
+                    let reg = cur.uleb128()? as u16;
+                    let len = cur.uleb128()? as usize;
+                    let expr = cur.bytes(len)?.to_vec();
+                    row.regs.insert(reg, if op == DW_CFA_EXPRESSION {
+                        RegRule::Expression(expr)
+                    } else {
+                        RegRule::ValExpression(expr)
+                    });
+                },
+                (_, DW_CFA_GNU_ARGS_SIZE) => { cur.uleb128()?; },
+                _ => return error(&format!("unsupported call frame instruction {:#x}", op))
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_encoded(cur: &mut Cursor, encoding: u8, section_addr: u64) -> Result<u64> {
+    if encoding == DW_EH_PE_OMIT {
+        return Ok(0);
+    }
+    let field_addr = section_addr.wrapping_add(cur.pos() as u64);
+    let val = match encoding & 0x0f {
+        DW_EH_PE_ABSPTR | DW_EH_PE_UDATA8 => cur.u64()?,
+        DW_EH_PE_ULEB128 => cur.uleb128()?,
+        DW_EH_PE_UDATA2 => cur.u16()? as u64,
+        DW_EH_PE_UDATA4 => cur.u32()? as u64,
+        DW_EH_PE_SLEB128 => cur.sleb128()? as u64,
+        DW_EH_PE_SDATA2 => cur.i16()? as u64,
+        DW_EH_PE_SDATA4 => cur.i32()? as u64,
+        DW_EH_PE_SDATA8 => cur.i64()? as u64,
+        _ => return error(&format!("unsupported pointer encoding {:#x}", encoding))
+    };
+    match encoding & 0x70 {
+        0 => Ok(val),
+        DW_EH_PE_PCREL => Ok(field_addr.wrapping_add(val)),
+        _ => error(&format!("unsupported pointer encoding {:#x}", encoding))
+    }
+}
+
+/// The call frame information in an object's .eh_frame and .debug_frame sections
+#[derive(Debug, Default)]
+pub struct CallFrameInfo {
+    tables: Vec<FrameTable>
+}
+
+impl CallFrameInfo {
+    pub fn parse(elf: &Elf) -> Result<Self> {
+        let mut tables = Vec::new();
+        for (name, is_eh) in [(".eh_frame", true), (".debug_frame", false)] {
+            let Some(sec) = elf.section(name) else {
+                continue;
+            };
+            tables.push(FrameTable::parse(elf.section_data(sec), sec.addr, is_eh)?);
+        }
+        Ok(Self { tables })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.iter().all(|t| t.fdes.is_empty())
+    }
+
+    /// The unwind rules in effect at a file address, if any FDE covers it
+    pub fn row_at(&self, addr: FileAddr) -> Option<Result<UnwindRow>> {
+        self.tables.iter()
+            .find_map(|t| t.fde_containing(addr.0).map(|fde| t.row_at(fde, addr.0)))
+    }
+}
+
+/// One frame of the inferior's call stack
+#[derive(Debug)]
+pub struct StackFrame {
+    pub pc: VirtAddr,
+    /// The canonical frame address: the caller's rsp before the call instruction
+    pub cfa: Option<VirtAddr>,
+    /// Registers as they were in this frame, as far as unwinding could recover them
+    pub regs: Registers,
+    /// Stack slots holding this frame's values of registers that a callee saved, by DWARF number
+    pub saved_regs: HashMap<u16, VirtAddr>,
+    pub symbol: Option<(String, u64)>,
+    pub object: Option<String>,
+    pub location: Option<SourceLocation>
+}
+
+/// The caller's state recovered by unwinding one frame
+struct Unwound {
+    cfa: VirtAddr,
+    regs: Registers,
+    saved_regs: HashMap<u16, VirtAddr>,
+    is_signal: bool
+}
+
+struct FrameContext<'a> {
+    p: &'a Process,
+    regs: &'a Registers,
+    cfa: Option<u64>
+}
+
+impl ExprContext for FrameContext<'_> {
+    fn read_reg(&self, dwarf_id: u16) -> Result<u64> {
+        read_dwarf_reg(self.regs, dwarf_id)
+    }
+
+    fn read_mem(&self, addr: u64, size: usize) -> Result<u64> {
+        read_mem(self.p, VirtAddr(addr), size)
+    }
+
+    fn cfa(&self) -> Result<u64> {
+        match self.cfa {
+            Some(cfa) => Ok(cfa),
+            None => error("CFA not yet known")
+        }
+    }
+}
+
+fn read_dwarf_reg(regs: &Registers, dwarf_id: u16) -> Result<u64> {
+    Ok(regs.read_as::<u64>(register_by_dwarf(dwarf_id as i32)?))
+}
+
+fn write_dwarf_reg(regs: &mut Registers, dwarf_id: u16, val: u64) -> Result<()> {
+    regs.write(&RValue::from(val, register_by_dwarf(dwarf_id as i32)?));
+    Ok(())
+}
+
+fn read_mem(p: &Process, va: VirtAddr, size: usize) -> Result<u64> {
+    let data = p.read_memory_clean(va, size)?;
+    let mut buf = [0u8; 8];
+    buf[..size].copy_from_slice(&data);
+    Ok(u64::from_le_bytes(buf))
+}
+
+/// Computes the CFA of the frame whose registers are `regs` and the caller's registers,
+/// or None for the caller if this is the outermost frame
+fn unwind_frame(p: &Process, regs: &Registers, saved_regs: &HashMap<u16, VirtAddr>, lookup_pc: VirtAddr) -> Result<Option<Unwound>> {
+    let row = match p.object_containing(lookup_pc).and_then(|o| o.frames.row_at(o.to_file(lookup_pc))) {
+        Some(row) => row?,
+        None => return unwind_frame_pointer(p, regs, saved_regs)
+    };
+    let ctx = FrameContext { p, regs, cfa: None };
+    let cfa = match &row.cfa {
+        CfaRule::RegOffset(reg, off) => read_dwarf_reg(regs, *reg)?.wrapping_add_signed(*off),
+        CfaRule::Expression(expr) => match evaluate(expr, &ctx, &[])? {
+            ExprResult::Address(v) | ExprResult::Value(v) => v,
+            ExprResult::Register(reg) => read_dwarf_reg(regs, reg)?
+        }
+    };
+    let ctx = FrameContext { p, regs, cfa: Some(cfa) };
+
+    let mut caller = regs.clone();
+    let mut caller_saved = saved_regs.clone();
+    let mut ra = None;
+    for (reg, rule) in row.regs.iter() {
+        let mut slot = None;
+        let val = match rule {
+            RegRule::Undefined => {
+                if *reg == row.ra_reg {
+                    return Ok(None);
+                }
+                caller_saved.remove(reg);
+                continue;
+            },
+            RegRule::SameValue => continue,
+            RegRule::Offset(off) => {
+                let addr = cfa.wrapping_add_signed(*off);
+                slot = Some(VirtAddr(addr));
+                read_mem(p, VirtAddr(addr), 8)?
+            },
+            RegRule::ValOffset(off) => cfa.wrapping_add_signed(*off),
+            RegRule::Register(other) => read_dwarf_reg(regs, *other)?,
+            RegRule::Expression(expr) => match evaluate(expr, &ctx, &[cfa])? {
+                ExprResult::Address(addr) => {
+                    slot = Some(VirtAddr(addr));
+                    read_mem(p, VirtAddr(addr), 8)?
+                },
+                ExprResult::Value(v) => v,
+                ExprResult::Register(other) => read_dwarf_reg(regs, other)?
+            },
+            RegRule::ValExpression(expr) => match evaluate(expr, &ctx, &[cfa])? {
+                ExprResult::Address(v) | ExprResult::Value(v) => v,
+                ExprResult::Register(other) => read_dwarf_reg(regs, other)?
+            }
+        };
+        match slot {
+            Some(addr) => { caller_saved.insert(*reg, addr); },
+            None => { caller_saved.remove(reg); }
+        }
+        if *reg == row.ra_reg {
+            ra = Some(val);
+        }
+        if register_by_dwarf(*reg as i32).is_ok() {
+            write_dwarf_reg(&mut caller, *reg, val)?;
+        }
+    }
+    let Some(ra) = ra else {
+        return Ok(None);
+    };
+    write_dwarf_reg(&mut caller, DWARF_RIP, ra)?;
+    write_dwarf_reg(&mut caller, DWARF_RSP, cfa)?;
+    caller_saved.remove(&DWARF_RIP);
+    caller_saved.remove(&DWARF_RSP);
+    Ok(Some(Unwound {
+        cfa: VirtAddr(cfa),
+        regs: caller,
+        saved_regs: caller_saved,
+        is_signal: row.is_signal
+    }))
+}
+
+/// Fallback for code without CFI: assume the standard push rbp; mov rsp, rbp frame layout
+fn unwind_frame_pointer(p: &Process, regs: &Registers, saved_regs: &HashMap<u16, VirtAddr>) -> Result<Option<Unwound>> {
+    let rbp = read_dwarf_reg(regs, DWARF_RBP)?;
+    let rsp = read_dwarf_reg(regs, DWARF_RSP)?;
+    if rbp == 0 || rbp < rsp {
+        return Ok(None);
+    }
+    let cfa = rbp + 16;
+    let mut caller = regs.clone();
+    write_dwarf_reg(&mut caller, DWARF_RBP, read_mem(p, VirtAddr(rbp), 8)?)?;
+    write_dwarf_reg(&mut caller, DWARF_RIP, read_mem(p, VirtAddr(rbp + 8), 8)?)?;
+    write_dwarf_reg(&mut caller, DWARF_RSP, cfa)?;
+    let mut caller_saved = saved_regs.clone();
+    caller_saved.insert(DWARF_RBP, VirtAddr(rbp));
+    Ok(Some(Unwound {
+        cfa: VirtAddr(cfa),
+        regs: caller,
+        saved_regs: caller_saved,
+        is_signal: false
+    }))
+}
+
+/// The CFA of the innermost frame, if call frame information covers the pc
+pub(crate) fn current_cfa(p: &Process) -> Option<VirtAddr> {
+    let pc = p.get_pc();
+    let obj = p.object_containing(pc)?;
+    obj.frames.row_at(obj.to_file(pc))?.ok()?;
+    unwind_frame(p, p.regs(), &HashMap::new(), pc).ok().flatten().map(|u| u.cfa)
+}
+
+/// Walks the stack of a stopped process from the current pc outwards
+pub fn backtrace(p: &Process) -> Result<Vec<StackFrame>> {
+    let mut frames: Vec<StackFrame> = Vec::new();
+    let mut regs = p.regs().clone();
+    let mut saved_regs = HashMap::new();
+    // A return address points after the call, which may be outside the calling function
+    let mut pc_is_exact = true;
+    loop {
+        let pc = VirtAddr(read_dwarf_reg(&regs, DWARF_RIP)?);
+        let lookup_pc = if pc_is_exact { pc } else { pc - 1u64 };
+        let symbol = p.symbol_at(lookup_pc).map(|(name, off)| (name, off + (pc - lookup_pc)));
+        let unwound = if frames.len() + 1 < MAX_FRAMES {
+            unwind_frame(p, &regs, &saved_regs, lookup_pc).ok().flatten()
+        } else {
+            None
+        };
+        frames.push(StackFrame {
+            pc,
+            cfa: unwound.as_ref().map(|u| u.cfa),
+            regs,
+            saved_regs,
+            symbol,
+            object: p.object_containing(lookup_pc).map(|o| o.name()),
+            location: p.source_location(lookup_pc)
+        });
+        let Some(unwound) = unwound else {
+            break;
+        };
+        let caller_pc = read_dwarf_reg(&unwound.regs, DWARF_RIP)?;
+        let prev_cfa = frames.len().checked_sub(2).and_then(|i| frames[i].cfa);
+        if caller_pc == 0 || prev_cfa.is_some_and(|prev| unwound.cfa <= prev && !unwound.is_signal) {
+            break;
+        }
+        regs = unwound.regs;
+        saved_regs = unwound.saved_regs;
+        pc_is_exact = unwound.is_signal;
+    }
+    Ok(frames)
+}
@@ -0,0 +1,789 @@
+use crate::{
+    Result,
+    error,
+    VirtAddr,
+    Process,
+    Registers,
+    register_by_dwarf,
+    debug_info::{AttrValue, DebugInfo, Die},
+    dwarf::*,
+    dwarf_expr::{evaluate, ExprContext, ExprResult},
+    unwind::backtrace
+};
+
+use extended::Extended;
+
+/// Longest string read through a char pointer, and most array elements printed
+const PRINT_LIMIT: usize = 200;
+
+/// A C type, either described by a DIE or made up by the debugger, like the type of `&x`
+#[derive(Clone)]
+pub enum Type<'a> {
+    Void,
+    Die(&'a DebugInfo, &'a Die),
+    Pointer(Box<Type<'a>>),
+    Array(Box<Type<'a>>, Option<u64>),
+    Base { name: String, size: usize, encoding: u64 }
+}
+
+impl std::fmt::Debug for Type<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<type {}>", self.name())
+    }
+}
+
+/// A field of a struct or union
+#[derive(Clone, Debug)]
+pub struct Member<'a> {
+    pub name: Option<String>,
+    pub ty: Type<'a>,
+    pub offset: u64,
+    /// Width and offset in bits from `offset`, for bitfields
+    pub bits: Option<(u64, u64)>
+}
+
+impl<'a> Type<'a> {
+    /// Builtin C types, for naming types that the debug info may not contain
+    pub fn builtin(name: &str) -> Option<Self> {
+        let (size, encoding) = match name {
+            "char" | "signed char" | "int8_t" => (1, DW_ATE_SIGNED_CHAR),
+            "unsigned char" | "uint8_t" => (1, DW_ATE_UNSIGNED_CHAR),
+            "_Bool" | "bool" => (1, DW_ATE_BOOLEAN),
+            "short" | "int16_t" => (2, DW_ATE_SIGNED),
+            "unsigned short" | "uint16_t" => (2, DW_ATE_UNSIGNED),
+            "int" | "int32_t" => (4, DW_ATE_SIGNED),
+            "unsigned" | "unsigned int" | "uint32_t" => (4, DW_ATE_UNSIGNED),
+            "long" | "long long" | "int64_t" | "ssize_t" => (8, DW_ATE_SIGNED),
+            "unsigned long" | "unsigned long long" | "uint64_t" | "size_t" | "uintptr_t" => (8, DW_ATE_UNSIGNED),
+            "float" => (4, DW_ATE_FLOAT),
+            "double" => (8, DW_ATE_FLOAT),
+            "long double" => (16, DW_ATE_FLOAT),
+            "void" => return Some(Type::Void),
+            _ => return None
+        };
+        Some(Type::Base { name: name.to_string(), size, encoding })
+    }
+
+    pub fn from_die(info: &'a DebugInfo, die: &'a Die) -> Self {
+        Type::Die(info, die)
+    }
+
+    pub fn pointer_to(self) -> Self {
+        Type::Pointer(Box::new(self))
+    }
+
+    /// The type named by a DIE's DW_AT_type, with no attribute meaning void
+    fn referenced(&self) -> Type<'a> {
+        let Type::Die(info, die) = self else {
+            return Type::Void;
+        };
+        die.reference(DW_AT_TYPE)
+            .and_then(|off| info.die(off))
+            .map(|d| Type::Die(info, d))
+            .unwrap_or(Type::Void)
+    }
+
+    pub fn tag(&self) -> u64 {
+        match self {
+            Type::Void => 0,
+            Type::Die(_, die) => die.tag,
+            Type::Pointer(_) => DW_TAG_POINTER_TYPE,
+            Type::Array(_, _) => DW_TAG_ARRAY_TYPE,
+            Type::Base { .. } => DW_TAG_BASE_TYPE
+        }
+    }
+
+    /// The type with typedefs and const/volatile qualifiers peeled off
+    pub fn strip(&self) -> Type<'a> {
+        let mut ty = self.clone();
+        while matches!(ty.tag(), DW_TAG_TYPEDEF | DW_TAG_CONST_TYPE | DW_TAG_VOLATILE_TYPE
+                | DW_TAG_RESTRICT_TYPE | DW_TAG_ATOMIC_TYPE) {
+            ty = ty.referenced();
+        }
+        ty
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self.strip(), Type::Void)
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self.strip().tag(), DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE)
+    }
+
+    pub fn is_array(&self) -> bool {
+        self.strip().tag() == DW_TAG_ARRAY_TYPE
+    }
+
+    pub fn is_aggregate(&self) -> bool {
+        matches!(self.strip().tag(), DW_TAG_STRUCTURE_TYPE | DW_TAG_UNION_TYPE | DW_TAG_ARRAY_TYPE)
+    }
+
+    /// The DW_ATE_* encoding of a base type, treating enums as signed integers
+    pub fn encoding(&self) -> Option<u64> {
+        match self.strip() {
+            Type::Base { encoding, .. } => Some(encoding),
+            Type::Die(_, die) if die.tag == DW_TAG_BASE_TYPE => die.udata(DW_AT_ENCODING),
+            Type::Die(_, die) if die.tag == DW_TAG_ENUMERATION_TYPE => Some(DW_ATE_SIGNED),
+            _ => None
+        }
+    }
+
+    pub fn is_float(&self) -> bool {
+        self.encoding() == Some(DW_ATE_FLOAT)
+    }
+
+    pub fn is_char(&self) -> bool {
+        matches!(self.encoding(), Some(DW_ATE_SIGNED_CHAR) | Some(DW_ATE_UNSIGNED_CHAR)) && self.size() == 1
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self.encoding(), Some(DW_ATE_SIGNED) | Some(DW_ATE_SIGNED_CHAR) | Some(DW_ATE_FLOAT))
+    }
+
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Void => 0,
+            Type::Pointer(_) => 8,
+            Type::Base { size, .. } => *size,
+            Type::Array(elem, len) => elem.size() * len.unwrap_or(0) as usize,
+            Type::Die(_, die) => {
+                if let Some(size) = die.udata(DW_AT_BYTE_SIZE) {
+                    return size as usize;
+                }
+                match die.tag {
+                    DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE => 8,
+                    DW_TAG_ARRAY_TYPE => {
+                        let (elem, len) = self.element().unwrap();
+                        elem.size() * len.unwrap_or(0) as usize
+                    },
+                    DW_TAG_ENUMERATION_TYPE => 4,
+                    DW_TAG_TYPEDEF | DW_TAG_CONST_TYPE | DW_TAG_VOLATILE_TYPE
+                        | DW_TAG_RESTRICT_TYPE | DW_TAG_ATOMIC_TYPE => self.referenced().size(),
+                    _ => 0
+                }
+            }
+        }
+    }
+
+    /// What a pointer points to
+    pub fn pointee(&self) -> Option<Type<'a>> {
+        match self.strip() {
+            Type::Pointer(target) => Some(*target),
+            ty @ Type::Die(..) if ty.is_pointer() => Some(ty.referenced()),
+            _ => None
+        }
+    }
+
+    /// The element type of an array and the array's length, if known
+    pub fn element(&self) -> Option<(Type<'a>, Option<u64>)> {
+        match self.strip() {
+            Type::Array(elem, len) => Some((*elem, len)),
+            ty @ Type::Die(info, die) if die.tag == DW_TAG_ARRAY_TYPE => {
+                let dims: Vec<Option<u64>> = info.children(die)
+                    .filter(|d| d.tag == DW_TAG_SUBRANGE_TYPE)
+                    .map(|d| d.udata(DW_AT_COUNT).or(d.udata(DW_AT_UPPER_BOUND).map(|n| n + 1)))
+                    .collect();
+                // a multi-dimensional array is an array of arrays of the remaining dimensions
+                let mut elem = ty.referenced();
+                for len in dims.iter().skip(1).rev() {
+                    elem = Type::Array(Box::new(elem), *len);
+                }
+                Some((elem, dims.first().copied().flatten()))
+            },
+            _ => None
+        }
+    }
+
+    pub fn members(&self) -> Vec<Member<'a>> {
+        let Type::Die(info, die) = self.strip() else {
+            return Vec::new();
+        };
+        info.children(die)
+            .filter(|d| d.tag == DW_TAG_MEMBER)
+            .map(|d| {
+                let offset = match d.attr(DW_AT_DATA_MEMBER_LOCATION) {
+                    // DWARF 2 encodes the offset as DW_OP_plus_uconst N
+                    Some(AttrValue::Block(expr)) => crate::dwarf::Cursor::at(expr, 1).uleb128().unwrap_or(0),
+                    _ => d.udata(DW_AT_DATA_MEMBER_LOCATION).unwrap_or(0)
+                };
+                let ty = Type::Die(info, d).referenced();
+                let bits = d.udata(DW_AT_BIT_SIZE).map(|width| {
+                    match (d.udata(DW_AT_DATA_BIT_OFFSET), d.udata(DW_AT_BIT_OFFSET)) {
+                        (Some(bit_off), _) => (width, bit_off),
+                        // DWARF 2/3 count from the most significant bit of the storage unit
+                        (None, Some(msb_off)) => (width, ty.size() as u64 * 8 - msb_off - width),
+                        (None, None) => (width, 0)
+                    }
+                });
+                let (offset, bits) = match bits {
+                    Some((width, bit_off)) if d.udata(DW_AT_DATA_BIT_OFFSET).is_some() =>
+                        (offset + bit_off / 8, Some((width, bit_off % 8))),
+                    other => (offset, other)
+                };
+                Member {
+                    name: d.name().map(String::from),
+                    ty,
+                    offset,
+                    bits
+                }
+            })
+            .collect()
+    }
+
+    pub fn enumerators(&self) -> Vec<(String, i64)> {
+        let Type::Die(info, die) = self.strip() else {
+            return Vec::new();
+        };
+        info.children(die)
+            .filter(|d| d.tag == DW_TAG_ENUMERATOR)
+            .map(|d| (d.name().unwrap_or("").to_string(), d.sdata(DW_AT_CONST_VALUE).unwrap_or(0)))
+            .collect()
+    }
+
+    /// The type's name as C would spell it, e.g. `struct node *` or `int [4]`
+    pub fn name(&self) -> String {
+        declare(self, "")
+    }
+
+    fn base_name(&self) -> String {
+        match self {
+            Type::Void => "void".to_string(),
+            Type::Base { name, .. } => name.clone(),
+            Type::Die(info, die) => {
+                let name = info.name_of(die);
+                let keyword = match die.tag {
+                    DW_TAG_STRUCTURE_TYPE => "struct ",
+                    DW_TAG_UNION_TYPE => "union ",
+                    DW_TAG_ENUMERATION_TYPE => "enum ",
+                    _ => ""
+                };
+                match name {
+                    Some(name) => format!("{}{}", keyword, name),
+                    None if !keyword.is_empty() => format!("{}{{...}}", keyword),
+                    None => "?".to_string()
+                }
+            },
+            Type::Pointer(_) | Type::Array(..) => self.name()
+        }
+    }
+}
+
+/// Builds a C declaration of `inner` (a name, or a partial declarator) with type `ty`
+fn declare(ty: &Type, inner: &str) -> String {
+    match ty.tag() {
+        DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE => {
+            let target = match ty {
+                Type::Pointer(target) => (**target).clone(),
+                _ => ty.referenced()
+            };
+            let inner = format!("{}{}", if ty.tag() == DW_TAG_POINTER_TYPE { "*" } else { "&" }, inner);
+            if matches!(target.tag(), DW_TAG_ARRAY_TYPE | DW_TAG_SUBROUTINE_TYPE) {
+                declare(&target, &format!("({})", inner))
+            } else {
+                declare(&target, &inner)
+            }
+        },
+        DW_TAG_ARRAY_TYPE => {
+            let (elem, len) = ty.element().unwrap();
+            let len = len.map(|n| n.to_string()).unwrap_or_default();
+            declare(&elem, &format!("{}[{}]", inner, len))
+        },
+        DW_TAG_SUBROUTINE_TYPE => {
+            let Type::Die(info, die) = ty else {
+                unreachable!();
+            };
+            let mut params: Vec<String> = info.children(die)
+                .filter(|d| d.tag == DW_TAG_FORMAL_PARAMETER)
+                .map(|d| Type::Die(info, d).referenced().name())
+                .collect();
+            if info.children(die).any(|d| d.tag == DW_TAG_UNSPECIFIED_PARAMETERS) {
+                params.push("...".to_string());
+            } else if params.is_empty() {
+                params.push("void".to_string());
+            }
+            declare(&ty.referenced(), &format!("{}({})", inner, params.join(", ")))
+        },
+        DW_TAG_CONST_TYPE | DW_TAG_VOLATILE_TYPE | DW_TAG_RESTRICT_TYPE | DW_TAG_ATOMIC_TYPE => {
+            let qualifier = match ty.tag() {
+                DW_TAG_CONST_TYPE => "const",
+                DW_TAG_VOLATILE_TYPE => "volatile",
+                DW_TAG_RESTRICT_TYPE => "restrict",
+                _ => "_Atomic"
+            };
+            let target = ty.referenced();
+            if target.tag() == DW_TAG_POINTER_TYPE {
+                declare(&target, format!("{} {}", qualifier, inner).trim_end())
+            } else {
+                format!("{} {}", qualifier, declare(&target, inner))
+            }
+        },
+        _ if inner.is_empty() => ty.base_name(),
+        _ => format!("{} {}", ty.base_name(), inner)
+    }
+}
+
+/// A typed value read from the inferior
+#[derive(Clone, Debug)]
+pub struct Value<'a> {
+    pub ty: Type<'a>,
+    pub data: Vec<u8>,
+    /// Where the value lives in the inferior's memory, if it is an lvalue there
+    pub addr: Option<VirtAddr>
+}
+
+impl<'a> Value<'a> {
+    pub fn new(ty: Type<'a>, data: Vec<u8>) -> Self {
+        Self { ty, data, addr: None }
+    }
+
+    /// Reads a value of type `ty` from memory
+    pub fn read(p: &Process, ty: Type<'a>, addr: VirtAddr) -> Result<Self> {
+        let data = p.read_memory_clean(addr, ty.size())?;
+        Ok(Self { ty, data, addr: Some(addr) })
+    }
+
+    /// The value as an integer, sign-extended for signed types
+    pub fn as_u64(&self) -> Result<u64> {
+        if self.ty.is_float() {
+            return Ok(self.as_f64()? as i64 as u64);
+        }
+        if self.data.len() > 8 || self.data.is_empty() || self.ty.is_aggregate() {
+            return error(&format!("{} is not a scalar type", self.ty.name()));
+        }
+        let mut buf = [0u8; 8];
+        buf[..self.data.len()].copy_from_slice(&self.data);
+        let val = u64::from_le_bytes(buf);
+        let bits = self.data.len() * 8;
+        if self.ty.is_signed() && bits < 64 {
+            let shift = 64 - bits;
+            return Ok((((val << shift) as i64) >> shift) as u64);
+        }
+        Ok(val)
+    }
+
+    pub fn as_f64(&self) -> Result<f64> {
+        if !self.ty.is_float() {
+            return Ok(if self.ty.is_signed() { self.as_u64()? as i64 as f64 } else { self.as_u64()? as f64 });
+        }
+        match self.data.len() {
+            4 => Ok(f32::from_le_bytes(self.data[..].try_into().unwrap()) as f64),
+            8 => Ok(f64::from_le_bytes(self.data[..].try_into().unwrap())),
+            10 | 16 => Ok(Extended::from_le_bytes(self.data[..10].try_into().unwrap()).to_f64()),
+            _ => error(&format!("unsupported float size {}", self.data.len()))
+        }
+    }
+
+    /// `*v`: the value a pointer points to, or the first element of an array
+    pub fn deref(&self, p: &Process) -> Result<Value<'a>> {
+        if self.ty.is_array() {
+            return self.index(p, 0);
+        }
+        let Some(target) = self.ty.pointee() else {
+            return error(&format!("cannot dereference a value of type {}", self.ty.name()));
+        };
+        if target.is_void() {
+            return error("attempt to dereference a void pointer");
+        }
+        Value::read(p, target, VirtAddr(self.as_u64()?))
+    }
+
+    /// `v.name`, also looking inside anonymous struct and union members
+    pub fn member(&self, name: &str) -> Result<Value<'a>> {
+        let members = self.ty.members();
+        if members.is_empty() && !matches!(self.ty.strip().tag(), DW_TAG_STRUCTURE_TYPE | DW_TAG_UNION_TYPE) {
+            return error(&format!("{} is not a struct or union", self.ty.name()));
+        }
+        for member in members.iter() {
+            if member.name.as_deref() == Some(name) {
+                return self.member_value(member);
+            }
+            if member.name.is_none() {
+                if let Ok(val) = self.member_value(member).and_then(|v| v.member(name)) {
+                    return Ok(val);
+                }
+            }
+        }
+        error(&format!("{} has no member named {}", self.ty.name(), name))
+    }
+
+    fn member_value(&self, member: &Member<'a>) -> Result<Value<'a>> {
+        let start = member.offset as usize;
+        let size = member.ty.size();
+        let Some((width, bit_off)) = member.bits else {
+            let Some(data) = self.data.get(start..start + size) else {
+                return error("member lies outside its struct");
+            };
+            return Ok(Value {
+                ty: member.ty.clone(),
+                data: data.to_vec(),
+                addr: self.addr.map(|a| a + member.offset)
+            });
+        };
+        let mut buf = [0u8; 8];
+        let avail = self.data.len().saturating_sub(start).min(8);
+        buf[..avail].copy_from_slice(&self.data[start..start + avail]);
+        let mut val = (u64::from_le_bytes(buf) >> bit_off) & (u64::MAX >> (64 - width));
+        if member.ty.is_signed() && val >> (width - 1) & 1 == 1 {
+            val |= u64::MAX << width;
+        }
+        Ok(Value::new(member.ty.clone(), val.to_le_bytes()[..size].to_vec()))
+    }
+
+    /// `v[idx]` for arrays and pointers
+    pub fn index(&self, p: &Process, idx: i64) -> Result<Value<'a>> {
+        if let Some((elem, len)) = self.ty.element() {
+            let size = elem.size();
+            if idx >= 0 && len.is_none_or(|len| (idx as u64) < len) {
+                let start = idx as usize * size;
+                if let Some(data) = self.data.get(start..start + size) {
+                    return Ok(Value {
+                        ty: elem,
+                        data: data.to_vec(),
+                        addr: self.addr.map(|a| a + start as u64)
+                    });
+                }
+            }
+            let Some(addr) = self.addr else {
+                return error(&format!("index {} out of bounds", idx));
+            };
+            return Value::read(p, elem, VirtAddr(addr.0.wrapping_add_signed(idx.wrapping_mul(size as i64))));
+        }
+        let Some(target) = self.ty.pointee() else {
+            return error(&format!("cannot index a value of type {}", self.ty.name()));
+        };
+        if target.is_void() {
+            return error("cannot index a void pointer");
+        }
+        let addr = self.as_u64()?.wrapping_add_signed(idx.wrapping_mul(target.size() as i64));
+        Value::read(p, target, VirtAddr(addr))
+    }
+
+    /// `&v`
+    pub fn address_of(&self) -> Result<Value<'a>> {
+        let Some(addr) = self.addr else {
+            return error("cannot take the address of a value that is not in memory");
+        };
+        Ok(Value::new(self.ty.clone().pointer_to(), addr.0.to_le_bytes().to_vec()))
+    }
+
+    /// Formats the value the way C source would write it
+    pub fn format(&self, p: &Process) -> String {
+        let ty = self.ty.strip();
+        match ty.tag() {
+            0 => "void".to_string(),
+            DW_TAG_POINTER_TYPE | DW_TAG_REFERENCE_TYPE => {
+                let Ok(addr) = self.as_u64() else {
+                    return "<unreadable>".to_string();
+                };
+                let target = ty.pointee().unwrap();
+                if addr != 0 && target.is_char() {
+                    return match read_c_string(p, VirtAddr(addr)) {
+                        Ok(s) => format!("{:#x} {}", addr, s),
+                        Err(e) => format!("{:#x} <{}>", addr, e)
+                    };
+                }
+                if target.strip().tag() == DW_TAG_SUBROUTINE_TYPE {
+                    if let Some((name, 0)) = p.symbol_at(VirtAddr(addr)) {
+                        return format!("{:#x} <{}>", addr, name);
+                    }
+                }
+                format!("{:#x}", addr)
+            },
+            DW_TAG_ARRAY_TYPE => {
+                let (elem, len) = ty.element().unwrap();
+                if elem.is_char() {
+                    let end = self.data.iter().position(|b| *b == 0).unwrap_or(self.data.len());
+                    return quote(&self.data[..end]);
+                }
+                let len = len.unwrap_or(0) as usize;
+                let mut parts: Vec<String> = (0..len.min(PRINT_LIMIT))
+                    .map(|i| match self.index(p, i as i64) {
+                        Ok(v) => v.format(p),
+                        Err(e) => format!("<{}>", e)
+                    })
+                    .collect();
+                if len > PRINT_LIMIT {
+                    parts.push("...".to_string());
+                }
+                format!("{{{}}}", parts.join(", "))
+            },
+            DW_TAG_STRUCTURE_TYPE | DW_TAG_UNION_TYPE => {
+                let parts: Vec<String> = ty.members().iter()
+                    .map(|m| {
+                        let val = match self.member_value(m) {
+                            Ok(v) => v.format(p),
+                            Err(e) => format!("<{}>", e)
+                        };
+                        match &m.name {
+                            Some(name) => format!("{} = {}", name, val),
+                            None => val
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", parts.join(", "))
+            },
+            DW_TAG_ENUMERATION_TYPE => {
+                let Ok(val) = self.as_u64() else {
+                    return "<unreadable>".to_string();
+                };
+                match ty.enumerators().into_iter().find(|(_, v)| *v == val as i64) {
+                    Some((name, _)) => name,
+                    None => format!("{}", val as i64)
+                }
+            },
+            DW_TAG_SUBROUTINE_TYPE => {
+                let addr = self.addr.map(|a| a.0).unwrap_or(0);
+                format!("{{{}}} {:#x}", ty.name(), addr)
+            },
+            _ => self.format_scalar()
+        }
+    }
+
+    fn format_scalar(&self) -> String {
+        if self.ty.is_float() {
+            return match self.as_f64() {
+                Ok(v) if v != 0.0 && (v.abs() < 1e-6 || v.abs() >= 1e16) => format!("{:e}", v),
+                Ok(v) if self.data.len() == 4 => format!("{}", v as f32),
+                Ok(v) => format!("{}", v),
+                Err(e) => format!("<{}>", e)
+            };
+        }
+        let Ok(val) = self.as_u64() else {
+            return "<unreadable>".to_string();
+        };
+        match self.ty.encoding() {
+            Some(DW_ATE_BOOLEAN) => (val != 0).to_string(),
+            Some(DW_ATE_SIGNED_CHAR) | Some(DW_ATE_UNSIGNED_CHAR) if self.data.len() == 1 => {
+                let num = if self.ty.is_signed() { val as i64 as i128 } else { val as i128 };
+                format!("{} {}", num, quote_char(val as u8))
+            },
+            Some(DW_ATE_SIGNED) | Some(DW_ATE_SIGNED_CHAR) => format!("{}", val as i64),
+            _ => format!("{}", val)
+        }
+    }
+}
+
+fn escape(byte: u8, quote: char) -> String {
+    match byte {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        0 => "\\0".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b if b as char == quote => format!("\\{}", quote),
+        0x20..=0x7e => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte)
+    }
+}
+
+fn quote(bytes: &[u8]) -> String {
+    let body: String = bytes.iter().map(|b| escape(*b, '"')).collect();
+    format!("\"{}\"", body)
+}
+
+fn quote_char(byte: u8) -> String {
+    format!("'{}'", escape(byte, '\''))
+}
+
+fn read_c_string(p: &Process, addr: VirtAddr) -> Result<String> {
+    let mut out = Vec::new();
+    while out.len() < PRINT_LIMIT {
+        // don't read across a page boundary in case the next page is unmapped
+        let chunk = (0x1000 - ((addr.0 as usize + out.len()) & 0xfff)).min(PRINT_LIMIT - out.len());
+        let data = p.read_memory_clean(addr + out.len() as u64, chunk)?;
+        if let Some(end) = data.iter().position(|b| *b == 0) {
+            out.extend_from_slice(&data[..end]);
+            return Ok(quote(&out));
+        }
+        out.extend_from_slice(&data);
+    }
+    Ok(format!("{}...", quote(&out)))
+}
+
+/// What evaluating a variable's location needs from the frame it lives in
+struct FrameContext<'a> {
+    p: &'a Process,
+    regs: &'a Registers,
+    cfa: Option<u64>,
+    frame_base: Option<u64>,
+    load_bias: u64
+}
+
+impl ExprContext for FrameContext<'_> {
+    fn read_reg(&self, dwarf_id: u16) -> Result<u64> {
+        Ok(self.regs.read_as::<u64>(register_by_dwarf(dwarf_id as i32)?))
+    }
+
+    fn read_mem(&self, addr: u64, size: usize) -> Result<u64> {
+        let data = self.p.read_memory_clean(VirtAddr(addr), size)?;
+        let mut buf = [0u8; 8];
+        buf[..size].copy_from_slice(&data);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn frame_base(&self) -> Result<u64> {
+        match self.frame_base {
+            Some(base) => Ok(base),
+            None => error("no frame base for this function")
+        }
+    }
+
+    fn cfa(&self) -> Result<u64> {
+        match self.cfa {
+            Some(cfa) => Ok(cfa),
+            None => error("could not compute the CFA for this frame")
+        }
+    }
+
+    fn relocate(&self, addr: u64) -> u64 {
+        addr.wrapping_add(self.load_bias)
+    }
+}
+
+fn location_value(ctx: &FrameContext, result: ExprResult) -> Result<u64> {
+    match result {
+        ExprResult::Address(v) | ExprResult::Value(v) => Ok(v),
+        ExprResult::Register(reg) => ctx.read_reg(reg)
+    }
+}
+
+/// Reads a variable visible from the selected frame: a local or parameter of its function,
+/// or failing that a global in any loaded object
+pub fn read_variable<'a>(p: &'a Process, name: &str) -> Result<Value<'a>> {
+    let idx = p.selected_frame();
+    let Some(frame) = backtrace(p)?.into_iter().nth(idx) else {
+        return error("selected frame no longer exists");
+    };
+    let lookup_pc = if idx == 0 { frame.pc } else { frame.pc - 1u64 };
+    let local = p.object_containing(lookup_pc).and_then(|obj| {
+        obj.debug_info.find_variable(name, Some(obj.to_file(lookup_pc))).map(|var| (obj, var))
+    });
+    let Some((obj, var)) = local.or_else(|| p.loaded_objects().iter()
+            .find_map(|obj| obj.debug_info.find_variable(name, None).map(|var| (obj, var)))) else {
+        return error(&format!("no symbol \"{}\" in current context", name));
+    };
+    let info = &obj.debug_info;
+    let ty = Type::Die(info, var).referenced();
+
+    let mut ctx = FrameContext {
+        p,
+        regs: &frame.regs,
+        cfa: frame.cfa.map(|cfa| cfa.0),
+        frame_base: None,
+        load_bias: obj.load_bias
+    };
+    let func = info.function_containing(obj.to_file(lookup_pc));
+    if let Some(expr) = func.and_then(|f| f.block(DW_AT_FRAME_BASE)) {
+        ctx.frame_base = Some(location_value(&ctx, evaluate(expr, &ctx, &[])?)?);
+    }
+
+    match var.attr(DW_AT_LOCATION) {
+        Some(AttrValue::Block(expr)) => match evaluate(expr, &ctx, &[])? {
+            ExprResult::Address(addr) => Value::read(p, ty, VirtAddr(addr)),
+            ExprResult::Register(reg) => {
+                let size = ty.size().min(8);
+                Ok(Value::new(ty, ctx.read_reg(reg)?.to_le_bytes()[..size].to_vec()))
+            },
+            ExprResult::Value(v) => {
+                let size = ty.size().min(8);
+                Ok(Value::new(ty, v.to_le_bytes()[..size].to_vec()))
+            }
+        },
+        Some(_) => error(&format!("{} has a location list, which is not supported", name)),
+        None => match var.attr(DW_AT_CONST_VALUE) {
+            Some(AttrValue::Block(data)) => Ok(Value::new(ty, data.clone())),
+            Some(AttrValue::Udata(v)) => {
+                let size = ty.size().min(8);
+                Ok(Value::new(ty, v.to_le_bytes()[..size].to_vec()))
+            },
+            Some(AttrValue::Sdata(v)) => {
+                let size = ty.size().min(8);
+                Ok(Value::new(ty, v.to_le_bytes()[..size].to_vec()))
+            },
+            _ => error(&format!("{} has been optimized out", name))
+        }
+    }
+}
+
+/// Finds a type by name in any loaded object's debug info, falling back to builtin C types.
+/// Trailing `*`s make pointer types.
+pub fn lookup_type<'a>(p: &'a Process, name: &str) -> Result<Type<'a>> {
+    let name = name.trim();
+    if let Some(inner) = name.strip_suffix('*') {
+        return lookup_type(p, inner).map(Type::pointer_to);
+    }
+    let found = p.loaded_objects().iter()
+        .find_map(|obj| obj.debug_info.find_type(name).map(|die| Type::Die(&obj.debug_info, die)));
+    match found.or_else(|| Type::builtin(name)) {
+        Some(ty) => Ok(ty),
+        None => error(&format!("no type named {}", name))
+    }
+}
+
+/// Evaluates a variable access such as `argc`, `*ptr`, `s.field`, `p->next->value`
+/// or `&arr[2]`
+pub fn evaluate_variable_path<'a>(p: &'a Process, path: &str) -> Result<Value<'a>> {
+    let path = path.trim();
+    if let Some(rest) = path.strip_prefix('*') {
+        return evaluate_variable_path(p, rest)?.deref(p);
+    }
+    if let Some(rest) = path.strip_prefix('&') {
+        return evaluate_variable_path(p, rest)?.address_of();
+    }
+    let name_end = path.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(path.len());
+    if name_end == 0 {
+        return error(&format!("could not parse {}", path));
+    }
+    let mut val = read_variable(p, &path[..name_end])?;
+    let mut rest = &path[name_end..];
+    while !rest.is_empty() {
+        let (field, deref) = if let Some(r) = rest.strip_prefix("->") {
+            (r, true)
+        } else if let Some(r) = rest.strip_prefix('.') {
+            (r, false)
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let Some((idx, r)) = r.split_once(']') else {
+                return error("missing ]");
+            };
+            val = val.index(p, crate::parse_u64(idx.trim())? as i64)?;
+            rest = r;
+            continue;
+        } else {
+            return error(&format!("could not parse {}", rest));
+        };
+        let end = field.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(field.len());
+        if deref {
+            val = val.deref(p)?;
+        }
+        val = val.member(&field[..end])?;
+        rest = &field[end..];
+    }
+    Ok(val)
+}
+
+/// Describes a type for `ptype`, laying out the members of structs and unions
+pub fn describe_type(ty: &Type) -> String {
+    let stripped = ty.strip();
+    match stripped.tag() {
+        DW_TAG_STRUCTURE_TYPE | DW_TAG_UNION_TYPE => {
+            let mut out = format!("{} {{\n", stripped.base_name());
+            for m in stripped.members() {
+                let mut decl = declare(&m.ty, m.name.as_deref().unwrap_or(""));
+                if let Some((width, _)) = m.bits {
+                    decl.push_str(&format!(" : {}", width));
+                }
+                out.push_str(&format!("    {:<32} /* offset {}, size {} */\n", format!("{};", decl), m.offset, m.ty.size()));
+            }
+            out.push_str(&format!("}} /* size {} */", stripped.size()));
+            out
+        },
+        DW_TAG_ENUMERATION_TYPE => {
+            let values: Vec<String> = stripped.enumerators().iter()
+                .map(|(name, val)| format!("{} = {}", name, val))
+                .collect();
+            format!("{} {{{}}}", stripped.base_name(), values.join(", "))
+        },
+        _ => ty.name()
+    }
+}
@@ -3,19 +3,16 @@
 use std::fs::read_to_string;
 use std::fs;
 use std::io;
-use std::io::BufReader;
-use std::io::BufRead;
 use std::env;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::os::fd::AsRawFd;
-use std::process::Command;
-use std::process::Stdio;
 use std::thread;
 use std::time;
 use extended::Extended;
 
 use gadb::BreakSite;
+use gadb::StopPoint;
 use gadb::RValue;
 use gadb::VirtAddr;
 use nix::{
@@ -24,12 +21,42 @@ use nix::{
 };
 
 use gadb::{
+    Elf,
+    FileAddr,
+    LoadedObject,
+    Location,
+    AT_ENTRY,
     Process,
+    ForkMode,
+    SignalPolicy,
+    MemoryMap,
+    parse_signal,
+    parse_syscall,
+    syscall_id,
+    syscall_name,
+    SyscallCatchPolicy,
+    SyscallFault,
+    FaultAction,
+    Occurrence,
+    parse_errno,
+    parse_syscall_fault,
+    SyscallData,
     Result,
     error,
     Pipe,
     RegisterId,
-    extend_vec
+    ProcessState,
+    TrapType,
+    WatchMode,
+    backtrace,
+    describe_type,
+    disassemble,
+    evaluate_address,
+    evaluate_expression,
+    evaluate_variable_path,
+    lookup_type,
+    extend_vec,
+    parse_location
 };
 use nix::sys::ptrace;
 use regex::Regex;
@@ -111,6 +138,12 @@ fn get_test_binary(name: &str) -> PathBuf {
     PathBuf::from(path_str)
 }
 
+/// Launches a test binary with what it prints thrown away
+fn launch_quiet(name: &str) -> Process {
+    let devnull = File::options().write(true).open("/dev/null").unwrap();
+    Process::launch_args(get_test_binary(name).to_str().unwrap(), vec![], true, Some(devnull.as_raw_fd())).unwrap()
+}
+
 fn re_wait(p: &mut Process) {
     &p.resume();
     &p.wait_on_signal();
@@ -214,37 +247,11 @@ fn parse_hex(s: &str) -> Result<u64> {
 }
 fn find_memory_offset(elf: &str, file_addr: VirtAddr) -> Result<VirtAddr> {
     // Read ELF to translate file offset to memory offset
-    let mut cmd = Command::new("readelf")
-        .arg("-WS")
-        .arg(elf)
-        .stdout(Stdio::piped())
-        .spawn();
-    let Ok(mut cmd) = cmd else {
-        return error("could not launch readelf");
+    let elf = Elf::load(Path::new(elf))?;
+    let Some(sec) = elf.section_containing_addr(file_addr.0) else {
+        return error("address is not inside any section");
     };
-
-    let stdout = cmd.stdout.take()
-        .expect("Child process stdout handle was not configured");
-
-    let re = Regex::new(r"PROGBITS\s+([0-9a-f]+)\s+([0-9a-f]+)\s+([0-9a-f]+)").unwrap();
-    let reader = BufReader::new(stdout);
-    for line in reader.lines() {
-        let line = match line {
-            Ok(line) => line,
-            Err(_) => continue
-        };
-        let Some(cap) = re.captures(&line) else {
-            continue;
-        };
-        let (_, [addr, off, size]) = cap.extract();
-        let sec_addr = parse_hex(addr)?;
-        let sec_off = parse_hex(off)?;
-        let sec_size = parse_hex(size)?;
-        if file_addr.0 >= sec_addr && file_addr.0 < sec_addr + sec_size {
-            return Ok((file_addr + sec_addr - sec_off).into())
-        }
-    }
-    return error("could not parse readelf output");
+    Ok(file_addr - sec.addr + sec.offset)
 }
 
 fn find_memory_address(elf: &str, pid: Pid, file_addr: VirtAddr) -> Result<VirtAddr> {
@@ -282,7 +289,8 @@ fn basic_breakpoints() {
 
     let mut proc = Process::launch_args(test_binary.to_str().unwrap(), vec![], true, Some(pipe.get_write().as_raw_fd())).unwrap();
     pipe.close_write();
-    let bp_file_addr: VirtAddr = 0x115b.into();
+    let elf = Elf::load(&test_binary).unwrap();
+    let bp_file_addr: VirtAddr = elf.symbols_by_name("main")[0].value.into();
     let bp_mem_addr = find_memory_address(test_binary.to_str().unwrap(), proc.pid, bp_file_addr).unwrap();
     let bpid = proc.create_breaksite(bp_mem_addr).unwrap();
     proc.enable_breaksite_by(bpid);
@@ -318,4 +326,1017 @@ fn memory_read() {
 
     let data = pipe.read_string().unwrap();
     assert!(data == str);
-}
\ No newline at end of file
+}
+#[test]
+fn hardware_watchpoints() {
+    let test_binary = get_test_binary("memory");
+    assert!(test_binary.exists(), "Test binary not found at {:?}", test_binary);
+
+    let mut pipe = Pipe::pipe(false).unwrap();
+
+    let mut proc = Process::launch_args(test_binary.to_str().unwrap(), vec![], true, Some(pipe.get_write().as_raw_fd())).unwrap();
+    pipe.close_write();
+    re_wait(&mut proc);
+    let _ = pipe.read().unwrap();
+    re_wait(&mut proc);
+
+    let data = pipe.read().unwrap();
+    let addr: u64 = u64::from_le_bytes(data.try_into().unwrap());
+
+    let mut ids = Vec::new();
+    for i in 0..4u64 {
+        let id = proc.create_watchpoint((addr + i).into(), 1, WatchMode::ReadWrite).unwrap();
+        proc.enable_watchpoint(id).unwrap();
+        ids.push(id);
+    }
+    let extra = proc.create_watchpoint((addr + 4).into(), 1, WatchMode::ReadWrite).unwrap();
+    assert!(proc.enable_watchpoint(extra).is_err());
+    assert!(proc.create_watchpoint((addr + 1).into(), 2, WatchMode::Write).is_err());
+
+    for id in &ids[1..] {
+        proc.clear_watchpoint(*id).unwrap();
+    }
+    proc.clear_watchpoint(extra).unwrap();
+    assert!(proc.regs().read_as_id::<u64>(RegisterId::dr7) & 0b11 == 0b01);
+
+    proc.resume().unwrap();
+    let reason = proc.wait_on_signal().unwrap();
+    assert!(reason.is_signal());
+    assert!(proc.state == ProcessState::Stopped);
+    assert!(reason.trap() == Some(TrapType::HardwareBreak(Some(ids[0]))));
+}
+
+#[test]
+fn step_instruction() {
+    let test_binary = get_test_binary("hello_world");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let start = proc.get_pc();
+    let original = proc.read_memory(start, 1).unwrap();
+    let id = proc.create_breaksite(start).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+
+    let reason = proc.step_instruction().unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(proc.get_pc() != start);
+    assert!(proc.breaksite_by_id(id).unwrap().enabled());
+    assert!(proc.read_memory(start, 1).unwrap() == vec![0xcc]);
+    assert!(proc.read_memory_clean(start, 1).unwrap() == original);
+}
+
+#[test]
+fn step_over_call() {
+    let test_binary = get_test_binary("hello_world");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    // The dynamic loader's entry point calls _dl_start within its first few instructions
+    let call = loop {
+        let inst = disassemble(&proc, proc.get_pc(), Some(1)).unwrap().remove(0);
+        if inst.is_call() {
+            break inst;
+        }
+        proc.step_instruction().unwrap();
+    };
+    let return_addr = call.va + call.opcodes.len();
+    let reason = proc.step_over_instruction().unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(proc.get_pc() == return_addr);
+    assert!(proc.breaksite_at_va(return_addr).is_none());
+    assert!(proc.breaksites().is_empty());
+
+    // the recursive calls made from the same place return there first
+    let test_binary = get_test_binary("recursion");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let addr = proc.resolve_location(&parse_location("depth").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    proc.disable_breaksite_by(id).unwrap();
+    while !disassemble(&proc, proc.get_pc(), Some(1)).unwrap()[0].is_call() {
+        proc.step_instruction().unwrap();
+    }
+    let rsp = proc.regs().read_as_id::<u64>(RegisterId::rsp);
+    let reason = proc.step_over_instruction().unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(proc.regs().read_as_id::<u64>(RegisterId::rsp) == rsp);
+    assert!(proc.regs().read_as_id::<u64>(RegisterId::rax) == 2);
+}
+
+#[test]
+fn step_out() {
+    let test_binary = get_test_binary("hello_world");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let call = loop {
+        let inst = disassemble(&proc, proc.get_pc(), Some(1)).unwrap().remove(0);
+        if inst.is_call() {
+            break inst;
+        }
+        proc.step_instruction().unwrap();
+    };
+    let return_addr = call.va + call.opcodes.len();
+    proc.step_instruction().unwrap();
+    assert!(proc.get_pc() != return_addr);
+
+    let reason = proc.step_out().unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(proc.get_pc() == return_addr);
+    assert!(proc.breaksites().is_empty());
+
+    // without unwind information the return address cannot be found past the prologue
+    let mut proc = Process::launch(get_test_binary("reg_write").to_str().unwrap()).unwrap();
+    re_wait(&mut proc);
+    assert!(proc.step_out().is_err());
+    assert!(proc.breaksites().is_empty());
+}
+
+#[test]
+fn elf_parsing() {
+    let test_binary = get_test_binary("hello_world");
+    let elf = Elf::load(&test_binary).unwrap();
+
+    let text = elf.section(".text").unwrap();
+    assert!(text.size > 0);
+    assert!(elf.section(".symtab").is_some());
+    assert!(elf.segments.iter().any(|seg| seg.p_type == libc::PT_LOAD));
+
+    let main = elf.symbols_by_name("main");
+    assert!(main.len() == 1);
+    assert!(text.contains_addr(main[0].value));
+    assert!(elf.symbol_containing_addr(main[0].value + 1).unwrap().name == "main");
+    assert!(!elf.symbols_by_name("printf").is_empty() || !elf.symbols_by_name("puts").is_empty());
+    assert!(Elf::is_elf_file(&test_binary));
+    assert!(!Elf::is_elf_file(Path::new("src/targets/hello_world.c")) && !Elf::is_elf_file(Path::new("/dev/zero")));
+
+    let proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    assert!(proc.elf().unwrap().symbols_by_name("main").len() == 1);
+
+    // an executable that cannot be read back still runs, just without symbols
+    let copy = env::temp_dir().join(format!("gadb-elf-{}", std::process::id()));
+    fs::copy(env!("CARGO_BIN_EXE_quietwait"), &copy).unwrap();
+    let proc = Process::launch_noattach(copy.to_str().unwrap()).unwrap();
+    fs::remove_file(&copy).unwrap();
+    let proc2 = Process::attach(proc.pid.as_raw()).unwrap();
+    assert!(proc2.elf().is_none() && proc2.find_symbol("main").is_empty());
+}
+
+#[test]
+fn symbol_breakpoints() {
+    let test_binary = get_test_binary("hello_world");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    assert!(parse_location("main+0x4").unwrap() == Location::Symbol(String::from("main"), 4));
+    assert!(proc.resolve_location(&parse_location("printf").unwrap()).is_err());
+
+    let main = proc.resolve_location(&parse_location("main").unwrap()).unwrap();
+    let id = proc.create_breaksite(main).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    assert!(proc.get_pc() == main);
+    assert!(proc.symbol_at(main + 1u64) == Some((String::from("main"), 1)));
+
+    // libc is mapped by the time main runs
+    let printf = proc.resolve_location(&parse_location("printf").unwrap()).unwrap();
+    let id = proc.create_breaksite(printf).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    assert!(proc.get_pc() == printf);
+    assert!(proc.symbol_at(printf).unwrap().0 == "printf");
+}
+
+#[test]
+fn address_translation() {
+    let test_binary = get_test_binary("hello_world");
+    let proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let elf = proc.elf().unwrap();
+    let path = elf.path.clone();
+    let main = FileAddr(elf.symbols_by_name("main")[0].value);
+
+    let va = proc.file_to_virt(&path, main).unwrap();
+    let expected = find_memory_address(test_binary.to_str().unwrap(), proc.pid, VirtAddr(main.0)).unwrap();
+    assert!(va == expected);
+
+    let (obj, addr) = proc.virt_to_file(va).unwrap();
+    assert!(obj.path() == path);
+    assert!(addr == main);
+
+    let auxv = proc.auxv().unwrap();
+    assert!(proc.file_to_virt(&path, FileAddr(elf.header.e_entry)).unwrap() == VirtAddr(auxv[&AT_ENTRY]));
+}
+
+#[test]
+fn memory_map() {
+    let test_binary = get_test_binary("hello_world");
+    let proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let map = proc.memory_map().unwrap();
+    let pc_region = map.region_containing(proc.get_pc()).unwrap();
+    assert!(pc_region.perms.read && pc_region.perms.execute && !pc_region.perms.write);
+    assert!(pc_region.path.as_ref().unwrap().contains("ld-linux"));
+
+    let exe = proc.elf().unwrap().path.to_str().unwrap().to_string();
+    assert!(map.regions.iter().any(|r| r.path.as_ref() == Some(&exe) && r.offset == 0));
+    assert!(map.regions.iter().any(|r| r.path.as_deref() == Some("[stack]") && r.perms.write));
+
+    let err = proc.read_memory(VirtAddr(0), 8).err().unwrap();
+    assert!(err.to_string() == "address 0x0 not mapped");
+    let err = proc.write_memory(VirtAddr(8), vec![1, 2, 3, 4, 5, 6, 7, 8]).err().unwrap();
+    assert!(err.to_string() == "address 0x8 not mapped");
+    let err = proc.read_memory(VirtAddr(0xfffffffffffffff8), 16).err().unwrap();
+    assert!(err.to_string() == "address 0xfffffffffffffff8 not mapped");
+}
+
+#[test]
+fn source_line_tables() {
+    for name in ["steps", "steps_dwarf4"] {
+        let test_binary = get_test_binary(name);
+        let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+        let addr = proc.resolve_location(&parse_location("steps.c:15").unwrap()).unwrap();
+        let id = proc.create_breaksite(addr).unwrap();
+        proc.enable_breaksite_by(id).unwrap();
+        re_wait(&mut proc);
+        assert!(proc.get_pc() == addr);
+        let loc = proc.source_location(proc.get_pc()).unwrap();
+        assert!(loc.file.ends_with("src/targets/steps.c"));
+        assert!(loc.file.is_absolute());
+        assert!(proc.loaded_objects().iter().all(|o| o.debug_errors.is_empty()));
+        assert!(loc.line == 15);
+
+        // Line 7 is blank, so the breakpoint moves to the next line with code
+        let blank = proc.resolve_location(&parse_location("steps.c:7").unwrap()).unwrap();
+        assert!(proc.source_location(blank).unwrap().line == 8);
+
+        let add = proc.resolve_location(&parse_location("add").unwrap()).unwrap();
+        assert!(proc.source_location(add).unwrap().line == 3);
+        assert!(proc.resolve_location(&parse_location("steps.c:400").unwrap()).is_err());
+    }
+
+    // a line table that cannot be read is reported rather than silently left out
+    let test_binary = get_test_binary("steps");
+    let mut data = fs::read(&test_binary).unwrap();
+    let debug_line = Elf::load(&test_binary).unwrap().section(".debug_line").unwrap().offset as usize;
+    data[debug_line + 4] = 99;
+    let object = LoadedObject::new(Elf::parse(&test_binary, data).unwrap(), 0);
+    assert!(object.lines.tables.is_empty() && !object.debug_info.is_empty());
+    assert!(object.debug_errors.len() == 1 && object.debug_errors[0].contains("version 99"));
+
+    let mut data = fs::read(&test_binary).unwrap();
+    data[debug_line..debug_line + 4].copy_from_slice(&0x7fffffffu32.to_le_bytes());
+    let object = LoadedObject::new(Elf::parse(&test_binary, data).unwrap(), 0);
+    assert!(object.debug_errors.len() == 1 && object.debug_errors[0].contains("runs past the end of .debug_line"));
+}
+
+#[test]
+fn source_stepping() {
+    let mut proc = launch_quiet("steps");
+    let line = |proc: &Process| proc.source_location(proc.get_pc()).unwrap().line;
+
+    let addr = proc.resolve_location(&parse_location("steps.c:15").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+
+    // step enters twice() past its prologue, next steps over add()
+    proc.step_line(false).unwrap();
+    assert!(line(&proc) == 9);
+    proc.step_line(true).unwrap();
+    assert!(line(&proc) == 10);
+
+    // finish returns to the middle of line 15
+    let reason = proc.step_out().unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(line(&proc) == 15);
+    assert!(proc.regs().read_as_id::<u64>(RegisterId::rax) == 6);
+
+    proc.step_line(false).unwrap();
+    assert!(line(&proc) == 16);
+    // printf has no line info, so step runs over it instead of descending into libc
+    let reason = proc.step_line(false).unwrap();
+    assert!(reason.trap() == Some(TrapType::SingleStep));
+    assert!(line(&proc) == 17);
+    assert!(proc.breaksites().len() == 1);
+
+    // stepping off the end of main returns into libc, which runs on to exit
+    proc.step_line(false).unwrap();
+    assert!(line(&proc) == 18);
+    let reason = proc.step_line(false).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+}
+
+#[test]
+fn backtrace_unwinding() {
+    let test_binary = get_test_binary("steps");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let addr = proc.resolve_location(&parse_location("add").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    proc.refresh_loaded_objects().unwrap();
+
+    let frames = backtrace(&proc).unwrap();
+    let names: Vec<&str> = frames.iter()
+        .filter_map(|f| f.symbol.as_ref().map(|(name, _)| name.as_str()))
+        .collect();
+    assert!(names.starts_with(&["add", "twice", "main"]));
+    assert!(frames[1].location.as_ref().unwrap().line == 9);
+    assert!(frames[2].location.as_ref().unwrap().line == 15);
+    // each caller's rsp is the callee's CFA, just past the return address
+    assert!(frames[0].cfa.unwrap().0 == frames[1].regs.read_as_id::<u64>(RegisterId::rsp));
+    let ret = proc.read_memory(frames[0].cfa.unwrap() - 8u64, 8).unwrap();
+    assert!(u64::from_le_bytes(ret[..].try_into().unwrap()) == frames[1].pc.0);
+    // unwinding carries on through libc to the entry point
+    assert!(names.last() == Some(&"_start"));
+
+    // an entry longer than .eh_frame is dropped rather than read past the end
+    let elf = Elf::load(&test_binary).unwrap();
+    let eh_frame = elf.section(".eh_frame").unwrap();
+    let text = elf.section(".text").unwrap();
+    let mut data = fs::read(&test_binary).unwrap();
+    let (mut pos, mut last) = (eh_frame.offset as usize, 0);
+    while pos < (eh_frame.offset + eh_frame.size) as usize {
+        let len = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+        if len == 0 {
+            break;
+        }
+        last = pos;
+        pos += 4 + len;
+    }
+    data[last..last + 4].copy_from_slice(&((pos - last + 0x1000) as u32).to_le_bytes());
+    let object = LoadedObject::new(Elf::parse(&test_binary, data).unwrap(), 0);
+    assert!(!object.frames.is_empty());
+    for addr in text.addr..text.addr + text.size {
+        let _ = object.frames.row_at(FileAddr(addr));
+    }
+}
+
+#[test]
+fn frame_selection() {
+    let test_binary = get_test_binary("steps");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    // past add()'s prologue, so twice()'s rbp has been pushed
+    let addr = proc.resolve_location(&parse_location("steps.c:4").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+
+    let live_rbp = proc.regs().read_as_id::<u64>(RegisterId::rbp);
+    let frame = proc.select_frame(1).unwrap();
+    assert!(proc.selected_frame() == 1);
+    assert!(frame.symbol.unwrap().0 == "twice");
+    let regs = proc.frame_registers(1).unwrap();
+    assert!(regs.read_as_id::<u64>(RegisterId::rip) == frame.pc.0);
+    let outer_rbp = regs.read_as_id::<u64>(RegisterId::rbp);
+    assert!(outer_rbp > live_rbp);
+
+    // rbp was pushed by add(), so writing it in twice()'s frame changes the saved copy
+    let slot = frame.saved_regs[&6];
+    proc.write_frame_reg(1, &RValue::from_id(0x1234u64, RegisterId::rbp)).unwrap();
+    assert!(proc.frame_registers(1).unwrap().read_as_id::<u64>(RegisterId::rbp) == 0x1234);
+    assert!(proc.read_memory(slot, 8).unwrap() == 0x1234u64.to_le_bytes());
+    assert!(proc.regs().read_as_id::<u64>(RegisterId::rbp) == live_rbp);
+    proc.write_frame_reg(1, &RValue::from_id(outer_rbp, RegisterId::rbp)).unwrap();
+
+    // caller-saved registers aren't recoverable in outer frames
+    assert!(proc.write_frame_reg(1, &RValue::from_id(0u64, RegisterId::rax)).is_err());
+    assert!(proc.select_frame(100).is_err());
+    assert!(proc.selected_frame() == 1);
+
+    // any stop puts the selection back on the innermost frame
+    proc.step_instruction().unwrap();
+    assert!(proc.selected_frame() == 0);
+}
+
+#[test]
+fn variable_inspection() {
+    let test_binary = get_test_binary("variables");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let addr = proc.resolve_location(&parse_location("variables.c:42").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    proc.refresh_loaded_objects().unwrap();
+
+    let print = |proc: &Process, path: &str| evaluate_variable_path(proc, path).unwrap().format(proc);
+    assert!(print(&proc, "argc") == "1");
+    assert!(print(&proc, "total") == "77");
+    assert!(print(&proc, "shape->name") == "\"square\"");
+    assert!(print(&proc, "shape->origin.y") == "4");
+    assert!(print(&proc, "first.next->value") == "2");
+    assert!(print(&proc, "*first.next") == "{value = 2, next = 0x0}");
+    assert!(print(&proc, "shape->color") == "BLUE");
+    assert!(print(&proc, "shape->delta") == "-3");
+    assert!(print(&proc, "shape->flags") == "5");
+    assert!(print(&proc, "shape->num.i") == "7");
+    assert!(print(&proc, "counter") == "42");
+    assert!(print(&proc, "grid") == "{{1, 2, 3}, {4, 5, 6}}");
+    assert!(print(&proc, "grid[1][2]") == "6");
+    assert!(evaluate_expression(&proc, "grid[1][-1]").unwrap().format(&proc) == "3");
+    let _ = evaluate_expression(&proc, "grid[-1]");
+    let _ = evaluate_expression(&proc, "grid[4611686018427387904]");
+    assert!(print(&proc, "*greeting") == "104 'h'");
+    assert!(print(&proc, "greeting").ends_with(" \"hello\""));
+    assert!(evaluate_variable_path(&proc, "scaled").is_err());
+
+    let counter_addr = proc.find_symbol("counter")[0];
+    let addr_of = evaluate_variable_path(&proc, "&counter").unwrap();
+    assert!(addr_of.as_u64().unwrap() == counter_addr.0);
+    assert!(addr_of.ty.name() == "int *");
+
+    assert!(evaluate_variable_path(&proc, "shape").unwrap().ty.name() == "shape_t *");
+    assert!(evaluate_variable_path(&proc, "grid").unwrap().ty.name() == "int [2][3]");
+    let node = lookup_type(&proc, "struct node").unwrap();
+    assert!(node.size() == 16);
+    let layout = describe_type(&node);
+    assert!(layout.contains("struct node *next;"));
+    assert!(layout.contains("offset 8, size 8"));
+    assert!(describe_type(&lookup_type(&proc, "enum color").unwrap()) == "enum color {RED = 0, GREEN = 5, BLUE = 6}");
+    assert!(lookup_type(&proc, "shape_t").unwrap().size() == 48);
+
+    // parameters of an outer frame are found through that frame's CFA
+    proc.select_frame(1).unwrap();
+    assert!(print(&proc, "shape.origin.x") == "3");
+    assert!(print(&proc, "shape.scale") == "1.5");
+
+    // a unit longer than the section is reported rather than read past the end
+    let mut data = fs::read(&test_binary).unwrap();
+    let debug_info = Elf::load(&test_binary).unwrap().section(".debug_info").unwrap().offset as usize;
+    data[debug_info..debug_info + 4].copy_from_slice(&0x7fffffffu32.to_le_bytes());
+    let object = LoadedObject::new(Elf::parse(&test_binary, data).unwrap(), 0);
+    assert!(object.debug_info.is_empty());
+    assert!(object.debug_errors.iter().any(|e| e.contains("runs past the end of .debug_info")));
+}
+
+#[test]
+fn expression_evaluation() {
+    let test_binary = get_test_binary("variables");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let addr = proc.resolve_location(&parse_location("variables.c:42").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    proc.refresh_loaded_objects().unwrap();
+
+    let eval = |proc: &Process, text: &str| evaluate_expression(proc, text).unwrap().as_u64().unwrap();
+    assert!(eval(&proc, "1 + 2 * 3") == 7);
+    assert!(eval(&proc, "(1 + 2) * 3") == 9);
+    assert!(eval(&proc, "0xf0 | 0x0f ^ 0x3") == 0xfc);
+    assert!(eval(&proc, "1 << 4 >> 2") == 4);
+    assert!(eval(&proc, "-7 / 2") as i64 == -3);
+    assert!(eval(&proc, "3 < 4 && 4 <= 4 && !(2 == 3)") == 1);
+    assert!(eval(&proc, "0 && *(int*)0") == 0);
+    assert!(eval(&proc, "1 || *(int*)0") == 1);
+    assert!(eval(&proc, "(0 && *(int*)0) + 2 * (1 || grid[1][nosuch])") == 2);
+    assert!(eval(&proc, "0 && 1 || 1") == 1);
+    assert!(evaluate_expression(&proc, "1 && *(int*)0").is_err());
+    assert!(evaluate_expression(&proc, "0 &&").is_err());
+    assert!(eval(&proc, "~0 == -1") == 1);
+    assert!(evaluate_expression(&proc, "1 / 0").is_err());
+    assert!(evaluate_expression(&proc, "1 +").is_err());
+
+    let rsp = proc.regs().read_as_id::<u64>(RegisterId::rsp);
+    assert!(eval(&proc, "$rsp+0x20") == rsp + 0x20);
+    assert!(eval(&proc, "$sp") == rsp);
+    let top = u64::from_le_bytes(proc.read_memory(VirtAddr(rsp), 8).unwrap().try_into().unwrap());
+    assert!(eval(&proc, "*(uint64_t*)$rsp") == top);
+    assert!(eval(&proc, "*(unsigned char*)($rsp + 1)") == (top >> 8) & 0xff);
+    assert!(evaluate_expression(&proc, "$nosuch").is_err());
+
+    // symbols without debug info evaluate to their address
+    let main_addr = proc.find_symbol("main")[0];
+    assert!(eval(&proc, "main + 4") == main_addr.0 + 4);
+    assert!(evaluate_address(&proc, "&grid[1]").unwrap().0 == proc.find_symbol("grid")[0].0 + 12);
+
+    // pointer arithmetic scales by the pointee and arrays decay to pointers
+    assert!(eval(&proc, "*(grid[1] + 2)") == 6);
+    assert!(eval(&proc, "&grid[1][2] - &grid[0][0]") == 5);
+    assert!(eval(&proc, "shape->origin.x * 10 + total") == 107);
+    assert!(eval(&proc, "(char)(counter + 256)") == 42);
+    assert!(evaluate_expression(&proc, "shape->scale * 2").unwrap().as_f64().unwrap() == 3.0);
+}
+
+#[test]
+fn shared_library_tracking() {
+    let test_binary = get_test_binary("dlopen");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    assert!(proc.shared_libraries().is_empty());
+
+    let addr = proc.resolve_location(&parse_location("dlopen.c:9").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    // the loader's breakpoint fires on the way here, but is continued through
+    re_wait(&mut proc);
+    assert!(proc.get_pc() == addr);
+
+    // no manual refresh: the library list and symbols were updated when dlopen returned
+    let names: Vec<&str> = proc.shared_libraries().iter().map(|lib| lib.name.as_str()).collect();
+    assert!(names.iter().any(|n| n.contains("libc.so")));
+    assert!(names.iter().any(|n| n.ends_with("libplugin.so")));
+    let plugin = proc.shared_libraries().iter().find(|lib| lib.name.ends_with("libplugin.so")).unwrap();
+    let object = proc.loaded_objects().iter().find(|o| o.name() == "libplugin.so").unwrap();
+    assert!(object.load_bias == plugin.base);
+
+    let answer = proc.find_symbol("plugin_answer");
+    assert!(answer.len() == 1);
+    let id = proc.create_breaksite(answer[0]).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.trap() == Some(TrapType::SoftwareBreak(Some(id))));
+    assert!(proc.symbol_at(proc.get_pc()).unwrap().0 == "plugin_answer");
+
+    // dlclose takes the library back out and leaves its breakpoint disabled
+    let addr = proc.resolve_location(&parse_location("dlopen.c:11").unwrap()).unwrap();
+    let id = proc.create_breaksite(addr).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    assert!(proc.get_pc() == addr);
+    assert!(!proc.shared_libraries().iter().any(|lib| lib.name.ends_with("libplugin.so")));
+    assert!(proc.find_symbol("plugin_answer").is_empty());
+    assert!(!proc.breaksite_at_va(answer[0]).unwrap().enabled());
+
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.is_exit());
+    assert!(reason.to_string() == "exited with exit code 42");
+}
+
+#[test]
+fn pending_breakpoints() {
+    let test_binary = get_test_binary("pending");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let answer = proc.create_breakpoint(parse_location("plugin_answer").unwrap()).unwrap();
+    assert!(proc.breakpoint_by_id(answer).unwrap().is_pending());
+    let twice = proc.create_breakpoint(parse_location("twice").unwrap()).unwrap();
+    assert!(proc.breakpoint_by_id(twice).unwrap().sites.len() == 1);
+    assert!(proc.create_breakpoint(Location::Address(0)).is_err());
+    assert!(proc.breakpoints().len() == 2);
+
+    // a second breakpoint on the same location shares its breaksite
+    let again = proc.create_breakpoint(parse_location("twice").unwrap()).unwrap();
+    let site = proc.breakpoint_by_id(twice).unwrap().sites[0];
+    assert!(proc.breakpoint_by_id(again).unwrap().sites == vec![site]);
+    proc.disable_breakpoint(twice).unwrap();
+    assert!(proc.breaksite_by_id(site).unwrap().enabled());
+    assert!(proc.breakpoint_for_site(site).unwrap().id == again);
+    proc.clear_breakpoint(again).unwrap();
+    assert!(!proc.breaksite_by_id(site).unwrap().enabled());
+    proc.enable_breakpoint(twice).unwrap();
+    assert!(proc.breaksite_by_id(site).unwrap().enabled());
+
+    let hit = |proc: &mut Process| {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        let Some(TrapType::SoftwareBreak(Some(site))) = reason.trap() else {
+            panic!("expected a breakpoint, got {}", reason);
+        };
+        (proc.breakpoint_for_site(site).unwrap().id, proc.symbol_at(proc.get_pc()).unwrap().0)
+    };
+    assert!(hit(&mut proc) == (twice, "twice".to_string()));
+
+    // loading the plugin resolves the pending breakpoint and adds a second site for twice
+    assert!(hit(&mut proc) == (answer, "plugin_answer".to_string()));
+    assert!(!proc.breakpoint_by_id(answer).unwrap().is_pending());
+    assert!(proc.breakpoint_by_id(twice).unwrap().sites.len() == 2);
+    assert!(hit(&mut proc) == (twice, "twice".to_string()));
+    assert!(proc.object_containing(proc.get_pc()).unwrap().name() == "libpending.so");
+
+    // unloading it leaves the breakpoint pending again
+    let end = proc.resolve_location(&parse_location("pending.c:15").unwrap()).unwrap();
+    let id = proc.create_breaksite(end).unwrap();
+    proc.enable_breaksite_by(id).unwrap();
+    re_wait(&mut proc);
+    assert!(proc.get_pc() == end);
+    assert!(proc.breakpoint_by_id(answer).unwrap().is_pending());
+    assert!(proc.breakpoint_by_id(twice).unwrap().sites.len() == 1);
+
+    proc.disable_breakpoint(twice).unwrap();
+    assert!(!proc.breaksite_by_id(proc.breakpoint_by_id(twice).unwrap().sites[0]).unwrap().enabled());
+    proc.clear_breakpoint(answer).unwrap();
+    assert!(proc.breakpoints().len() == 1);
+}
+
+#[test]
+fn multithreaded_inferior() {
+    let test_binary = get_test_binary("threads");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    let worker = proc.create_breakpoint(parse_location("worker").unwrap()).unwrap();
+    let entry = proc.resolve_location(&parse_location("worker").unwrap()).unwrap();
+    let mut tids = Vec::new();
+    for _ in 0..3 {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(matches!(reason.trap(), Some(TrapType::SoftwareBreak(Some(_)))));
+        assert!(reason.thread() == Some(proc.current_thread()));
+        assert!(proc.get_pc() == entry);
+        // every other thread was stopped along with the one that hit the breakpoint
+        assert!(proc.threads().iter().all(|t| t.state == ProcessState::Stopped));
+        assert!(!tids.contains(&proc.tid()) && proc.tid() != proc.pid);
+        tids.push(proc.tid());
+    }
+
+    // switching threads switches the registers everything else reads
+    let current = proc.current_thread();
+    proc.select_thread(1).unwrap();
+    assert!(proc.tid() == proc.pid);
+    assert!(proc.get_pc() != entry);
+    assert!(proc.select_thread(100).is_err());
+    proc.select_thread(current).unwrap();
+
+    proc.clear_breakpoint(worker).unwrap();
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 6");
+
+    // stepping over the syscall that creates a thread stops right after it
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let clones = ["clone", "clone3"].iter().filter_map(|name| syscall_id(name)).collect();
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(clones));
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.syscall().is_some_and(|info| info.is_entry()));
+    let reason = proc.step_instruction().unwrap();
+    assert!(reason.syscall().is_none() && proc.threads().len() == 2);
+    let child = proc.regs().read_as_id::<u64>(RegisterId::rax);
+    assert!(proc.threads()[1].tid.as_raw() as u64 == child);
+
+    // watchpoints set before the threads exist are copied to each of them
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let finished = proc.find_symbol("finished")[0];
+    let watch = proc.create_watchpoint(finished, 4, WatchMode::Write).unwrap();
+    proc.enable_watchpoint(watch).unwrap();
+    let mut tids = Vec::new();
+    for _ in 0..3 {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(reason.trap() == Some(TrapType::HardwareBreak(Some(watch))));
+        assert!(!tids.contains(&proc.tid()) && proc.tid() != proc.pid);
+        tids.push(proc.tid());
+    }
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 6");
+}
+
+#[test]
+fn attach_multithreaded() {
+    let test_binary = get_test_binary("threadwait");
+    let target = Process::launch_noattach(test_binary.to_str().unwrap()).unwrap();
+    let tasks = |pid: Pid| -> Vec<Pid> {
+        let mut out: Vec<Pid> = fs::read_dir(format!("/proc/{}/task", pid)).unwrap()
+            .map(|e| Pid::from_raw(e.unwrap().file_name().to_str().unwrap().parse().unwrap()))
+            .collect();
+        out.sort();
+        out
+    };
+    for _ in 0..100 {
+        if tasks(target.pid).len() == 4 {
+            break;
+        }
+        thread::sleep(time::Duration::from_millis(10));
+    }
+    let tids = tasks(target.pid);
+    assert!(tids.len() == 4);
+
+    {
+        let proc = Process::attach(target.pid.as_raw()).unwrap();
+        let mut attached: Vec<Pid> = proc.threads().iter().map(|t| t.tid).collect();
+        attached.sort();
+        assert!(attached == tids);
+        assert!(proc.threads().iter().all(|t| t.state == ProcessState::Stopped));
+        assert!(tids.iter().all(|tid| get_process_status(*tid) == Ok('t')));
+    }
+    // every thread was detached, so the process can be attached to again
+    let running = |tid: &Pid| matches!(get_process_status(*tid), Ok('R') | Ok('S'));
+    assert!(tids.iter().all(running));
+    {
+        let mut proc = Process::attach(target.pid.as_raw()).unwrap();
+        assert!(proc.threads().len() == 4);
+        proc.resume().unwrap();
+    }
+    assert!(tids.iter().all(running));
+}
+
+#[test]
+fn follow_fork() {
+    let test_binary = get_test_binary("fork");
+    let run = |proc: &mut Process| proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+
+    // the child runs free without the breakpoints it inherited; a stray int3 would kill it
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    proc.create_breakpoint(parse_location("child_work").unwrap()).unwrap();
+    let mut reason = run(&mut proc);
+    while proc.state == ProcessState::Stopped {
+        assert!(!matches!(reason.trap(), Some(TrapType::SoftwareBreak(_))));
+        reason = run(&mut proc);
+    }
+    assert!(reason.to_string() == "exited with exit code 13");
+
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let parent = proc.pid;
+    proc.follow_fork = ForkMode::Child;
+    proc.create_breakpoint(parse_location("child_work").unwrap()).unwrap();
+    let reason = run(&mut proc);
+    assert!(matches!(reason.trap(), Some(TrapType::SoftwareBreak(Some(_)))));
+    assert!(proc.pid != parent && proc.tid() == proc.pid);
+    assert!(run(&mut proc).to_string() == "exited with exit code 3");
+
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    proc.follow_fork = ForkMode::Both;
+    proc.create_breakpoint(parse_location("child_work").unwrap()).unwrap();
+    let reason = run(&mut proc);
+    let Some(TrapType::Fork(pid)) = reason.trap() else {
+        panic!("expected a fork, got {}", reason);
+    };
+    let mut children = proc.take_forked();
+    assert!(children.len() == 1 && children[0].pid == pid);
+    let child = &mut children[0];
+    assert!(child.state == ProcessState::Stopped && child.get_pc() == proc.get_pc());
+    assert!(matches!(run(child).trap(), Some(TrapType::SoftwareBreak(Some(_)))));
+    assert!(run(child).to_string() == "exited with exit code 3");
+    let mut reason = run(&mut proc);
+    while proc.state == ProcessState::Stopped {
+        reason = run(&mut proc);
+    }
+    assert!(reason.to_string() == "exited with exit code 13");
+}
+
+#[test]
+fn exec_reloads_image() {
+    let test_binary = get_test_binary("exec");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let main = proc.create_breakpoint(parse_location("main").unwrap()).unwrap();
+
+    let mut exit = VirtAddr(0);
+    for argc in [1, 2] {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        let Some(TrapType::SoftwareBreak(Some(site))) = reason.trap() else {
+            panic!("expected a breakpoint, got {}", reason);
+        };
+        assert!(proc.breakpoint_for_site(site).unwrap().id == main);
+        assert!(proc.regs().read_as_id::<u64>(RegisterId::rdi) == argc);
+        if argc == 1 {
+            exit = *proc.find_symbol("exit").last().unwrap();
+            proc.create_breakpoint(Location::Address(exit.0)).unwrap();
+        }
+    }
+    assert!(proc.breakpoint_by_id(main).unwrap().sites.len() == 1);
+    assert!(proc.threads().len() == 1);
+
+    // an address in libc is armed again once the new image's loader has mapped libc
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(matches!(reason.trap(), Some(TrapType::SoftwareBreak(Some(_)))) && proc.get_pc() == exit);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 7");
+}
+
+#[test]
+fn signal_policies() {
+    let test_binary = get_test_binary("signals");
+    let run = |proc: &mut Process| proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    let usr1 = signal::Signal::SIGUSR1;
+    assert!(parse_signal("usr1") == Ok(usr1) && parse_signal("SIGUSR1") == Ok(usr1) && parse_signal("10") == Ok(usr1));
+    assert!(parse_signal("SIGNOPE").is_err());
+
+    // by default the signal stops, and continuing delivers it to the handler
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    assert!(proc.signal_policy(signal::Signal::SIGCHLD) == SignalPolicy { stop: false, print: false, pass: true });
+    for _ in 0..3 {
+        let reason = run(&mut proc);
+        assert!(reason.delivered_signal() == Some(usr1));
+    }
+    assert!(run(&mut proc).to_string() == "exited with exit code 3");
+
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let mut policy = proc.signal_policy(usr1);
+    policy.apply("noprint").unwrap();
+    assert!(!policy.stop);
+    policy.apply("nopass").unwrap();
+    assert!(policy.apply("sometimes").is_err());
+    policy.apply("print").unwrap();
+    proc.set_signal_policy(usr1, policy);
+    assert!(run(&mut proc).to_string() == "exited with exit code 0");
+    assert!(proc.take_noted_signals() == vec![(1, usr1); 3]);
+
+    // a signal injected by hand replaces the one the thread stopped with
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    run(&mut proc);
+    proc.resume_with_signal(None).unwrap();
+    proc.wait_on_signal().unwrap();
+    proc.resume_with_signal(Some(signal::Signal::SIGTERM)).unwrap();
+    assert!(proc.wait_on_signal().unwrap().to_string() == "terminated with signal SIGTERM");
+}
+
+#[test]
+fn step_with_quiet_signal_in_other_thread() {
+    let test_binary = get_test_binary("threads");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+    let usr1 = signal::Signal::SIGUSR1;
+    let mut policy = proc.signal_policy(usr1);
+    policy.apply("nostop").unwrap();
+    policy.apply("nopass").unwrap();
+    proc.set_signal_policy(usr1, policy);
+    let clones = ["clone", "clone3"].iter().filter_map(|name| syscall_id(name)).collect();
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(clones));
+
+    for _ in 0..3 {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(reason.syscall().is_some_and(|info| info.is_entry()));
+        assert!(proc.current_thread() == 1);
+        // whichever thread takes the signal, the step finishes the call in the one that made it
+        signal::kill(proc.pid, usr1).unwrap();
+        proc.step_instruction().unwrap();
+        assert!(proc.current_thread() == 1);
+        let child = proc.regs().read_as_id::<u64>(RegisterId::rax);
+        assert!(proc.threads().iter().any(|t| t.id != 1 && t.tid.as_raw() as u64 == child));
+    }
+
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::None);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 6");
+}
+
+#[test]
+fn syscall_catchpoints() {
+    assert!(syscall_name(0) == Some("read") && syscall_id("openat") == Some(257));
+    assert!(parse_syscall("write") == Ok(1) && parse_syscall("1") == Ok(1));
+    assert!(parse_syscall("not_a_syscall").is_err());
+
+    let mut proc = launch_quiet("hello_world");
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(vec![syscall_id("write").unwrap()]));
+
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.trap() == Some(TrapType::Syscall));
+    let info = reason.syscall().unwrap();
+    assert!(info.to_string() == "syscall entry: write");
+    let SyscallData::Args(args) = info.data else {
+        panic!("expected an entry stop");
+    };
+    assert!(args[0] == 1);
+    let len = args[2];
+
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    let info = reason.syscall().unwrap();
+    assert!(!info.is_entry() && info.data == SyscallData::Ret(len as i64));
+
+    // entry and exit stops alternate even when every syscall is caught
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::All);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    let entry = reason.syscall().unwrap();
+    assert!(entry.is_entry());
+    if entry.name() != Some("exit_group") {
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(reason.syscall().is_some_and(|info| info.id == entry.id && !info.is_entry()));
+    }
+
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::None);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+
+    // thousands of syscalls that are not caught go by without stopping
+    let mut proc = launch_quiet("syscalls");
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(vec![syscall_id("write").unwrap()]));
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+}
+
+#[test]
+fn syscall_tracing() {
+    let mut proc = launch_quiet("hello_world");
+    proc.trace_syscalls(None);
+
+    // tracing alone never stops the inferior
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+
+    let records = proc.take_syscall_records();
+    let lines: Vec<String> = records.iter().map(|r| r.to_string()).collect();
+    assert!(lines.iter().any(|l| l == "write(1, \"Hello, world!\", 13) = 13"));
+    assert!(lines.iter().any(|l| l.starts_with("openat(AT_FDCWD, \"") && l.contains("libc.so.6\", O_RDONLY|O_CLOEXEC")));
+    assert!(lines.iter().any(|l| l.starts_with("mmap(NULL, ") && l.contains("PROT_READ") && l.contains("MAP_PRIVATE")));
+    assert!(lines.last().unwrap() == "exit_group(0) = ?");
+    let write = records.iter().find(|r| r.name() == Some("write")).unwrap();
+    assert!(write.args[0] == 1 && write.ret == Some(13) && write.errno().is_none());
+
+    // a trace written to a file, while stopping at the syscalls that are caught
+    let path = env::temp_dir().join(format!("gadb_trace_{}", std::process::id()));
+    let mut proc = launch_quiet("hello_world");
+    proc.trace_syscalls(Some(Box::new(File::create(&path).unwrap())));
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(vec![syscall_id("write").unwrap()]));
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.syscall().is_some_and(|info| info.is_entry() && info.name() == Some("write")));
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::None);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+    assert!(proc.take_syscall_records().is_empty());
+
+    let trace = read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(trace.lines().any(|l| l == "write(1, \"Hello, world!\", 13) = 13"));
+    assert!(trace.ends_with("exit_group(0) = ?\n"));
+
+    // a long run of syscalls is traced without stopping
+    let mut proc = launch_quiet("syscalls");
+    proc.trace_syscalls(None);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+    let records = proc.take_syscall_records();
+    assert!(records.iter().filter(|r| r.to_string() == format!("getppid() = {}", std::process::id())).count() >= 5000);
+}
+
+#[test]
+fn syscall_fault_injection() {
+    let every_third: Occurrence = "3+3".parse().unwrap();
+    assert!(!every_third.includes(2) && every_third.includes(3) && !every_third.includes(4) && every_third.includes(6));
+    assert!("2".parse::<Occurrence>().is_ok_and(|o| o.includes(2) && !o.includes(4)));
+    assert!("0".parse::<Occurrence>().is_err());
+    assert!(parse_errno("EINTR") == Ok(nix::errno::Errno::EINTR) && parse_errno("4") == Ok(nix::errno::Errno::EINTR));
+
+    let rule = parse_syscall_fault(&["write", "when=3+3", "error=EINTR"]).unwrap();
+    assert!(rule.syscall == syscall_id("write").unwrap() && rule.action == FaultAction::Error(nix::errno::Errno::EINTR));
+    assert!(rule.to_string() == "write when=3+3 error=EINTR");
+    assert!(parse_syscall_fault(&["write", "when=2"]).is_err());
+    assert!(parse_syscall_fault(&["write", "arg6==1", "retval=0"]).is_err());
+
+    // the target counts writes that fail with EINTR, tens of writes that write nothing, and
+    // hundreds if getppid returns 4242
+    let mut proc = launch_quiet("faults");
+    let id = proc.add_syscall_fault(rule);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 2");
+    assert!(proc.syscall_faults()[&id].matched == 6);
+
+    let mut proc = launch_quiet("faults");
+    proc.add_syscall_fault(SyscallFault::new(syscall_id("getppid").unwrap(), FaultAction::Return(4242)));
+    proc.add_syscall_fault(parse_syscall_fault(&["write", "arg0==1", "when=2", "arg2=0"]).unwrap());
+    let never = proc.add_syscall_fault(parse_syscall_fault(&["write", "arg0==2", "error=EBADF"]).unwrap());
+    // injected results show at exit stops like real ones
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(vec![syscall_id("getppid").unwrap()]));
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.syscall().is_some_and(|info| info.is_entry()));
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.syscall().is_some_and(|info| info.data == SyscallData::Ret(4242)));
+    proc.set_syscall_catch_policy(SyscallCatchPolicy::None);
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 110");
+    assert!(proc.syscall_faults()[&never].matched == 0);
+    assert!(proc.remove_syscall_fault(never).is_ok() && proc.remove_syscall_fault(never).is_err());
+
+    assert!(parse_syscall_fault(&["write", "retval=-9223372036854775808"]).is_ok_and(|f| f.action == FaultAction::Return(i64::MIN)));
+    assert!(parse_syscall_fault(&["write", "retval=9223372036854775808"]).is_err());
+    assert!(parse_errno("4294967300").is_err());
+
+    // a rule on a syscall that never happens does not get in the way of thousands that do
+    let mut proc = launch_quiet("syscalls");
+    let id = proc.add_syscall_fault(parse_syscall_fault(&["kill", "error=EPERM"]).unwrap());
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "exited with exit code 0");
+    assert!(proc.syscall_faults()[&id].matched == 0);
+
+    // the chosen error survives stepping off the entry stop, or the rule being cleared
+    for step in [true, false] {
+        let mut proc = launch_quiet("faults");
+        let id = proc.add_syscall_fault(parse_syscall_fault(&["write", "when=1", "error=EINTR"]).unwrap());
+        proc.set_syscall_catch_policy(SyscallCatchPolicy::Some(vec![syscall_id("write").unwrap()]));
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(reason.syscall().is_some_and(|info| info.is_entry()));
+        if step {
+            proc.step_instruction().unwrap();
+            assert!(proc.regs().read_as_id::<u64>(RegisterId::rax) as i64 == -4);
+        }
+        proc.remove_syscall_fault(id).unwrap();
+        proc.set_syscall_catch_policy(SyscallCatchPolicy::None);
+        let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+        assert!(reason.to_string() == "exited with exit code 1");
+    }
+}
+
+#[test]
+fn signal_info() {
+    let test_binary = get_test_binary("segv");
+    let mut proc = Process::launch(test_binary.to_str().unwrap()).unwrap();
+
+    // the target sends itself SIGUSR1, then reads through a null pointer
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    let info = reason.siginfo().unwrap();
+    assert!(info.signal == signal::Signal::SIGUSR1 && info.code_name() == Some("SI_USER") && info.addr.is_none());
+    let uid = unsafe { libc::getuid() };
+    assert!(info.sender == Some((proc.pid, uid)));
+    assert!(info.to_string() == format!("SIGUSR1: SI_USER from pid {} uid {}", proc.pid, uid));
+
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    let info = reason.siginfo().unwrap();
+    assert!(info.signal == signal::Signal::SIGSEGV && info.sender.is_none());
+    assert!(info.to_string() == "SIGSEGV: SEGV_MAPERR at address 0x0");
+    let map = proc.memory_map().unwrap();
+    assert!(map.describe_address(info.addr.unwrap()) == "address 0x0 is not mapped");
+    let pc_region = map.region_containing(proc.get_pc()).unwrap();
+    assert!(map.describe_address(proc.get_pc()).starts_with(&format!("address {:#x} is in {}", proc.get_pc(), pc_region.path.as_ref().unwrap())));
+
+    let reason = proc.resume().and_then(|_| proc.wait_on_signal()).unwrap();
+    assert!(reason.to_string() == "terminated with signal SIGSEGV" && reason.siginfo().is_none());
+
+    let map = MemoryMap::parse("7ffffffde000-7ffffffff000 rw-p 00000000 00:00 0                          [stack]").unwrap();
+    assert!(map.describe_address(VirtAddr(0x7ffffffdd000)) == "address 0x7ffffffdd000 is not mapped, 0x1000 bytes below [stack]");
+    assert!(map.describe_address(VirtAddr(0x7ffffffde010)) == "address 0x7ffffffde010 is in [stack] (0x7ffffffde000-0x7ffffffff000 rw-p)");
+    assert!(map.describe_address(VirtAddr(0x800000000000)) == "address 0x800000000000 is not mapped, 0x1000 bytes past the end of [stack]");
+    assert!(map.describe_address(VirtAddr(0x7ffffffdcfff)) == "address 0x7ffffffdcfff is not mapped");
+}